@@ -0,0 +1,90 @@
+//! Timings for building and compositing large boxes: a big matrix through the
+//! whole renderer, and raw grid operations against a `Vec<Vec<String>>` grid
+//! like the one MathBox used to store.
+//!
+//! Run with `cargo bench --bench mathbox`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use tui_math::{MathBox, MathRenderer};
+
+/// Run `f` repeatedly for about a second and print the mean time per run
+fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+    let mut runs = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        black_box(f());
+        runs += 1;
+    }
+    println!("{name:<32} {:>12.1?}", start.elapsed() / runs);
+}
+
+/// The nested grid MathBox used to store, for comparison
+struct NestedGrid {
+    content: Vec<Vec<String>>,
+}
+
+impl NestedGrid {
+    fn empty(width: usize, height: usize) -> Self {
+        Self {
+            content: vec![vec![" ".to_string(); width]; height],
+        }
+    }
+
+    fn blit(&mut self, other: &NestedGrid, x_offset: usize, y_offset: usize) {
+        for (y, row) in other.content.iter().enumerate() {
+            for (x, g) in row.iter().enumerate().filter(|(_, g)| *g != " ") {
+                self.content[y_offset + y][x_offset + x] = g.clone();
+            }
+        }
+    }
+}
+
+fn matrix(size: usize) -> String {
+    let rows: Vec<String> = (0..size)
+        .map(|i| {
+            (0..size)
+                .map(|j| format!("x_{{{i}{j}}}"))
+                .collect::<Vec<_>>()
+                .join(" & ")
+        })
+        .collect();
+    format!(r"\begin{{pmatrix}} {} \end{{pmatrix}}", rows.join(r" \\ "))
+}
+
+fn main() {
+    let latex = matrix(30);
+    let renderer = MathRenderer::new();
+    bench("render 30x30 matrix", || {
+        renderer.render_to_box(&latex).unwrap()
+    });
+
+    let tile = MathBox::from_text("α₁₂ + β");
+    bench("MathBox: tile 200x100 grid", || {
+        let mut grid = MathBox::empty(200, 100, 50);
+        for y in 0..100 {
+            for x in (0..200).step_by(tile.width) {
+                grid.blit(&tile, x, y);
+            }
+        }
+        grid
+    });
+
+    let nested_tile = NestedGrid {
+        content: vec![tile.to_lines()[0].chars().map(String::from).collect()],
+    };
+    bench("Vec<Vec<String>>: tile 200x100", || {
+        let mut grid = NestedGrid::empty(200 + tile.width, 100);
+        for y in 0..100 {
+            for x in (0..200).step_by(tile.width) {
+                grid.blit(&nested_tile, x, y);
+            }
+        }
+        grid
+    });
+
+    let big = renderer.render_to_box(&latex).unwrap();
+    bench("MathBox: clone and print matrix", || {
+        big.clone().to_string()
+    });
+}
@@ -0,0 +1,140 @@
+//! Process-wide LRU cache of rendered expressions
+//!
+//! Immediate-mode UIs rebuild their widgets every frame, so the same LaTeX is
+//! converted and laid out over and over. With the cache enabled (see
+//! [`set_render_cache_capacity`]), [`MathRenderer::render_to_box`] and
+//! everything built on it look the result up by input and renderer options
+//! first. The cache is off until a capacity is set.
+//!
+//! [`MathRenderer::render_to_box`]: crate::MathRenderer::render_to_box
+
+use crate::mathbox::MathBox;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A cache key: the input and a fingerprint of the renderer options
+pub(crate) type Key = (String, String);
+
+static CACHE: Lazy<Mutex<Lru>> = Lazy::new(|| Mutex::new(Lru::new(0)));
+
+/// Least-recently-used map from keys to rendered boxes
+struct Lru {
+    capacity: usize,
+    /// Each entry with the tick it was last used at
+    entries: HashMap<Key, (MathBox, u64)>,
+    tick: u64,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, key: &Key) -> Option<MathBox> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries.get_mut(key).map(|(mbox, used)| {
+            *used = tick;
+            mbox.clone()
+        })
+    }
+
+    fn insert(&mut self, key: Key, mbox: MathBox) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        self.entries.insert(key, (mbox, self.tick));
+        self.shrink();
+    }
+
+    /// Evict least recently used entries until within capacity
+    fn shrink(&mut self) {
+        while self.entries.len() > self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+    }
+}
+
+/// Enable the render cache, holding up to `capacity` expressions; 0 (the
+/// default) disables it and drops everything cached
+pub fn set_render_cache_capacity(capacity: usize) {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache.capacity = capacity;
+    cache.shrink();
+}
+
+/// Drop every cached expression, keeping the cache's capacity
+pub fn clear_render_cache() {
+    CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entries
+        .clear();
+}
+
+/// Whether the cache is enabled, to skip building keys when it isn't
+pub(crate) fn enabled() -> bool {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).capacity > 0
+}
+
+pub(crate) fn get(key: &Key) -> Option<MathBox> {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).get(key)
+}
+
+pub(crate) fn insert(key: Key, mbox: MathBox) {
+    CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, mbox);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(latex: &str) -> Key {
+        (latex.to_string(), String::new())
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut lru = Lru::new(2);
+        lru.insert(key("a"), MathBox::from_text("a"));
+        lru.insert(key("b"), MathBox::from_text("b"));
+        assert!(lru.get(&key("a")).is_some());
+        lru.insert(key("c"), MathBox::from_text("c"));
+        assert!(lru.get(&key("b")).is_none());
+        assert_eq!(lru.get(&key("a")).unwrap().to_string(), "a");
+        assert!(lru.get(&key("c")).is_some());
+    }
+
+    #[test]
+    fn test_render_cache_keys_options() {
+        use crate::MathRenderer;
+        let key = |renderer: &MathRenderer| ("x^2".to_string(), renderer.fingerprint());
+        let unicode = MathRenderer::new();
+        let ascii = MathRenderer::new().use_unicode_scripts(false);
+        assert_ne!(key(&unicode), key(&ascii));
+        let mut lru = Lru::new(16);
+        lru.insert(key(&unicode), unicode.render_to_box("x^2").unwrap());
+        assert!(lru.get(&key(&ascii)).is_none());
+        assert_eq!(
+            lru.get(&key(&MathRenderer::new())).unwrap().to_string(),
+            "x²"
+        );
+    }
+}
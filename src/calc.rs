@@ -0,0 +1,304 @@
+//! Calculator-style expressions: `x^2/(y+1) + sqrt(2)` without any LaTeX
+//!
+//! Input is parsed with operator precedence and translated to LaTeX, so
+//! divisions become stacked fractions, `sqrt(...)` a radical and `abs(...)`
+//! bars. Juxtaposition multiplies (`2x`, `3(a+b)`), and names of Greek letters
+//! and common functions are recognized.
+
+use crate::renderer::RenderError;
+
+/// Functions written as operator names (`sin(x)` → `\sin(x)`)
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "exp", "det", "min", "max", "gcd",
+];
+
+/// Names turned into their symbol (`alpha` → α)
+const SYMBOLS: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa",
+    "lambda", "mu", "nu", "xi", "pi", "rho", "sigma", "tau", "upsilon", "phi", "chi", "psi",
+    "omega", "Gamma", "Delta", "Theta", "Lambda", "Xi", "Pi", "Sigma", "Phi", "Psi", "Omega",
+    "infinity", "inf",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Name(String),
+    /// Operators and punctuation, including two-character relations (`<=`)
+    Op(&'static str),
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(String),
+    Name(String),
+    Call(String, Vec<Expr>),
+    Paren(Box<Expr>),
+    Neg(Box<Expr>),
+    Factorial(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// Translate a calculator-style expression to LaTeX
+pub(crate) fn calc_to_latex(input: &str) -> Result<String, RenderError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.expression(0)?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(parse_error(format!("unexpected {:?}", token)));
+    }
+    Ok(to_latex(&expr))
+}
+
+fn parse_error(message: String) -> RenderError {
+    RenderError::ExpressionParse(message)
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, RenderError> {
+    const OPERATORS: &[&str] = &[
+        "<=", ">=", "!=", "==", "**", "+", "-", "*", "/", "^", "(", ")", ",", "!", "=", "<", ">",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_digit() || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(parse_error(format!("unexpected character '{}'", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), RenderError> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(found)) if *found == op => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(parse_error(format!("expected '{}'", op))),
+        }
+    }
+
+    /// Parse operators binding tighter than `min_precedence`
+    fn expression(&mut self, min_precedence: u8) -> Result<Expr, RenderError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op)) => match *op {
+                    "**" => "^",
+                    "==" => "=",
+                    "(" => "",
+                    op => op,
+                },
+                // Juxtaposition multiplies: 2x, 3(a + b), x y
+                Some(Token::Number(_) | Token::Name(_)) => "",
+                _ => break,
+            };
+            let Some((precedence, right_assoc)) = binary_precedence(op) else {
+                break;
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            if !op.is_empty() {
+                self.pos += 1;
+            }
+            let rhs = self.expression(if right_assoc {
+                precedence
+            } else {
+                precedence + 1
+            })?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, RenderError> {
+        if self.peek() == Some(&Token::Op("-")) {
+            self.pos += 1;
+            // Unary minus binds looser than powers: -x^2 is -(x^2)
+            return Ok(Expr::Neg(Box::new(self.expression(4)?)));
+        }
+        if self.peek() == Some(&Token::Op("+")) {
+            self.pos += 1;
+        }
+        let mut expr = self.primary()?;
+        while self.peek() == Some(&Token::Op("!")) {
+            self.pos += 1;
+            expr = Expr::Factorial(Box::new(expr));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, RenderError> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Name(name)) => {
+                if self.peek() != Some(&Token::Op("(")) || !is_function(&name) {
+                    return Ok(Expr::Name(name));
+                }
+                self.pos += 1;
+                let mut args = vec![self.expression(0)?];
+                while self.peek() == Some(&Token::Op(",")) {
+                    self.pos += 1;
+                    args.push(self.expression(0)?);
+                }
+                self.expect(")")?;
+                Ok(Expr::Call(name, args))
+            }
+            Some(Token::Op("(")) => {
+                let inner = self.expression(0)?;
+                self.expect(")")?;
+                Ok(Expr::Paren(Box::new(inner)))
+            }
+            Some(token) => Err(parse_error(format!("unexpected {:?}", token))),
+            None => Err(parse_error("unexpected end of expression".to_string())),
+        }
+    }
+}
+
+/// Precedence and right-associativity of a binary operator ("" is juxtaposition)
+fn binary_precedence(op: &str) -> Option<(u8, bool)> {
+    match op {
+        "=" | "!=" | "<" | ">" | "<=" | ">=" => Some((1, false)),
+        "+" | "-" => Some((2, false)),
+        "*" | "/" | "" => Some((3, false)),
+        "^" => Some((5, true)),
+        _ => None,
+    }
+}
+
+fn is_function(name: &str) -> bool {
+    matches!(name, "sqrt" | "cbrt" | "root" | "abs") || FUNCTIONS.contains(&name)
+}
+
+/// Strip redundant parentheses, for operands that are set apart anyway (fraction
+/// parts, exponents, radicands)
+fn unwrap_paren(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Paren(inner) => unwrap_paren(inner),
+        _ => expr,
+    }
+}
+
+fn to_latex(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(n) => n.clone(),
+        Expr::Name(name) => name_to_latex(name),
+        Expr::Paren(inner) => format!(r"\left( {} \right)", to_latex(inner)),
+        Expr::Neg(inner) => format!("-{}", to_latex(inner)),
+        Expr::Factorial(inner) => format!("{}!", to_latex(inner)),
+        Expr::Call(name, args) => {
+            let arg = |i: usize| {
+                args.get(i)
+                    .map(|a| to_latex(unwrap_paren(a)))
+                    .unwrap_or_default()
+            };
+            match name.as_str() {
+                "sqrt" => format!(r"\sqrt{{{}}}", arg(0)),
+                "cbrt" => format!(r"\sqrt[3]{{{}}}", arg(0)),
+                "root" => format!(r"\sqrt[{}]{{{}}}", arg(1), arg(0)),
+                "abs" => format!(r"\left| {} \right|", arg(0)),
+                _ => {
+                    let args: Vec<_> = args.iter().map(to_latex).collect();
+                    format!(r"\{}\left( {} \right)", name, args.join(", "))
+                }
+            }
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let (l, r) = (to_latex(lhs), to_latex(rhs));
+            match *op {
+                "/" => format!(
+                    r"\frac{{{}}}{{{}}}",
+                    to_latex(unwrap_paren(lhs)),
+                    to_latex(unwrap_paren(rhs))
+                ),
+                "^" => format!("{{{}}}^{{{}}}", l, to_latex(unwrap_paren(rhs))),
+                // Juxtaposition and a number times a non-number are written side by
+                // side (2x); a dot keeps numbers apart and marks other explicit `*`
+                "*" | "" => {
+                    let numeric_rhs = matches!(**rhs, Expr::Number(_) | Expr::Neg(_));
+                    if numeric_rhs || (*op == "*" && !matches!(**lhs, Expr::Number(_))) {
+                        format!(r"{} \cdot {}", l, r)
+                    } else {
+                        format!("{} {}", l, r)
+                    }
+                }
+                "<=" => format!(r"{} \leq {}", l, r),
+                ">=" => format!(r"{} \geq {}", l, r),
+                "!=" => format!(r"{} \neq {}", l, r),
+                op => format!("{} {} {}", l, op, r),
+            }
+        }
+    }
+}
+
+fn name_to_latex(name: &str) -> String {
+    match name {
+        "inf" | "infinity" => r"\infty".to_string(),
+        _ if SYMBOLS.contains(&name) => format!(r"\{}", name),
+        // Multi-letter names are words, not products of variables
+        _ if name.chars().count() > 1 => format!(r"\mathrm{{{}}}", name.replace('_', r"\_")),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calc_to_latex() {
+        assert_eq!(
+            calc_to_latex("x^2/(y+1) + sqrt(2)").unwrap(),
+            r"\frac{{x}^{2}}{y + 1} + \sqrt{2}"
+        );
+        assert_eq!(
+            calc_to_latex("2x - 3*4 + 2(a*b)").unwrap(),
+            r"2 x - 3 \cdot 4 + 2 \left( a \cdot b \right)"
+        );
+        assert_eq!(
+            calc_to_latex("sin(pi/2)").unwrap(),
+            r"\sin\left( \frac{\pi}{2} \right)"
+        );
+        assert_eq!(
+            calc_to_latex("-x**2 <= abs(a)").unwrap(),
+            r"-{x}^{2} \leq \left| a \right|"
+        );
+    }
+
+    #[test]
+    fn test_calc_errors() {
+        assert!(calc_to_latex("(1 + 2").is_err());
+        assert!(calc_to_latex("2 $ 3").is_err());
+    }
+}
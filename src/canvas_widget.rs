@@ -1,37 +1,69 @@
 //! Canvas-based math widget using Braille markers for sub-cell resolution
 //!
-//! Uses Braille characters for smooth lines (fraction bars, sqrt) while
-//! rendering text normally for better readability.
+//! Uses Braille characters for smooth lines (fraction bars, sqrt, tall
+//! delimiters, extensible arrows) while rendering text normally for better
+//! readability. In full Braille mode every drawn shape (bars, radicals,
+//! brackets, braces, arrows, rules) is stroked in Braille dots and only
+//! letters, digits and symbols stay text. With the half-block marker, bars and
+//! radical overlines fill the upper or lower half of their cells instead,
+//! drawn over the widget's background color.
 
-use crate::{MathBox, MathRenderer};
+use crate::widget::{pad, Source};
+use crate::Background;
+use crate::{AccentStrategy, MathBox, MathRenderer, RendererOptions};
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
     style::{Color, Style},
     symbols::Marker,
+    text::Span,
     widgets::{
-        canvas::{Canvas, Line},
-        Block, Widget,
+        canvas::{Canvas, Context, Line},
+        Block, Padding, Widget,
     },
 };
 
 /// A high-resolution math widget using Canvas with Braille markers for lines
 #[derive(Clone)]
 pub struct CanvasMathWidget<'a> {
-    latex: &'a str,
+    source: Source<'a>,
     style: Style,
     block: Option<Block<'a>>,
+    padding: Padding,
+    background: Background,
     color: Color,
+    full_braille: bool,
+    marker: Marker,
+    scale: f64,
+    options: RendererOptions,
 }
 
 impl<'a> CanvasMathWidget<'a> {
     /// Create a new CanvasMathWidget from a LaTeX expression
     pub fn new(latex: &'a str) -> Self {
+        Self::with_source(Source::Latex(latex))
+    }
+
+    /// Create a CanvasMathWidget from a box already rendered with
+    /// [`MathRenderer::render_to_box`] (e.g. on a worker thread), skipping the
+    /// parse at draw time; only the Braille and text composite is redrawn each
+    /// frame. Rendering options were fixed when the box was made.
+    pub fn from_box(mathbox: MathBox) -> Self {
+        Self::with_source(Source::Box(mathbox))
+    }
+
+    fn with_source(source: Source<'a>) -> Self {
         Self {
-            latex,
+            source,
             style: Style::default(),
             block: None,
+            padding: Padding::ZERO,
+            background: Background::Rows,
             color: Color::White,
+            full_braille: false,
+            marker: Marker::Braille,
+            scale: 1.0,
+            options: RendererOptions::default(),
         }
     }
 
@@ -47,11 +79,64 @@ impl<'a> CanvasMathWidget<'a> {
         self
     }
 
+    /// Draw every shape (radicals, brackets, braces, arrows, rules) in Braille
+    /// sub-cell strokes instead of box-drawing characters, for a smoother look
+    /// on title screens and splash pages
+    pub fn full_braille(mut self, full_braille: bool) -> Self {
+        self.full_braille = full_braille;
+        self
+    }
+
+    /// Set the canvas marker used for lines (Braille by default).
+    /// `Marker::HalfBlock` draws fraction bars and radical overlines as half
+    /// blocks, which looks better than Braille on many fonts. Full Braille mode
+    /// only applies with the Braille marker.
+    pub fn marker(mut self, marker: Marker) -> Self {
+        self.marker = marker;
+        self
+    }
+
     /// Wrap in a block
     pub fn block(mut self, block: Block<'a>) -> Self {
         self.block = Some(block);
         self
     }
+
+    /// Draw the equation `scale` times larger, for title screens: text is
+    /// spread over a sparser grid and strokes are stretched to match.
+    /// Factors below 1 are treated as 1.
+    pub fn scale(mut self, scale: f64) -> Self {
+        self.scale = scale.max(1.0);
+        self
+    }
+
+    /// Leave space between the equation and the edges of the area (inside
+    /// the block's borders, if any)
+    pub fn padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Set what happens to the cells the equation leaves blank (see
+    /// [`Background`]); the canvas otherwise clears the equation's area
+    pub fn background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Set the layout options the LaTeX is rendered with (see
+    /// [`RendererOptions`]); boxes from [`from_box`](Self::from_box) keep
+    /// their own
+    pub fn options(mut self, options: RendererOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set how accents are drawn (see [`AccentStrategy`])
+    pub fn accents(mut self, strategy: AccentStrategy) -> Self {
+        self.options.accents = strategy;
+        self
+    }
 }
 
 /// Line segment to draw with Braille
@@ -62,52 +147,390 @@ struct BrailleLine {
     y2: f64,
 }
 
-/// Extract line segments and text positions from MathBox
-/// area_height is used to flip y coordinates for Canvas (which has y=0 at bottom)
-fn extract_elements(mbox: &MathBox, area_height: f64) -> (Vec<BrailleLine>, Vec<(usize, usize, char)>) {
+/// Strokes of a glyph drawn in full Braille mode, as (x1, y1, x2, y2) in cell
+/// units: x from 0 (left) to 1 (right), y from 0 (bottom) to 1 (top)
+pub(crate) fn glyph_strokes(ch: char) -> Option<Vec<(f64, f64, f64, f64)>> {
+    // Box-drawing characters are arms reaching from the cell centre to its edges
+    let arms = match ch {
+        '─' => Some((false, false, true, true)),
+        '│' | '⎜' | '⎢' | '⎪' | '⎮' | '∣' => Some((true, true, false, false)),
+        '┌' => Some((false, true, false, true)),
+        '┐' => Some((false, true, true, false)),
+        '└' => Some((true, false, false, true)),
+        '┘' => Some((true, false, true, false)),
+        '├' => Some((true, true, false, true)),
+        '┤' => Some((true, true, true, false)),
+        '┬' => Some((false, true, true, true)),
+        '┴' => Some((true, false, true, true)),
+        '┼' => Some((true, true, true, true)),
+        _ => None,
+    };
+    if let Some((up, down, left, right)) = arms {
+        let strokes = [
+            (up, (0.5, 0.5, 0.5, 1.0)),
+            (down, (0.5, 0.5, 0.5, 0.0)),
+            (left, (0.5, 0.5, 0.0, 0.5)),
+            (right, (0.5, 0.5, 1.0, 0.5)),
+        ];
+        return Some(
+            strokes
+                .iter()
+                .filter(|(arm, _)| *arm)
+                .map(|(_, stroke)| *stroke)
+                .collect(),
+        );
+    }
+
+    let strokes: &[(f64, f64, f64, f64)] = match ch {
+        '═' => &[(0.0, 0.3, 1.0, 0.3), (0.0, 0.7, 1.0, 0.7)],
+        '║' => &[(0.0, 0.0, 0.0, 1.0), (1.0, 0.0, 1.0, 1.0)],
+        '_' => &[(0.0, 0.0, 1.0, 0.0)],
+        '‾' | '¯' => &[(0.0, 1.0, 1.0, 1.0)],
+        '╱' => &[(0.0, 0.0, 1.0, 1.0)],
+        '╲' => &[(0.0, 1.0, 1.0, 0.0)],
+        // Radical: a short tick, then the long stroke up to the overbar
+        '√' => &[(0.0, 0.4, 0.3, 0.0), (0.3, 0.0, 1.0, 1.0)],
+        // A cell is only two dots wide, so curves run between its left and right dot
+        '⎛' => &[(1.0, 1.0, 0.0, 0.6), (0.0, 0.6, 0.0, 0.0)],
+        '⎝' => &[(0.0, 1.0, 0.0, 0.4), (0.0, 0.4, 1.0, 0.0)],
+        '⎞' => &[(0.0, 1.0, 1.0, 0.6), (1.0, 0.6, 1.0, 0.0)],
+        '⎠' => &[(1.0, 1.0, 1.0, 0.4), (1.0, 0.4, 0.0, 0.0)],
+        '⎟' | '⎥' => &[(1.0, 0.0, 1.0, 1.0)],
+        '⎡' => &[(1.0, 1.0, 0.0, 1.0), (0.0, 1.0, 0.0, 0.0)],
+        '⎣' => &[(0.0, 1.0, 0.0, 0.0), (0.0, 0.0, 1.0, 0.0)],
+        '⎤' => &[(0.0, 1.0, 1.0, 1.0), (1.0, 1.0, 1.0, 0.0)],
+        '⎦' => &[(1.0, 1.0, 1.0, 0.0), (1.0, 0.0, 0.0, 0.0)],
+        '⎧' | '⎩' => &[(1.0, 0.0, 1.0, 1.0)],
+        '⎨' => &[
+            (1.0, 1.0, 1.0, 0.7),
+            (1.0, 0.7, 0.0, 0.5),
+            (0.0, 0.5, 1.0, 0.3),
+            (1.0, 0.3, 1.0, 0.0),
+        ],
+        '⎫' | '⎭' => &[(0.0, 0.0, 0.0, 1.0)],
+        '⎬' => &[
+            (0.0, 1.0, 0.0, 0.7),
+            (0.0, 0.7, 1.0, 0.5),
+            (1.0, 0.5, 0.0, 0.3),
+            (0.0, 0.3, 0.0, 0.0),
+        ],
+        '⌠' => &[
+            (0.5, 0.0, 0.5, 0.8),
+            (0.5, 0.8, 0.8, 1.0),
+            (0.8, 1.0, 1.0, 0.9),
+        ],
+        '⌡' => &[
+            (0.5, 1.0, 0.5, 0.2),
+            (0.5, 0.2, 0.2, 0.0),
+            (0.2, 0.0, 0.0, 0.1),
+        ],
+        '⟨' => &[(1.0, 1.0, 0.0, 0.5), (0.0, 0.5, 1.0, 0.0)],
+        '⟩' => &[(0.0, 1.0, 1.0, 0.5), (1.0, 0.5, 0.0, 0.0)],
+        '→' => &[
+            (0.0, 0.5, 1.0, 0.5),
+            (0.5, 0.8, 1.0, 0.5),
+            (0.5, 0.2, 1.0, 0.5),
+        ],
+        '←' => &[
+            (0.0, 0.5, 1.0, 0.5),
+            (0.5, 0.8, 0.0, 0.5),
+            (0.5, 0.2, 0.0, 0.5),
+        ],
+        '↑' => &[
+            (0.5, 0.0, 0.5, 1.0),
+            (0.0, 0.6, 0.5, 1.0),
+            (1.0, 0.6, 0.5, 1.0),
+        ],
+        '↓' => &[
+            (0.5, 0.0, 0.5, 1.0),
+            (0.0, 0.4, 0.5, 0.0),
+            (1.0, 0.4, 0.5, 0.0),
+        ],
+        '⇒' => &[
+            (0.0, 0.3, 0.7, 0.3),
+            (0.0, 0.7, 0.7, 0.7),
+            (0.4, 1.0, 1.0, 0.5),
+            (0.4, 0.0, 1.0, 0.5),
+        ],
+        '⇐' => &[
+            (0.3, 0.3, 1.0, 0.3),
+            (0.3, 0.7, 1.0, 0.7),
+            (0.6, 1.0, 0.0, 0.5),
+            (0.6, 0.0, 0.0, 0.5),
+        ],
+        _ => return None,
+    };
+    Some(strokes.to_vec())
+}
+
+/// Extract line segments and text positions from MathBox for the default
+/// Braille mode: fraction bars, radicals, the pieces of tall delimiters and
+/// extensible arrows are stroked, so they join up without the gaps fonts leave
+/// between glyphs, and everything else stays text
+fn extract_elements(
+    mbox: &MathBox,
+    area_height: f64,
+    scale: f64,
+) -> (Vec<BrailleLine>, Vec<(usize, usize, String)>) {
+    extract_strokes_where(mbox, area_height, scale, |mbox, x, y| {
+        match mbox.get(x, y) {
+            '─' | '_' | '√' | '⎛' | '⎜' | '⎝' | '⎞' | '⎟' | '⎠' | '⎡' | '⎢' | '⎣' | '⎤' | '⎥'
+            | '⎦' | '⎧' | '⎨' | '⎩' | '⎪' | '⎫' | '⎬' | '⎭' => true,
+            '→' | '←' | '⇒' | '⇐' | '═' => in_extensible_arrow(mbox, x, y),
+            _ => false,
+        }
+    })
+}
+
+/// Whether the cell at (x, y) is part of an arrow stretched over a label, as
+/// `\xrightarrow` lays out: an arrowhead on the end of a run of `─` or `═`.
+/// A lone `→` is left as text, like any other relation.
+fn in_extensible_arrow(mbox: &MathBox, x: usize, y: usize) -> bool {
+    let before = x.checked_sub(1).map(|x| mbox.get(x, y));
+    let after = Some(mbox.get(x + 1, y));
+    match mbox.get(x, y) {
+        '→' => before == Some('─'),
+        '⇒' => before == Some('═'),
+        '←' => after == Some('─'),
+        '⇐' => after == Some('═'),
+        '═' => {
+            let head_after = (x..mbox.width)
+                .map(|x| mbox.get(x, y))
+                .find(|&ch| ch != '═')
+                == Some('⇒');
+            let head_before =
+                (0..x).rev().map(|x| mbox.get(x, y)).find(|&ch| ch != '═') == Some('⇐');
+            head_after || head_before
+        }
+        _ => false,
+    }
+}
+
+/// The character of a grapheme that is a single character, the only kind
+/// drawn as strokes
+fn single_char(g: &str) -> Option<char> {
+    let mut chars = g.chars();
+    chars.next().filter(|_| chars.next().is_none())
+}
+
+/// Extract strokes for every drawn shape, and the remaining text, for full
+/// Braille mode
+fn extract_strokes(
+    mbox: &MathBox,
+    area_height: f64,
+    scale: f64,
+) -> (Vec<BrailleLine>, Vec<(usize, usize, String)>) {
+    extract_strokes_where(mbox, area_height, scale, |_, _, _| true)
+}
+
+/// Extract strokes for the drawn shapes `stroked` picks, given the box and a
+/// cell's column and row, and the remaining text. Canvas bounds of
+/// `[0, width - 0.5]` by `[0, height - 0.25]` put cell (col, row) on dots
+/// 2·col..2·col+1 and 4·row..4·row+3, so a cell spans half a unit across and
+/// three quarters of a unit up. Scaled up, a cell spans `scale` units each
+/// way, less the last dot.
+fn extract_strokes_where(
+    mbox: &MathBox,
+    area_height: f64,
+    scale: f64,
+    stroked: impl Fn(&MathBox, usize, usize) -> bool,
+) -> (Vec<BrailleLine>, Vec<(usize, usize, String)>) {
     let mut lines = Vec::new();
     let mut text_chars = Vec::new();
 
-    let content = mbox.to_lines();
-
-    for (row, line) in content.iter().enumerate() {
-        for (col, ch) in line.chars().enumerate() {
-            // Convert screen row (0=top) to canvas y (0=bottom)
-            // For row r, we want the line in the middle of that cell
-            // Screen row r is at canvas y = area_height - r - 0.5 (middle of cell)
-            let canvas_y_mid = area_height - row as f64 - 0.5;
-            let _canvas_y_top = area_height - row as f64;
-            let _canvas_y_bot = area_height - row as f64 - 1.0;
-
-            match ch {
-                // Horizontal line for fractions - draw with Braille for smoothness
-                '─' => {
-                    let x1 = col as f64;
-                    let x2 = (col + 1) as f64;
-                    lines.push(BrailleLine { x1, y1: canvas_y_mid, x2, y2: canvas_y_mid });
-                }
-                // Keep box-drawing characters as text for better visual connection
-                // with adjacent symbols like √
-                '╱' | '╲' | '│' => {
-                    text_chars.push((col, row, ch));
-                }
-                // Everything else is text
-                ' ' => {} // skip spaces
-                _ => {
-                    text_chars.push((col, row, ch));
-                }
+    let mbox = mbox.place_label(0);
+    for (col, row, g) in mbox.cells() {
+        let strokes = single_char(g)
+            .and_then(glyph_strokes)
+            .filter(|_| stroked(&mbox, col, row));
+        match strokes {
+            Some(strokes) => {
+                let bottom = area_height - scale * (row + 1) as f64;
+                let x = |u: f64| scale * col as f64 + (scale - 0.5) * u;
+                let y = |v: f64| bottom + (scale - 0.25) * v;
+                lines.extend(strokes.into_iter().map(|(x1, y1, x2, y2)| BrailleLine {
+                    x1: x(x1),
+                    y1: y(y1),
+                    x2: x(x2),
+                    y2: y(y2),
+                }));
             }
+            None if g == " " => {}
+            None => text_chars.push((col, row, g.to_string())),
         }
     }
 
     (lines, text_chars)
 }
 
+/// Where cell `index` of the layout lands when it's drawn `scale` times
+/// larger: the middle of the cells it's stretched over
+fn scaled(index: usize, scale: f64) -> f64 {
+    (index as f64 * scale + (scale - 1.0) / 2.0).floor()
+}
+
+/// Extract half-block lines and the remaining text for the half-block marker.
+/// Canvas bounds of `[0, width - 1]` by `[0, 2 * height - 1]` put cell (col, row)
+/// on x = col, with its upper half at y = 2 * (height - row) - 1 and its lower
+/// half one below. Scaled up, a cell's bar runs across the `scale` columns
+/// it covers, in the half row at the middle of its upper or lower half.
+fn extract_half_blocks(
+    mbox: &MathBox,
+    area_height: f64,
+    scale: f64,
+) -> (Vec<BrailleLine>, Vec<(usize, usize, String)>) {
+    let mut lines = Vec::new();
+    let mut text_chars = Vec::new();
+
+    for (col, row, g) in mbox.place_label(0).cells() {
+        // Fraction bars and overlines sit in the upper half; a radical's
+        // `_` overline in the lower half, against the radicand below
+        let half = match g {
+            "─" | "‾" => 2 * row,
+            "_" => 2 * row + 1,
+            " " => continue,
+            _ => {
+                text_chars.push((col, row, g.to_string()));
+                continue;
+            }
+        };
+        let y = 2.0 * area_height - 1.0 - scaled(half, scale);
+        let x1 = (scale * col as f64).floor();
+        let x2 = (scale * (col + 1) as f64).ceil() - 1.0;
+        lines.push(BrailleLine {
+            x1,
+            y1: y,
+            x2,
+            y2: y,
+        });
+    }
+
+    (lines, text_chars)
+}
+
+/// Where a [`Canvas`] puts its dots and labels: the area it's rendered in,
+/// its bounds and its marker. [`paint_into`] needs these to line a formula's
+/// strokes up with its text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanvasGrid {
+    /// Area the canvas is rendered in
+    pub area: Rect,
+    /// The canvas's x bounds, left to right
+    pub x_bounds: [f64; 2],
+    /// The canvas's y bounds, bottom to top
+    pub y_bounds: [f64; 2],
+    /// The canvas's marker
+    pub marker: Marker,
+}
+
+/// Paint a rendered formula into a caller's [`Canvas`], e.g. to label the
+/// axes or curves of a plot. The formula's top-left cell is the one
+/// `ctx.print` would put a label at `origin` in. Bars, radicals, brackets,
+/// arrows and rules are stroked as in full Braille mode, in the style's
+/// foreground color, and everything else is printed as text; parts falling
+/// outside the canvas are dropped.
+///
+/// ```
+/// use ratatui::layout::Rect;
+/// use ratatui::style::Style;
+/// use ratatui::symbols::Marker;
+/// use ratatui::widgets::canvas::Canvas;
+/// use tui_math::{paint_into, CanvasGrid, MathRenderer};
+///
+/// let area = Rect::new(0, 0, 80, 24);
+/// let grid = CanvasGrid { area, x_bounds: [0.0, 10.0], y_bounds: [-1.0, 1.0], marker: Marker::Braille };
+/// let label = MathRenderer::new().render_to_box(r"y = \sqrt{x}").unwrap();
+/// let canvas = Canvas::default()
+///     .x_bounds(grid.x_bounds)
+///     .y_bounds(grid.y_bounds)
+///     .marker(grid.marker)
+///     .paint(|ctx| paint_into(ctx, &label, (8.0, 0.8), grid, Style::default()));
+/// ```
+pub fn paint_into(
+    ctx: &mut Context<'_>,
+    mathbox: &MathBox,
+    origin: (f64, f64),
+    grid: CanvasGrid,
+    style: Style,
+) {
+    let [left, right] = grid.x_bounds;
+    let [bottom, top] = grid.y_bounds;
+    let (x_span, y_span) = (right - left, top - bottom);
+    if x_span <= 0.0 || y_span <= 0.0 {
+        return;
+    }
+    let (width, height) = (
+        f64::from(grid.area.width.max(2)),
+        f64::from(grid.area.height.max(2)),
+    );
+    let (dots_x, dots_y) = match grid.marker {
+        Marker::Braille => (2.0, 4.0),
+        Marker::HalfBlock => (1.0, 2.0),
+        _ => (1.0, 1.0),
+    };
+    // Labels land on cell floor((x - left) * (width - 1) / x_span) and dots on
+    // floor((x - left) * (dots - 1) / x_span), counted from the top left; aim
+    // for the middle of each to stay clear of rounding
+    let col0 = ((origin.0 - left) * (width - 1.0) / x_span).floor();
+    let row0 = ((top - origin.1) * (height - 1.0) / y_span).floor();
+    let label_at = |col: f64, row: f64| {
+        let x = (left + (col + 0.5) * x_span / (width - 1.0)).min(right);
+        let y = (top - (row + 0.5) * y_span / (height - 1.0)).max(bottom);
+        (x, y)
+    };
+    let dot_at = |dx: f64, dy: f64| {
+        let x = (left + (dx + 0.5) * x_span / (dots_x * width - 1.0)).min(right);
+        let y = (top - (dy + 0.5) * y_span / (dots_y * height - 1.0)).max(bottom);
+        (x, y)
+    };
+    let color = style.fg.unwrap_or(Color::Reset);
+
+    for (col, row, g) in mathbox.place_label(0).cells() {
+        let (col, row) = (col0 + col as f64, row0 + row as f64);
+        if col >= width || row >= height {
+            continue;
+        }
+        match single_char(g).and_then(glyph_strokes) {
+            Some(strokes) => {
+                let point = |u: f64, v: f64| {
+                    let (du, dv) = (
+                        (u * (dots_x - 1.0)).floor(),
+                        ((1.0 - v) * (dots_y - 1.0)).floor(),
+                    );
+                    dot_at(dots_x * col + du, dots_y * row + dv)
+                };
+                for (u1, v1, u2, v2) in strokes {
+                    let ((x1, y1), (x2, y2)) = (point(u1, v1), point(u2, v2));
+                    ctx.draw(&Line {
+                        x1,
+                        y1,
+                        x2,
+                        y2,
+                        color,
+                    });
+                }
+            }
+            None if g == " " => {}
+            None => {
+                let (x, y) = label_at(col, row);
+                ctx.print(x, y, Span::styled(g.to_string(), style));
+            }
+        }
+    }
+}
+
 impl Widget for CanvasMathWidget<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        self.background
+            .apply(area, buf, self.style, |buf| self.draw(area, buf));
+    }
+}
+
+impl CanvasMathWidget<'_> {
+    fn draw(&self, area: Rect, buf: &mut Buffer) {
         // First render to MathBox using existing renderer
-        let renderer = MathRenderer::new();
-        let mbox = match renderer.render_to_box(self.latex) {
+        let renderer = MathRenderer::with_options(self.options.clone());
+        let mbox = match self.source.to_box(&renderer) {
             Ok(b) => b,
             Err(e) => {
                 buf.set_string(area.x, area.y, format!("Error: {}", e), self.style);
@@ -115,24 +538,29 @@ impl Widget for CanvasMathWidget<'_> {
             }
         };
 
-        // Calculate content area (accounting for block borders)
+        // Calculate content area (accounting for block borders and padding)
         let content_area = if let Some(ref block) = self.block {
             let inner = block.inner(area);
             block.clone().render(area, buf);
-            inner
+            pad(inner, self.padding)
         } else {
-            area
+            pad(area, self.padding)
         };
 
         // Extract line segments and text
-        // Use MathBox height for coordinate mapping to ensure alignment
-        let mbox_height_f = mbox.height as f64;
-        let (braille_lines, text_chars) = extract_elements(&mbox, mbox_height_f);
+        // Use the (scaled) MathBox height for coordinate mapping to ensure alignment
+        let scale = self.scale;
+        let mbox_width = (mbox.width as f64 * scale).ceil() as u16;
+        let mbox_height = (mbox.height as f64 * scale).ceil() as u16;
+        let mbox_height_f = f64::from(mbox_height);
+        let (braille_lines, text_chars) = match self.marker {
+            Marker::HalfBlock => extract_half_blocks(&mbox, mbox_height_f, scale),
+            Marker::Braille if self.full_braille => extract_strokes(&mbox, mbox_height_f, scale),
+            _ => extract_elements(&mbox, mbox_height_f, scale),
+        };
 
         // Render Canvas FIRST (so text can overlay it)
         if !braille_lines.is_empty() {
-            let mbox_width = mbox.width as u16;
-            let mbox_height = mbox.height as u16;
             let color = self.color;
 
             // Create canvas area that matches MathBox size
@@ -145,18 +573,27 @@ impl Widget for CanvasMathWidget<'_> {
 
             let canvas_width = canvas_area.width as f64;
             let canvas_height = canvas_area.height as f64;
+            // Strokes and half blocks are placed on exact dots (see
+            // extract_strokes_where and extract_half_blocks)
+            let (x_max, y_max) = match self.marker {
+                Marker::HalfBlock => (canvas_width - 1.0, 2.0 * canvas_height - 1.0),
+                _ => (canvas_width - 0.5, canvas_height - 0.25),
+            };
 
+            // The canvas clears its area to its background color, and half blocks
+            // show it in their undrawn half: compose strokes over the widget's
+            // background so a half-drawn cell doesn't cut a hole in it
             let canvas = Canvas::default()
-                .marker(Marker::Braille)
-                .x_bounds([0.0, canvas_width])
-                .y_bounds([0.0, canvas_height])
+                .marker(self.marker)
+                .background_color(self.style.bg.unwrap_or(Color::Reset))
+                .x_bounds([0.0, x_max])
+                .y_bounds([0.0, y_max])
                 .paint(move |ctx| {
                     for line in &braille_lines {
-                        // Add 0.5 to x coordinates to align Braille dots with character cells
                         ctx.draw(&Line {
-                            x1: line.x1 + 0.5,
+                            x1: line.x1,
                             y1: line.y1,
-                            x2: line.x2 + 0.5,
+                            x2: line.x2,
                             y2: line.y2,
                             color,
                         });
@@ -167,12 +604,164 @@ impl Widget for CanvasMathWidget<'_> {
         }
 
         // Render text characters AFTER canvas (so text overlays Braille)
-        for (col, row, ch) in &text_chars {
-            let x = content_area.x + *col as u16;
-            let y = content_area.y + *row as u16;
+        for (col, row, g) in &text_chars {
+            let x = content_area.x.saturating_add(scaled(*col, scale) as u16);
+            let y = content_area.y.saturating_add(scaled(*row, scale) as u16);
             if x < content_area.right() && y < content_area.bottom() {
-                buf.set_string(x, y, ch.to_string(), self.style);
+                buf.set_string(x, y, g, self.style);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer_rows(buf: &Buffer) -> Vec<String> {
+        let area = buf.area;
+        (0..area.height)
+            .map(|y| {
+                (0..area.width)
+                    .map(|x| buf[(x, y)].symbol())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn rows(widget: CanvasMathWidget, area: Rect) -> Vec<String> {
+        let mut buf = Buffer::empty(area);
+        widget.render(area, &mut buf);
+        buffer_rows(&buf)
+    }
+
+    #[test]
+    fn test_full_braille() {
+        let area = Rect::new(0, 0, 12, 3);
+        let widget = CanvasMathWidget::new(r"\left( \frac{x}{y} \right)").full_braille(true);
+        assert_eq!(rows(widget, area), ["⡎x⢱", "⡇⠒⢸", "⢇y⡸"]);
+    }
+
+    #[test]
+    fn test_braille_radical() {
+        let area = Rect::new(0, 0, 6, 2);
+        let widget = CanvasMathWidget::new(r"\sqrt{x+1}");
+        assert_eq!(rows(widget, area), [" ⣀⣀⣀⣀⣀", "⡞x + 1"]);
+    }
+
+    #[test]
+    fn test_braille_delimiters() {
+        let area = Rect::new(0, 0, 3, 3);
+        let widget = CanvasMathWidget::new(r"\left[ \frac{a}{b} \right)");
+        assert_eq!(rows(widget, area), ["⡏a⢱", "⡇⠒⢸", "⣇b⡸"]);
+    }
+
+    #[test]
+    fn test_paint_into() {
+        let mbox = MathRenderer::new().render_to_box(r"\frac{12}{3}").unwrap();
+        let area = Rect::new(0, 0, 5, 4);
+        let mut buf = Buffer::empty(area);
+        Canvas::default()
+            .x_bounds([0.0, 4.0])
+            .y_bounds([0.0, 3.0])
+            .paint(|ctx| {
+                let grid = CanvasGrid {
+                    area,
+                    x_bounds: [0.0, 4.0],
+                    y_bounds: [0.0, 3.0],
+                    marker: Marker::Braille,
+                };
+                paint_into(ctx, &mbox, (2.0, 2.0), grid, Style::default());
+            })
+            .render(area, &mut buf);
+        assert_eq!(buffer_rows(&buf), ["", "  12", "  ⠒⠒", "  3"]);
+    }
+
+    #[test]
+    fn test_scale() {
+        let area = Rect::new(0, 0, 5, 6);
+        let widget = CanvasMathWidget::new(r"\frac{12}{3}").scale(2.0);
+        assert_eq!(rows(widget, area), ["1 2", "", "⣀⣀⣀⣀", "", "3", ""]);
+    }
+
+    #[test]
+    fn test_braille_extensible_arrow() {
+        let area = Rect::new(0, 0, 7, 2);
+        assert_eq!(
+            rows(CanvasMathWidget::new(r"\xrightarrow{abc}"), area),
+            [" abc", "⠒⠒⠒⠒⠗"]
+        );
+        assert_eq!(rows(CanvasMathWidget::new(r"A \to B"), area), ["A → B", ""]);
+    }
+
+    #[test]
+    fn test_half_block_marker() {
+        let area = Rect::new(0, 0, 4, 4);
+        let mut buf = Buffer::empty(area);
+        CanvasMathWidget::new(r"\frac{1}{\sqrt{2}}")
+            .marker(Marker::HalfBlock)
+            .color(Color::Yellow)
+            .style(Style::default().bg(Color::Blue))
+            .render(area, &mut buf);
+        assert_eq!(buffer_rows(&buf), ["1", "▀▀", " ▄", "√2"]);
+        assert_eq!(
+            (buf[(1, 2)].fg, buf[(1, 2)].bg),
+            (Color::Yellow, Color::Blue)
+        );
+    }
+
+    #[test]
+    fn test_from_box() {
+        let mbox = MathRenderer::new().render_to_box(r"\frac{1}{2}").unwrap();
+        let area = Rect::new(0, 0, 4, 4);
+        let (mut from_box, mut from_latex) = (Buffer::empty(area), Buffer::empty(area));
+        CanvasMathWidget::from_box(mbox).render(area, &mut from_box);
+        CanvasMathWidget::new(r"\frac{1}{2}").render(area, &mut from_latex);
+        assert_eq!(from_box, from_latex);
+    }
+
+    #[test]
+    fn test_padding() {
+        let area = Rect::new(0, 0, 4, 5);
+        let widget = CanvasMathWidget::new(r"\frac{12}{3}")
+            .marker(Marker::HalfBlock)
+            .padding(Padding::new(2, 0, 1, 0));
+        assert_eq!(rows(widget, area), ["", "  12", "  ▀▀", "  3", ""]);
+    }
+
+    #[test]
+    fn test_options() {
+        let area = Rect::new(0, 0, 8, 1);
+        let ascii = RendererOptions {
+            ascii: true,
+            ..RendererOptions::default()
+        };
+        assert_eq!(
+            rows(CanvasMathWidget::new(r"\alpha \leq 1"), area),
+            ["α ≤ 1"]
+        );
+        assert_eq!(
+            rows(CanvasMathWidget::new(r"\alpha \leq 1").options(ascii), area),
+            ["alpha <="]
+        );
+        assert_eq!(
+            rows(
+                CanvasMathWidget::new(r"\vec{v}").accents(AccentStrategy::Ascii),
+                area
+            ),
+            ["vec(v)"]
+        );
+    }
+
+    #[test]
+    fn test_transparent_background() {
+        let mut buf = Buffer::with_lines(["....", "....", "...."]);
+        CanvasMathWidget::new(r"\frac{12}{3}")
+            .marker(Marker::HalfBlock)
+            .background(Background::Transparent)
+            .render(buf.area, &mut buf);
+        assert_eq!(buffer_rows(&buf), ["12..", "▀▀..", "3..."]);
+    }
+}
@@ -0,0 +1,114 @@
+//! What characters a terminal can show, for degrading output on consoles
+//! with limited fonts
+
+/// The characters a terminal's font and encoding can be relied on for.
+/// [`MathRenderer`](crate::MathRenderer) falls back to simpler glyphs for
+/// anything a profile rules out instead of leaving boxes or tofu; pick one
+/// with [`CapabilityProfile::detect`] or set it through
+/// [`RendererOptions::profile`](crate::RendererOptions::profile).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CapabilityProfile {
+    /// Everything: combining marks and characters outside the Basic
+    /// Multilingual Plane, such as bold and double-struck math letters
+    #[default]
+    FullUnicode,
+    /// No combining marks: accents are stacked on their own row and other
+    /// marks dropped
+    NoCombining,
+    /// No combining marks and only Basic Multilingual Plane characters:
+    /// styled math letters (𝐱, 𝔽) are drawn as plain letters, bold ones with
+    /// a bold modifier. Suits the Linux console and legacy Windows consoles.
+    BmpOnly,
+    /// ASCII only, as with [`MathRenderer::ascii_only`](crate::MathRenderer::ascii_only)
+    Ascii,
+}
+
+impl CapabilityProfile {
+    /// Guess the profile of the terminal the process runs in from its
+    /// environment: `TERM` (the Linux console, `dumb` and VT terminals), the
+    /// locale (`LC_ALL`, `LC_CTYPE` or `LANG` naming a non-UTF-8 encoding)
+    /// and, on Windows, whether it is Windows Terminal (`WT_SESSION`) or a
+    /// legacy console. Defaults to full Unicode when nothing says otherwise.
+    pub fn detect() -> Self {
+        Self::from_env(|name| std::env::var(name).ok(), cfg!(windows))
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>, windows: bool) -> Self {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+        let term = var("TERM").unwrap_or_default();
+        if matches!(
+            term.as_str(),
+            "dumb" | "ansi" | "vt52" | "vt100" | "vt102" | "vt220"
+        ) {
+            return CapabilityProfile::Ascii;
+        }
+        let locale = var("LC_ALL")
+            .or_else(|| var("LC_CTYPE"))
+            .or_else(|| var("LANG"));
+        if let Some(locale) = locale {
+            let locale = locale.to_ascii_lowercase();
+            if !locale.contains("utf-8") && !locale.contains("utf8") {
+                return CapabilityProfile::Ascii;
+            }
+        }
+        if term == "linux" || (windows && term.is_empty() && var("WT_SESSION").is_none()) {
+            return CapabilityProfile::BmpOnly;
+        }
+        CapabilityProfile::FullUnicode
+    }
+
+    /// Whether combining marks (as in `x̂`) display correctly
+    pub fn supports_combining(self) -> bool {
+        self == CapabilityProfile::FullUnicode
+    }
+
+    /// Whether characters outside the Basic Multilingual Plane display
+    pub fn supports_astral(self) -> bool {
+        matches!(
+            self,
+            CapabilityProfile::FullUnicode | CapabilityProfile::NoCombining
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(vars: &[(&str, &str)], windows: bool) -> CapabilityProfile {
+        let var = |name: &str| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, value)| value.to_string())
+        };
+        CapabilityProfile::from_env(var, windows)
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            detect(
+                &[("TERM", "xterm-256color"), ("LANG", "en_US.UTF-8")],
+                false
+            ),
+            CapabilityProfile::FullUnicode
+        );
+        assert_eq!(
+            detect(&[("TERM", "linux"), ("LANG", "en_US.UTF-8")], false),
+            CapabilityProfile::BmpOnly
+        );
+        assert_eq!(
+            detect(
+                &[("TERM", "xterm"), ("LC_ALL", "C"), ("LANG", "en_US.UTF-8")],
+                false
+            ),
+            CapabilityProfile::Ascii
+        );
+        assert_eq!(detect(&[("TERM", "dumb")], false), CapabilityProfile::Ascii);
+        assert_eq!(detect(&[], true), CapabilityProfile::BmpOnly);
+        assert_eq!(
+            detect(&[("WT_SESSION", "1")], true),
+            CapabilityProfile::FullUnicode
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Chemical equations: a minimal subset of mhchem's `\ce{...}`
+//!
+//! `\ce` contents are translated to plain LaTeX before conversion: element
+//! symbols are set upright, counts after an element or group become subscripts,
+//! charges become superscripts and reaction arrows become relations.
+
+/// What the last translated token was, to decide how digits and signs attach
+#[derive(Clone, Copy, PartialEq)]
+enum Prev {
+    /// Start of input or whitespace
+    Space,
+    /// An element symbol, closing bracket or subscript: digits after it are counts
+    Atom,
+    /// Anything else (coefficients, operators, opening brackets)
+    Other,
+}
+
+/// Translate the body of a `\ce{...}` to LaTeX
+pub(crate) fn ce_to_latex(formula: &str) -> String {
+    let chars: Vec<char> = formula.chars().collect();
+    let mut out = String::new();
+    let mut prev = Prev::Space;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let rest: String = chars[i..].iter().take(3).collect();
+        let c = chars[i];
+
+        // Reaction arrows, longest first
+        if let Some((arrow, command)) = [
+            ("<=>", r"\rightleftharpoons"),
+            ("<->", r"\leftrightarrow"),
+            ("->", r"\rightarrow"),
+            ("<-", r"\leftarrow"),
+        ]
+        .into_iter()
+        .find(|(arrow, _)| rest.starts_with(arrow))
+        {
+            i += arrow.len();
+            // `->[above]` puts a condition over the arrow
+            if command == r"\rightarrow" && chars.get(i) == Some(&'[') {
+                if let Some(close) = chars[i..].iter().position(|&c| c == ']') {
+                    let above: String = chars[i + 1..i + close].iter().collect();
+                    // Conditions are text unless they use math commands (->[\Delta])
+                    if above.contains('\\') {
+                        out.push_str(&format!(r" \xrightarrow{{{}}} ", above));
+                    } else {
+                        out.push_str(&format!(r" \xrightarrow{{\text{{{}}}}} ", above));
+                    }
+                    i += close + 1;
+                    prev = Prev::Other;
+                    continue;
+                }
+            }
+            out.push_str(&format!(" {} ", command));
+            prev = Prev::Other;
+            continue;
+        }
+
+        match c {
+            'A'..='Z' => {
+                let len = 1 + chars[i + 1..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_lowercase())
+                    .count();
+                let symbol: String = chars[i..i + len].iter().collect();
+                out.push_str(&format!(r"\mathrm{{{}}}", symbol));
+                i += len;
+                prev = Prev::Atom;
+                continue;
+            }
+            'a'..='z' => {
+                // State symbols such as (aq) and (s) are upright too
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_lowercase())
+                    .count();
+                let word: String = chars[i..i + len].iter().collect();
+                out.push_str(&format!(r"\mathrm{{{}}}", word));
+                i += len;
+                // The electron takes a charge like an element (e-)
+                prev = if word == "e" { Prev::Atom } else { Prev::Other };
+                continue;
+            }
+            '0'..='9' => {
+                let len = chars[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let digits: String = chars[i..i + len].iter().collect();
+                i += len;
+                if prev == Prev::Atom {
+                    out.push_str(&format!("_{{{}}}", digits));
+                } else {
+                    out.push_str(&digits);
+                    prev = Prev::Other;
+                }
+                continue;
+            }
+            '^' => {
+                // Explicit charge: ^{2+}, ^2-, ^-
+                i += 1;
+                let charge: String = if chars.get(i) == Some(&'{') {
+                    let len = chars[i..]
+                        .iter()
+                        .position(|&c| c == '}')
+                        .unwrap_or(chars.len() - i);
+                    let charge = chars[i + 1..i + len].iter().collect();
+                    i += len + 1;
+                    charge
+                } else {
+                    let len = chars[i..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_digit() || matches!(c, '+' | '-'))
+                        .count();
+                    let charge = chars[i..i + len].iter().collect();
+                    i += len;
+                    charge
+                };
+                out.push_str(&format!("^{{{}}}", charge));
+                prev = Prev::Other;
+                continue;
+            }
+            '+' | '-'
+                if prev == Prev::Atom
+                    && chars
+                        .get(i + 1)
+                        .is_none_or(|c| c.is_whitespace() || *c == ')') =>
+            {
+                // A sign right after an atom is its charge (Na+, Cl-)
+                out.push_str(&format!("^{{{}}}", c));
+                prev = Prev::Other;
+            }
+            ')' | ']' => {
+                out.push(c);
+                prev = Prev::Atom;
+            }
+            '.' | '*' => {
+                out.push_str(r" \cdot ");
+                prev = Prev::Other;
+            }
+            c if c.is_whitespace() => {
+                out.push(' ');
+                prev = Prev::Space;
+            }
+            _ => {
+                out.push(c);
+                prev = Prev::Other;
+            }
+        }
+        i += 1;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ce_to_latex() {
+        assert_eq!(
+            ce_to_latex("2H2O -> 2H2 + O2"),
+            r"2\mathrm{H}_{2}\mathrm{O}  \rightarrow  2\mathrm{H}_{2} + \mathrm{O}_{2}"
+        );
+        assert_eq!(
+            ce_to_latex("SO4^2- + Na+"),
+            r"\mathrm{S}\mathrm{O}_{4}^{2-} + \mathrm{Na}^{+}"
+        );
+        assert_eq!(
+            ce_to_latex("Fe^3+ + e- <=> Fe^2+"),
+            r"\mathrm{Fe}^{3+} + \mathrm{e}^{-}  \rightleftharpoons  \mathrm{Fe}^{2+}"
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Formula entry: LaTeX source with a cursor and a live rendering
+
+use crate::latex::{atom_at, atoms, tokens};
+use crate::widget::box_to_lines;
+use crate::MathRenderer;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, StatefulWidget, Widget},
+};
+
+/// Text being edited in a [`MathEditor`], with a cursor between atoms
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MathEditorState {
+    source: String,
+    /// Byte offset of the cursor, always at an atom boundary
+    cursor: usize,
+}
+
+impl MathEditorState {
+    /// Create an empty editor state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an editor state holding `source`, with the cursor at the end
+    pub fn with_source(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            cursor: source.len(),
+        }
+    }
+
+    /// The LaTeX being edited
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Byte offset of the cursor in the source
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Move the cursor to the atom boundary at or before byte offset `pos`
+    pub fn set_cursor(&mut self, pos: usize) {
+        self.cursor = atoms(&self.source)
+            .into_iter()
+            .map(|(start, _)| start)
+            .take_while(|&start| start <= pos)
+            .last()
+            .unwrap_or(0);
+        if pos >= self.source.len() {
+            self.cursor = self.source.len();
+        }
+    }
+
+    /// The atom just before the cursor
+    fn atom_before(&self) -> Option<(usize, usize)> {
+        atoms(&self.source)
+            .into_iter()
+            .take_while(|&(_, end)| end <= self.cursor)
+            .last()
+    }
+
+    /// Move the cursor back over one character or command
+    pub fn move_left(&mut self) {
+        if let Some((start, _)) = self.atom_before() {
+            self.cursor = start;
+        }
+    }
+
+    /// Move the cursor forward over one character or command
+    pub fn move_right(&mut self) {
+        if let Some((_, end)) = atom_at(&self.source, self.cursor) {
+            self.cursor = end;
+        }
+    }
+
+    /// Move the cursor to the start of the source
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Move the cursor to the end of the source
+    pub fn move_end(&mut self) {
+        self.cursor = self.source.len();
+    }
+
+    /// Insert a character at the cursor
+    pub fn insert_char(&mut self, ch: char) {
+        self.source.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+    }
+
+    /// Insert text at the cursor, e.g. a snippet such as `\frac{}{}`
+    pub fn insert_str(&mut self, text: &str) {
+        self.source.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    /// Delete the character or command before the cursor (backspace)
+    pub fn delete_backward(&mut self) {
+        if let Some((start, end)) = self.atom_before() {
+            self.source.replace_range(start..end, "");
+            self.cursor = start;
+        }
+    }
+
+    /// Delete the character or command after the cursor
+    pub fn delete_forward(&mut self) {
+        if let Some((start, end)) = atom_at(&self.source, self.cursor) {
+            self.source.replace_range(start..end, "");
+        }
+    }
+
+    /// Byte range of the token under the cursor, or just before it at the end
+    /// of the source (see the [`MathEditor`] docs). Braces, scripts and spaces
+    /// aren't tokens.
+    pub fn token_at_cursor(&self) -> Option<(usize, usize)> {
+        let cursor = self.cursor;
+        tokens(&self.source).into_iter().find(|&(start, end)| {
+            (start..end).contains(&cursor) || (end == cursor && cursor == self.source.len())
+        })
+    }
+}
+
+/// A formula-entry widget: the LaTeX source on the first row with the
+/// cursor shown, and below it the live rendering with the token under the
+/// cursor highlighted. A token is a character or command, with a run of
+/// digits taken whole since it renders as one number. Feed key presses to
+/// [`MathEditorState`].
+#[derive(Clone)]
+pub struct MathEditor<'a> {
+    style: Style,
+    cursor_style: Style,
+    block: Option<Block<'a>>,
+    show_source: bool,
+    use_unicode_scripts: bool,
+}
+
+impl<'a> MathEditor<'a> {
+    /// Create an editor widget showing the source and the rendering
+    pub fn new() -> Self {
+        Self {
+            style: Style::default(),
+            cursor_style: Style::default().add_modifier(Modifier::REVERSED),
+            block: None,
+            show_source: true,
+            use_unicode_scripts: true,
+        }
+    }
+
+    /// Set the style for the source and rendering
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Set the style of the cursor and of the token it's on in the
+    /// rendering (reversed by default)
+    pub fn cursor_style(mut self, style: Style) -> Self {
+        self.cursor_style = style;
+        self
+    }
+
+    /// Wrap the widget in a block
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Show the source row above the rendering (on by default)
+    pub fn show_source(mut self, show: bool) -> Self {
+        self.show_source = show;
+        self
+    }
+
+    /// Enable or disable Unicode superscript/subscript characters
+    pub fn use_unicode_scripts(mut self, use_unicode: bool) -> Self {
+        self.use_unicode_scripts = use_unicode;
+        self
+    }
+
+    /// The source row, scrolled sideways to keep the cursor in `width` columns
+    fn source_line(&self, state: &MathEditorState, width: usize) -> Line<'static> {
+        let before: Vec<char> = state.source[..state.cursor].chars().collect();
+        let mut after = state.source[state.cursor..].chars();
+        let skip = (before.len() + 1).saturating_sub(width);
+        let cursor = after.next().map_or(" ".to_string(), String::from);
+        Line::from(vec![
+            Span::styled(before[skip..].iter().collect::<String>(), self.style),
+            Span::styled(cursor, self.style.patch(self.cursor_style)),
+            Span::styled(after.collect::<String>(), self.style),
+        ])
+    }
+}
+
+impl Default for MathEditor<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatefulWidget for MathEditor<'_> {
+    type State = MathEditorState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut MathEditorState) {
+        let width = usize::from(self.block.as_ref().map_or(area, |b| b.inner(area)).width);
+        let mut renderer = MathRenderer::new().use_unicode_scripts(self.use_unicode_scripts);
+        if let Some(path) = state
+            .token_at_cursor()
+            .and_then(|span| renderer.token_path(&state.source, span))
+        {
+            renderer = renderer.highlight(&path, self.cursor_style);
+        }
+
+        let mut lines = Vec::new();
+        if self.show_source {
+            lines.push(self.source_line(state, width));
+        }
+        match renderer.render_to_box(&state.source) {
+            Ok(mbox) => lines.extend(box_to_lines(&mbox.place_label(width), self.style)),
+            Err(e) => lines.push(Line::from(Span::styled(
+                format!("Error: {}", e),
+                self.style,
+            ))),
+        }
+
+        let mut paragraph = Paragraph::new(lines);
+        if let Some(block) = self.block {
+            paragraph = paragraph.block(block);
+        }
+        paragraph.render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editing_by_atoms() {
+        let mut state = MathEditorState::with_source(r"\alpha+12");
+        state.move_home();
+        state.move_right();
+        assert_eq!(state.cursor(), 6);
+        state.insert_str("^2");
+        state.delete_backward();
+        state.delete_backward();
+        assert_eq!(state.source(), r"\alpha+12");
+        state.delete_forward();
+        state.move_end();
+        state.move_left();
+        assert_eq!(state.token_at_cursor(), Some((6, 8)));
+        state.delete_backward();
+        state.delete_backward();
+        state.delete_backward();
+        assert_eq!(state.source(), "2");
+    }
+
+    #[test]
+    fn test_cursor_token_highlighted() {
+        let mut state = MathEditorState::with_source(r"\frac{1}{x} + y");
+        state.set_cursor(9);
+        let area = Rect::new(0, 0, 16, 4);
+        let mut buf = Buffer::empty(area);
+        MathEditor::new().render(area, &mut buf, &mut state);
+        assert_eq!(buf[(9, 0)].symbol(), "x");
+        assert!(buf[(9, 0)].modifier.contains(Modifier::REVERSED));
+        assert_eq!(buf[(0, 3)].symbol(), "x");
+        assert!(buf[(0, 3)].modifier.contains(Modifier::REVERSED));
+        assert!(!buf[(0, 1)].modifier.contains(Modifier::REVERSED));
+    }
+
+    #[test]
+    fn test_cursor_token_after_text() {
+        let mut state = MathEditorState::with_source(r"\text{ab} + \mathbf{v} + y");
+        state.set_cursor(25);
+        let area = Rect::new(0, 0, 26, 2);
+        let mut buf = Buffer::empty(area);
+        MathEditor::new().render(area, &mut buf, &mut state);
+        // Rendered as "ab + 𝐯 + y"
+        let reversed: Vec<u16> = (0..10)
+            .filter(|&x| buf[(x, 1)].modifier.contains(Modifier::REVERSED))
+            .collect();
+        assert_eq!(buf[(9, 1)].symbol(), "y");
+        assert_eq!(reversed, [9]);
+    }
+}
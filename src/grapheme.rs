@@ -0,0 +1,132 @@
+//! Interned grapheme clusters, the cells of a [`MathBox`](crate::MathBox)
+//!
+//! A rendered formula is mostly spaces and single ASCII characters, with a
+//! handful of distinct symbols (`─`, `∑`, `α`, ...) repeated across it.
+//! Cells hold a [`Grapheme`], a reference into either a static table of the
+//! printable ASCII characters or a process-wide pool where every other
+//! cluster is stored once, so copying cells between boxes never allocates.
+//! The pool only grows, by the clusters actually drawn.
+
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::RwLock;
+
+/// The printable ASCII characters, from space to `~`
+static ASCII: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+static POOL: Lazy<RwLock<HashSet<&'static str>>> = Lazy::new(|| RwLock::new(HashSet::new()));
+
+/// A grapheme cluster in a [`MathBox`](crate::MathBox) cell, interned so it
+/// is a cheap copy. Compares and dereferences as the `&str` it holds; make
+/// one from a `&str` or `char` with `into()`. The empty grapheme marks the
+/// continuation cells after a wide character.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Grapheme(&'static str);
+
+impl Grapheme {
+    /// A blank cell
+    pub const SPACE: Grapheme = Grapheme(" ");
+
+    /// Intern `g`
+    pub fn new(g: &str) -> Self {
+        if let Some(ascii) = ascii(g) {
+            return ascii;
+        }
+        if let Some(&interned) = POOL.read().unwrap_or_else(|e| e.into_inner()).get(g) {
+            return Grapheme(interned);
+        }
+        let mut pool = POOL.write().unwrap_or_else(|e| e.into_inner());
+        match pool.get(g) {
+            Some(&interned) => Grapheme(interned),
+            None => {
+                let interned: &'static str = Box::leak(g.into());
+                pool.insert(interned);
+                Grapheme(interned)
+            }
+        }
+    }
+
+    /// The grapheme cluster
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+/// The static entry for an empty or single printable ASCII character string
+fn ascii(g: &str) -> Option<Grapheme> {
+    match g.as_bytes() {
+        [] => Some(Grapheme("")),
+        &[byte @ b' '..=b'~'] => {
+            let i = usize::from(byte - b' ');
+            Some(Grapheme(&ASCII[i..i + 1]))
+        }
+        _ => None,
+    }
+}
+
+impl Deref for Grapheme {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl AsRef<str> for Grapheme {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl From<&str> for Grapheme {
+    fn from(g: &str) -> Self {
+        Grapheme::new(g)
+    }
+}
+
+impl From<char> for Grapheme {
+    fn from(ch: char) -> Self {
+        Grapheme::new(ch.encode_utf8(&mut [0; 4]))
+    }
+}
+
+impl PartialEq<str> for Grapheme {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Grapheme {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Debug for Grapheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+impl fmt::Display for Grapheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interning() {
+        assert!(std::ptr::eq(Grapheme::from('x').as_str(), &ASCII[88..89]));
+        assert_eq!(Grapheme::from('~'), "~");
+        let bar = Grapheme::new("─");
+        assert!(std::ptr::eq(bar.as_str(), Grapheme::from('─').as_str()));
+        assert_eq!(Grapheme::new("T\u{0304}"), "T\u{0304}");
+        assert_eq!(Grapheme::default(), "");
+    }
+}
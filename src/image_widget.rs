@@ -0,0 +1,178 @@
+//! Math widget drawn as an image through a terminal graphics protocol
+//!
+//! The equation is rasterized (see [`crate::raster`]) at the terminal's cell
+//! size and drawn by ratatui-image over the widget's cells, with whichever
+//! protocol its [`Picker`] chose (kitty, iTerm2, sixel). Terminals without one
+//! get the Unicode [`MathWidget`] instead.
+
+use crate::raster::{rasterize, rgb, CellSize};
+use crate::{MathBox, MathRenderer, MathWidget, RendererOptions};
+use image::{DynamicImage, RgbaImage};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::{Block, Widget},
+};
+use ratatui_image::picker::{Picker, ProtocolType};
+use ratatui_image::{Image, Resize};
+
+/// Pixel size of a cell assumed when the picker is guessed from the environment
+const FONT_SIZE: (u16, u16) = (12, 24);
+
+/// Guess a picker for the running terminal from its environment, without
+/// querying it, or `None` if it has no graphics protocol (half blocks would
+/// look worse than the Unicode widget)
+fn detect_picker() -> Option<Picker> {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    let mut picker = Picker::from_fontsize(FONT_SIZE);
+    if !var("KITTY_WINDOW_ID").is_empty()
+        || var("TERM").contains("kitty")
+        || matches!(var("TERM_PROGRAM").as_str(), "ghostty" | "WezTerm")
+    {
+        picker.set_protocol_type(ProtocolType::Kitty);
+    }
+    (picker.protocol_type() != ProtocolType::Halfblocks).then_some(picker)
+}
+
+/// A math widget that displays the equation as an image, falling back to the
+/// Unicode [`MathWidget`] when no graphics protocol is available or the image
+/// doesn't fit the area
+#[derive(Clone)]
+pub struct ImageMathWidget<'a> {
+    latex: &'a str,
+    style: Style,
+    block: Option<Block<'a>>,
+    options: RendererOptions,
+    picker: Option<Picker>,
+}
+
+impl<'a> ImageMathWidget<'a> {
+    /// Create a new ImageMathWidget from a LaTeX expression, with a picker
+    /// guessed from the environment
+    pub fn new(latex: &'a str) -> Self {
+        Self {
+            latex,
+            style: Style::default(),
+            block: None,
+            options: RendererOptions::default(),
+            picker: detect_picker(),
+        }
+    }
+
+    /// Set the style; its foreground color is the ink color of the image
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Wrap the widget in a block
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Set the layout options (display or text style, spacing, brackets,
+    /// accents, ...)
+    pub fn options(mut self, options: RendererOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Draw with this picker instead of the guessed one, or with the Unicode
+    /// widget if `None`. [`Picker::from_query_stdio`], called once at startup,
+    /// finds the terminal's actual protocol and cell size.
+    pub fn picker(mut self, picker: Option<Picker>) -> Self {
+        self.picker = picker;
+        self
+    }
+
+    fn fallback(&self) -> MathWidget<'a> {
+        let widget = MathWidget::new(self.latex)
+            .style(self.style)
+            .options(self.options.clone());
+        match &self.block {
+            Some(block) => widget.block(block.clone()),
+            None => widget,
+        }
+    }
+
+    /// The equation as an image of `mbox`'s cells at the picker's cell size
+    fn image(&self, mbox: &MathBox, picker: &Picker) -> Option<DynamicImage> {
+        let (width, height) = picker.font_size();
+        let raster = rasterize(
+            mbox,
+            self.style.fg.unwrap_or(Color::White),
+            &CellSize::font(usize::from(width), usize::from(height)),
+        );
+        let rgba: Vec<u8> = raster
+            .pixels
+            .iter()
+            .flat_map(|pixel| match pixel {
+                Some(index) => {
+                    let (r, g, b) = rgb(raster.palette[*index]);
+                    [r, g, b, 255]
+                }
+                None => [0, 0, 0, 0],
+            })
+            .collect();
+        let image = RgbaImage::from_raw(raster.width as u32, raster.height as u32, rgba)?;
+        Some(DynamicImage::ImageRgba8(image))
+    }
+}
+
+impl Widget for ImageMathWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let inner = self.block.as_ref().map_or(area, |block| block.inner(area));
+        let rendered = MathRenderer::with_options(self.options.clone()).render_to_box(self.latex);
+        let (Some(picker), Ok(mbox)) = (&self.picker, rendered) else {
+            return self.fallback().render(area, buf);
+        };
+        let mbox = mbox.place_label(0);
+        let (columns, rows) = (mbox.width as u16, mbox.height as u16);
+        if columns == 0 || columns > inner.width || rows > inner.height {
+            return self.fallback().render(area, buf);
+        }
+
+        let cells = Rect::new(inner.x, inner.y, columns, rows);
+        let protocol = self
+            .image(&mbox, picker)
+            .and_then(|image| picker.new_protocol(image, cells, Resize::Fit(None)).ok());
+        let Some(protocol) = protocol else {
+            return self.fallback().render(area, buf);
+        };
+        if let Some(block) = &self.block {
+            block.clone().render(area, buf);
+        }
+        Image::new(&protocol).render(cells, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_widget() {
+        let area = Rect::new(0, 0, 10, 3);
+        let mut picker = Picker::from_fontsize((10, 20));
+        picker.set_protocol_type(ProtocolType::Kitty);
+        let mut buf = Buffer::empty(area);
+        ImageMathWidget::new("x")
+            .picker(Some(picker))
+            .render(area, &mut buf);
+        // One cell, rasterized at the picker's cell size
+        assert!(buf[(0, 0)].symbol().contains("s=10,v=20,"));
+
+        // Without a picker the Unicode widget draws instead, with the options
+        let mut buf = Buffer::empty(area);
+        ImageMathWidget::new("x^2")
+            .picker(None)
+            .options(RendererOptions {
+                unicode_scripts: false,
+                ..RendererOptions::default()
+            })
+            .render(area, &mut buf);
+        assert_eq!(buf[(0, 1)].symbol(), "x");
+    }
+}
@@ -0,0 +1,1202 @@
+//! LaTeX front-end: extends latex2mathml with constructs it does not support
+//!
+//! Commands and environments latex2mathml does not know are converted to
+//! MathML fragments here. Each fragment is replaced in the LaTeX source by a
+//! placeholder character from the Unicode private use area, which latex2mathml
+//! passes through as a plain `<mi>`; the fragment is spliced back in afterwards.
+
+use crate::chem::ce_to_latex;
+use crate::renderer::RenderError;
+use crate::unicode_maps::negate_relation;
+use crate::units::{number_to_latex, unit_to_latex};
+use latex2mathml::DisplayStyle;
+
+/// First code point used for fragment placeholders (Unicode private use area)
+const SLOT_BASE: u32 = 0xE000;
+/// Number of available placeholder code points, up to [`TOKEN_MARK`]
+const SLOT_COUNT: u32 = 0x18FF;
+/// The last private use code point, kept out of the slots for marking a
+/// token in the source to find where it lands in the MathML
+pub(crate) const TOKEN_MARK: char = '\u{F8FF}';
+
+/// Convert LaTeX to a complete MathML document
+pub(crate) fn to_mathml(latex: &str, display: DisplayStyle) -> Result<String, RenderError> {
+    let (latex, display) = strip_delimiters(latex).unwrap_or((latex, display));
+    let mut converter = Converter::default();
+    // Top-level `\\` breaks stack the lines centered, as in `gather`
+    let rows = split_rows(latex);
+    let mut inner = if rows.len() > 1 {
+        converter.table(&rows, "")?
+    } else {
+        converter.convert(latex)?
+    };
+    if let Some(tag) = converter.tag.take() {
+        inner = format!(
+            "<mtable><mlabeledtr><mtd><mtext>{}</mtext></mtd><mtd>{}</mtd></mlabeledtr></mtable>",
+            escape_xml(&tag),
+            inner
+        );
+    }
+    Ok(format!(
+        r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="{}">{}</math>"#,
+        display, inner
+    ))
+}
+
+#[derive(Default)]
+struct Converter {
+    /// MathML fragments waiting to be spliced in, indexed by placeholder
+    slots: Vec<String>,
+    /// Placeholders standing in for a script of a subscript-superscript pair
+    script_slots: Vec<char>,
+    /// Equation label from `\tag`, already parenthesized unless `\tag*` was used
+    tag: Option<String>,
+}
+
+impl Converter {
+    /// Convert LaTeX to MathML content (without the `<math>` wrapper)
+    fn convert(&mut self, latex: &str) -> Result<String, RenderError> {
+        let rewritten = self.rewrite(latex)?;
+        let mathml = latex2mathml::latex_to_mathml(&rewritten, DisplayStyle::Inline)
+            .map_err(|e| RenderError::LatexConversion(e.to_string()))?;
+        // latex2mathml emits relational `<` and `>` unescaped, which is not valid XML,
+        // and angle brackets as HTML entities, which XML doesn't define
+        let mathml = mathml
+            .replace("<mo><</mo>", "<mo>&lt;</mo>")
+            .replace("<mo>></mo>", "<mo>&gt;</mo>")
+            .replace("&lang;", "⟨")
+            .replace("&rang;", "⟩");
+        let inner = self.join_scripts(strip_math_wrapper(&mathml));
+        Ok(self.splice(&inner))
+    }
+
+    /// Handle the scripts following a base at `pos`: a subscript, a superscript and
+    /// a run of primes, in any order. latex2mathml nests a subscript-superscript
+    /// pair (`x_a^b` comes out as `x_{a^b}`) and only understands a single prime,
+    /// so those cases are emitted as `_{..}^{..}` placeholders that `join_scripts`
+    /// turns back into an msubsup. Lone scripts are left to latex2mathml.
+    fn scripts(&mut self, latex: &str, pos: usize) -> Result<Option<(String, usize)>, RenderError> {
+        let mut sub = None;
+        let mut sup = None;
+        let mut primes = 0;
+        let mut end = pos;
+
+        loop {
+            let rest = &latex[end..];
+            if rest.starts_with('\'') && primes == 0 && sup.is_none() {
+                primes = rest.chars().take_while(|&c| c == '\'').count();
+                end += primes;
+                continue;
+            }
+            let slot = match rest.chars().next() {
+                Some('_') if sub.is_none() => &mut sub,
+                Some('^') if sup.is_none() => &mut sup,
+                _ => break,
+            };
+            match read_argument(latex, end + 1) {
+                // A command taking its own arguments can't be split off reliably
+                Some((arg, after))
+                    if !(arg.starts_with('\\') && latex[after..].starts_with('{')) =>
+                {
+                    *slot = Some(arg);
+                    end = after;
+                }
+                _ => break,
+            }
+        }
+
+        if primes == 0 && (sub.is_none() || sup.is_none()) {
+            return Ok(None);
+        }
+
+        let mut sup_mathml = String::new();
+        if primes > 0 {
+            sup_mathml = format!("<mo>{}</mo>", "′".repeat(primes));
+        }
+        if let Some(sup) = sup {
+            sup_mathml.push_str(&self.convert(sup)?);
+        }
+        let sup = self.slot(format!("<mrow>{}</mrow>", sup_mathml))?;
+        let Some(sub) = sub else {
+            return Ok(Some((format!("^{}", sup), end)));
+        };
+        let sub_mathml = self.convert(sub)?;
+        let sub = self.slot(format!("<mrow>{}</mrow>", sub_mathml))?;
+        for placeholder in [&sub, &sup] {
+            self.script_slots.extend(placeholder.chars().nth(1));
+        }
+        Ok(Some((format!("_{}^{}", sub, sup), end)))
+    }
+
+    /// Turn the `<msub>base<msup>sub sup</msup></msub>` latex2mathml produces for a
+    /// placeholder script pair back into `<msubsup>base sub sup</msubsup>`
+    fn join_scripts(&self, mathml: &str) -> String {
+        let mut mathml = mathml.to_string();
+        loop {
+            let wrapped = format!("<r>{}</r>", mathml);
+            let Ok(doc) = roxmltree::Document::parse(&wrapped) else {
+                return mathml;
+            };
+            let is_script = |node: &roxmltree::Node| {
+                node.tag_name().name() == "mi"
+                    && node
+                        .text()
+                        .and_then(|t| t.chars().next())
+                        .is_some_and(|c| self.script_slots.contains(&c))
+            };
+            // The last match can't contain another one
+            let mut pairs = doc
+                .descendants()
+                .filter(|n| n.tag_name().name() == "msub")
+                .filter_map(|msub| {
+                    let [base, msup] = msub
+                        .children()
+                        .filter(|n| n.is_element())
+                        .collect::<Vec<_>>()[..]
+                    else {
+                        return None;
+                    };
+                    let [sub, sup] = msup
+                        .children()
+                        .filter(|n| n.is_element())
+                        .collect::<Vec<_>>()[..]
+                    else {
+                        return None;
+                    };
+                    (msup.tag_name().name() == "msup" && is_script(&sub) && is_script(&sup))
+                        .then(|| (msub.range(), [base.range(), sub.range(), sup.range()]))
+                });
+            let Some((range, parts)) = pairs.next_back() else {
+                return mathml;
+            };
+            let joined = format!(
+                "<msubsup>{}</msubsup>",
+                parts.map(|part| &wrapped[part]).concat()
+            );
+            mathml = format!(
+                "{}{}{}",
+                &wrapped[3..range.start],
+                joined,
+                &wrapped[range.end..wrapped.len() - 4]
+            );
+        }
+    }
+
+    /// Register a MathML fragment and return the LaTeX placeholder standing in for it
+    fn slot(&mut self, mathml: String) -> Result<String, RenderError> {
+        let index = self.slots.len() as u32;
+        if index >= SLOT_COUNT {
+            return Err(RenderError::LatexConversion(
+                "expression has too many nested constructs".to_string(),
+            ));
+        }
+        self.slots.push(mathml);
+        let ch = char::from_u32(SLOT_BASE + index).unwrap_or('\u{E000}');
+        Ok(format!("{{{}}}", ch))
+    }
+
+    /// Rewrite the LaTeX source, replacing unsupported constructs with placeholders
+    fn rewrite(&mut self, latex: &str) -> Result<String, RenderError> {
+        let mut out = String::with_capacity(latex.len());
+        let mut pos = 0;
+
+        while pos < latex.len() {
+            let rest = &latex[pos..];
+            if !rest.starts_with('\\') {
+                let ch = rest.chars().next().unwrap_or(' ');
+                if matches!(ch, '_' | '^' | '\'') {
+                    if let Some((scripts, end)) = self.scripts(latex, pos)? {
+                        out.push_str(&scripts);
+                        pos = end;
+                        continue;
+                    }
+                }
+                if ch == '~' {
+                    // Non-breaking space, which latex2mathml shows as a literal tilde
+                    out.push_str(&self.slot(r#"<mspace width="0.333em"/>"#.to_string())?);
+                    pos += 1;
+                    continue;
+                }
+                out.push(ch);
+                pos += ch.len_utf8();
+                continue;
+            }
+
+            let (name, after) = read_command(latex, pos);
+            if let Some((replacement, end)) = self.command(name, latex, after)? {
+                out.push_str(&replacement);
+                pos = end;
+                continue;
+            }
+            if name == "begin" {
+                if let Some((env, body_start)) = read_group(latex, after) {
+                    if let Some((body, end)) = find_environment_end(latex, body_start, env) {
+                        if let Some(replacement) = self.environment(env, body)? {
+                            out.push_str(&replacement);
+                            pos = end;
+                            continue;
+                        }
+                    }
+                }
+            }
+
+            out.push_str(&latex[pos..after]);
+            pos = after;
+        }
+
+        Ok(out)
+    }
+
+    /// Handle a command whose arguments start at `pos`, returning its replacement
+    /// and the byte offset after its arguments if it is one we support
+    fn command(
+        &mut self,
+        name: &str,
+        latex: &str,
+        pos: usize,
+    ) -> Result<Option<(String, usize)>, RenderError> {
+        match name {
+            "text" | "textrm" | "textup" | "textnormal" | "mbox" => {
+                let Some((text, end)) = read_group(latex, pos) else {
+                    return Ok(None);
+                };
+                let mtext = format!("<mtext>{}</mtext>", escape_xml(text));
+                Ok(Some((self.slot(mtext)?, end)))
+            }
+            "mathrm" | "mathit" | "mathbf" | "boldsymbol" | "bm" | "mathsf" | "mathtt"
+            | "mathbb" | "mathcal" | "mathscr" | "mathfrak" => {
+                let Some((arg, end)) = read_argument(latex, pos) else {
+                    return Ok(None);
+                };
+                let variant = match name {
+                    "mathrm" => "normal",
+                    "mathit" => "italic",
+                    "mathbf" => "bold",
+                    "boldsymbol" | "bm" => "bold-italic",
+                    "mathsf" => "sans-serif",
+                    "mathtt" => "monospace",
+                    "mathbb" => "double-struck",
+                    "mathcal" | "mathscr" => "script",
+                    _ => "fraktur",
+                };
+                let mstyle = format!(
+                    r#"<mstyle mathvariant="{}">{}</mstyle>"#,
+                    variant,
+                    self.convert(arg)?
+                );
+                Ok(Some((self.slot(mstyle)?, end)))
+            }
+            "not" => {
+                let Some((arg, end)) = read_argument(latex, pos) else {
+                    return Ok(None);
+                };
+                // A single symbol is negated in place; anything longer is
+                // struck through as a whole
+                let mathml = self.convert(arg)?;
+                let negated = match single_token(&mathml) {
+                    Some(symbol) => {
+                        let symbol = symbol
+                            .replace("&lt;", "<")
+                            .replace("&gt;", ">")
+                            .replace("&amp;", "&");
+                        format!("<mo>{}</mo>", escape_xml(&negate_relation(&symbol)))
+                    }
+                    None => format!(
+                        r#"<menclose notation="updiagonalstrike"><mrow>{}</mrow></menclose>"#,
+                        mathml
+                    ),
+                };
+                Ok(Some((self.slot(negated)?, end)))
+            }
+            "bra" | "ket" | "braket" | "ketbra" => {
+                let Some((first, mut end)) = read_group(latex, pos) else {
+                    return Ok(None);
+                };
+                // `\braket{a}{b}` and `\ketbra{a}{b}` take a second argument
+                let mut parts = split_bars(first);
+                if matches!(name, "braket" | "ketbra") {
+                    if let Some((second, after)) = read_group(latex, end) {
+                        parts = vec![first, second];
+                        end = after;
+                    }
+                }
+                let (open, close) = match name {
+                    "bra" => ("⟨", "|"),
+                    "ket" => ("|", "⟩"),
+                    "braket" => ("⟨", "⟩"),
+                    _ => ("|", "|"),
+                };
+                // Inner bars stretch along with the outer delimiters
+                let middle: &[&str] = if name == "ketbra" {
+                    &["⟩", "⟨"]
+                } else {
+                    &["|"]
+                };
+                let mut mathml =
+                    format!(r#"<mrow><mo stretchy="true" form="prefix">{}</mo>"#, open);
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        for bar in middle {
+                            mathml.push_str(&format!(
+                                r#"<mo stretchy="true" form="infix">{}</mo>"#,
+                                bar
+                            ));
+                        }
+                    }
+                    mathml.push_str(&format!("<mrow>{}</mrow>", self.convert(part)?));
+                }
+                mathml.push_str(&format!(
+                    r#"<mo stretchy="true" form="postfix">{}</mo></mrow>"#,
+                    close
+                ));
+                Ok(Some((self.slot(mathml)?, end)))
+            }
+            "ce" => {
+                let Some((formula, end)) = read_group(latex, pos) else {
+                    return Ok(None);
+                };
+                let mathml = format!("<mrow>{}</mrow>", self.convert(&ce_to_latex(formula))?);
+                Ok(Some((self.slot(mathml)?, end)))
+            }
+            "SI" | "qty" | "si" | "unit" | "num" => {
+                let Some((first, mut end)) = read_group(latex, pos) else {
+                    return Ok(None);
+                };
+                let quantity = match name {
+                    "SI" | "qty" => {
+                        let Some((unit, after)) = read_group(latex, end) else {
+                            return Ok(None);
+                        };
+                        end = after;
+                        format!(r"{}\,{}", number_to_latex(first), unit_to_latex(unit))
+                    }
+                    "num" => number_to_latex(first),
+                    _ => unit_to_latex(first),
+                };
+                let mathml = format!("<mrow>{}</mrow>", self.convert(&quantity)?);
+                Ok(Some((self.slot(mathml)?, end)))
+            }
+            "prime" => Ok(Some((self.slot("<mo>′</mo>".to_string())?, pos))),
+            "hspace" => {
+                let starred = latex[pos..].starts_with('*');
+                let Some((width, end)) = read_group(latex, pos + usize::from(starred)) else {
+                    return Ok(None);
+                };
+                let mspace = format!(r#"<mspace width="{}"/>"#, escape_xml(width.trim()));
+                Ok(Some((self.slot(mspace)?, end)))
+            }
+            " " | "enspace" | "thinspace" | "medspace" | "thickspace" | "negthinspace"
+            | "negmedspace" | "negthickspace" => {
+                let width = match name {
+                    " " => "0.333em",
+                    "enspace" => "0.5em",
+                    "thinspace" => "0.167em",
+                    "medspace" => "0.222em",
+                    "thickspace" => "0.278em",
+                    "negthinspace" => "-0.167em",
+                    "negmedspace" => "-0.222em",
+                    _ => "-0.278em",
+                };
+                let mspace = format!(r#"<mspace width="{}"/>"#, width);
+                Ok(Some((self.slot(mspace)?, pos)))
+            }
+            "pmod" | "mod" | "pod" => {
+                let Some((arg, end)) = read_argument(latex, pos) else {
+                    return Ok(None);
+                };
+                let arg = self.convert(arg)?;
+                let space = r#"<mspace width="0.444em"/>"#;
+                let mod_word = r#"<mi mathvariant="normal">mod</mi><mspace width="0.333em"/>"#;
+                let mathml = match name {
+                    "pmod" => format!(
+                        "<mrow>{}<mo>(</mo>{}{}<mo>)</mo></mrow>",
+                        space, mod_word, arg
+                    ),
+                    "mod" => format!("<mrow>{}{}{}</mrow>", space, mod_word, arg),
+                    _ => format!("<mrow>{}<mo>(</mo>{}<mo>)</mo></mrow>", space, arg),
+                };
+                Ok(Some((self.slot(mathml)?, end)))
+            }
+            "bmod" => {
+                let mathml = r#"<mrow><mspace width="0.278em"/><mi mathvariant="normal">mod</mi><mspace width="0.278em"/></mrow>"#;
+                Ok(Some((self.slot(mathml.to_string())?, pos)))
+            }
+            "substack" => {
+                let Some((body, end)) = read_group(latex, pos) else {
+                    return Ok(None);
+                };
+                let rows: Vec<Vec<&str>> = split_rows(body)
+                    .into_iter()
+                    .map(|row| vec![row.first().copied().unwrap_or("")])
+                    .collect();
+                let table = self.table(&rows, "")?;
+                Ok(Some((self.slot(table)?, end)))
+            }
+            "tag" => {
+                let starred = latex[pos..].starts_with('*');
+                let Some((text, end)) = read_group(latex, pos + usize::from(starred)) else {
+                    return Ok(None);
+                };
+                self.tag = Some(if starred {
+                    text.to_string()
+                } else {
+                    format!("({})", text)
+                });
+                Ok(Some((String::new(), end)))
+            }
+            "dfrac" | "tfrac" => {
+                let Some((num, after_num)) = read_argument(latex, pos) else {
+                    return Ok(None);
+                };
+                let Some((den, end)) = read_argument(latex, after_num) else {
+                    return Ok(None);
+                };
+                let mstyle = format!(
+                    r#"<mstyle displaystyle="{}"><mfrac><mrow>{}</mrow><mrow>{}</mrow></mfrac></mstyle>"#,
+                    name == "dfrac",
+                    self.convert(num)?,
+                    self.convert(den)?
+                );
+                Ok(Some((self.slot(mstyle)?, end)))
+            }
+            "boxed" | "cancel" | "bcancel" | "xcancel" => {
+                let Some((arg, end)) = read_argument(latex, pos) else {
+                    return Ok(None);
+                };
+                let notation = match name {
+                    "boxed" => "box",
+                    "cancel" => "updiagonalstrike",
+                    "bcancel" => "downdiagonalstrike",
+                    _ => "updiagonalstrike downdiagonalstrike",
+                };
+                let menclose = format!(
+                    r#"<menclose notation="{}">{}</menclose>"#,
+                    notation,
+                    self.convert(arg)?
+                );
+                Ok(Some((self.slot(menclose)?, end)))
+            }
+            "xrightarrow" | "xleftarrow" | "xleftrightarrow" | "xRightarrow" | "xLeftarrow"
+            | "xLeftrightarrow" | "xmapsto" => {
+                let (below, pos) = match read_optional(latex, pos) {
+                    Some((below, end)) => (Some(below), end),
+                    None => (None, pos),
+                };
+                let Some((above, end)) = read_group(latex, pos) else {
+                    return Ok(None);
+                };
+                let arrow = match name {
+                    "xrightarrow" => "→",
+                    "xleftarrow" => "←",
+                    "xleftrightarrow" => "↔",
+                    "xRightarrow" => "⇒",
+                    "xLeftarrow" => "⇐",
+                    "xLeftrightarrow" => "⇔",
+                    _ => "↦",
+                };
+                let mo = format!(r#"<mo stretchy="true">{}</mo>"#, arrow);
+                let above = format!("<mrow>{}</mrow>", self.convert(above)?);
+                let mathml = match below {
+                    Some(below) => format!(
+                        "<munderover>{}<mrow>{}</mrow>{}</munderover>",
+                        mo,
+                        self.convert(below)?,
+                        above
+                    ),
+                    None => format!("<mover>{}{}</mover>", mo, above),
+                };
+                Ok(Some((self.slot(mathml)?, end)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Handle an environment, returning its replacement if it is one we support
+    fn environment(&mut self, env: &str, body: &str) -> Result<Option<String>, RenderError> {
+        match env {
+            "align" | "align*" | "aligned" | "split" => {
+                let rows = split_rows(body);
+                let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+                // Alternate right/left alignment so `&` pairs line up on the relation
+                let align: Vec<&str> = (0..columns)
+                    .map(|j| if j % 2 == 0 { "right" } else { "left" })
+                    .collect();
+                let spacing: Vec<&str> = (1..columns)
+                    .map(|j| if j % 2 == 1 { "0em" } else { "1em" })
+                    .collect();
+                let attrs = format!(
+                    r#" columnalign="{}" columnspacing="{}""#,
+                    align.join(" "),
+                    if spacing.is_empty() {
+                        "0em".to_string()
+                    } else {
+                        spacing.join(" ")
+                    }
+                );
+                let table = self.table(&rows, &attrs)?;
+                self.slot(table).map(Some)
+            }
+            "gather" | "gather*" | "gathered" => {
+                let table = self.table(&split_rows(body), "")?;
+                self.slot(table).map(Some)
+            }
+            "array" => {
+                let Some((spec, body_start)) = read_group(body, 0) else {
+                    return Ok(None);
+                };
+                let spec = parse_column_spec(spec);
+                let (rows, hlines) = take_hlines(split_rows(&body[body_start..]));
+                let inner_hlines = &hlines[1..hlines.len() - 1];
+                // A table ruled on all four sides is framed rather than fenced
+                let framed =
+                    spec.left_rule && spec.right_rule && hlines[0] && hlines[hlines.len() - 1];
+                let mut attrs = format!(
+                    r#" columnalign="{}" columnlines="{}" columnspacing="0.5em""#,
+                    spec.aligns.join(" "),
+                    if spec.lines.is_empty() {
+                        "none".to_string()
+                    } else {
+                        spec.lines.join(" ")
+                    }
+                );
+                if inner_hlines.iter().any(|&line| line) {
+                    let lines: Vec<_> = inner_hlines
+                        .iter()
+                        .map(|&line| if line { "solid" } else { "none" })
+                        .collect();
+                    attrs.push_str(&format!(r#" rowlines="{}""#, lines.join(" ")));
+                }
+                if framed {
+                    attrs.push_str(r#" frame="solid""#);
+                }
+                let table = self.table(&rows, &attrs)?;
+                // Outer rules are drawn as stretchy bars around the table
+                if framed || (!spec.left_rule && !spec.right_rule) {
+                    return self.slot(table).map(Some);
+                }
+                let bar = |draw: bool, form: &str| {
+                    format!(
+                        r#"<mo stretchy="true" form="{}">{}</mo>"#,
+                        form,
+                        if draw { "|" } else { "" }
+                    )
+                };
+                let mrow = format!(
+                    r#"<mrow>{}{}{}</mrow>"#,
+                    bar(spec.left_rule, "prefix"),
+                    table,
+                    bar(spec.right_rule, "postfix")
+                );
+                self.slot(mrow).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Build an `<mtable>` from rows of LaTeX cells
+    fn table(&mut self, rows: &[Vec<&str>], attrs: &str) -> Result<String, RenderError> {
+        let mut mathml = format!("<mtable{}>", attrs);
+        for row in rows {
+            mathml.push_str("<mtr>");
+            for cell in row {
+                mathml.push_str("<mtd>");
+                mathml.push_str(&self.convert(cell)?);
+                mathml.push_str("</mtd>");
+            }
+            mathml.push_str("</mtr>");
+        }
+        mathml.push_str("</mtable>");
+        Ok(mathml)
+    }
+
+    /// Replace placeholder `<mi>` elements with their MathML fragments
+    fn splice(&self, mathml: &str) -> String {
+        let mut out = String::with_capacity(mathml.len());
+        let mut rest = mathml;
+
+        while let Some((index, ch)) = rest
+            .char_indices()
+            .find(|(_, c)| self.slot_index(*c).is_some())
+        {
+            let slot = self.slot_index(ch).unwrap_or(0);
+            let open = rest[..index].rfind("<mi");
+            let close = rest[index..]
+                .find("</mi>")
+                .map(|i| index + i + "</mi>".len());
+            match (open, close) {
+                (Some(open), Some(close)) => {
+                    out.push_str(&rest[..open]);
+                    out.push_str(&self.slots[slot]);
+                    rest = &rest[close..];
+                }
+                _ => {
+                    out.push_str(&rest[..index + ch.len_utf8()]);
+                    rest = &rest[index + ch.len_utf8()..];
+                }
+            }
+        }
+
+        out.push_str(rest);
+        out
+    }
+
+    fn slot_index(&self, ch: char) -> Option<usize> {
+        let code = ch as u32;
+        if code >= SLOT_BASE && ((code - SLOT_BASE) as usize) < self.slots.len() {
+            Some((code - SLOT_BASE) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Best guess at the source span (byte offset and length) an error message
+/// from the LaTeX conversion is about: the end of the input when it ran out,
+/// the unknown environment's `\begin`, or the first occurrence of the
+/// unexpected token (the first unmatched `}` for a stray brace)
+pub(crate) fn error_span(latex: &str, message: &str) -> Option<(usize, usize)> {
+    if message.contains("\"EOF\" is found") {
+        return Some((latex.len(), 0));
+    }
+    if let Some(rest) = message.split("unknown environment \"").nth(1) {
+        let begin = format!("\\begin{{{}}}", rest.split('"').next()?);
+        return latex.find(&begin).map(|pos| (pos, begin.len()));
+    }
+    let token = message
+        .split("but the token \"")
+        .nth(1)?
+        .rsplit_once("\" is found")?
+        .0;
+    if token == "RBrace" {
+        let mut depth = 0usize;
+        for (pos, ch) in latex.char_indices() {
+            match ch {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Some((pos, 1)),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        return None;
+    }
+    let text = match token {
+        "LBrace" => "{".to_string(),
+        "Left" => "\\left".to_string(),
+        "Right" => "\\right".to_string(),
+        "Middle" => "\\middle".to_string(),
+        "Begin" => "\\begin".to_string(),
+        "End" => "\\end".to_string(),
+        "Frac" => "\\frac".to_string(),
+        "Sqrt" => "\\sqrt".to_string(),
+        "Ampersand" => "&".to_string(),
+        "Underscore" => "_".to_string(),
+        "Circumflex" => "^".to_string(),
+        _ => {
+            let (kind, arg) = token.strip_suffix("\")")?.split_once("(\"")?;
+            match kind {
+                "Paren" => arg.to_string(),
+                "Command" => format!("\\{}", arg),
+                _ => return None,
+            }
+        }
+    };
+    latex.find(&text).map(|pos| (pos, text.len()))
+}
+
+/// Byte range of the atom starting at `pos`: a whole command (`\alpha`,
+/// `\,`) or a single character. The cursor moves and deletes by atoms.
+pub(crate) fn atom_at(source: &str, pos: usize) -> Option<(usize, usize)> {
+    let rest = source.get(pos..)?;
+    let mut chars = rest.char_indices();
+    let (_, first) = chars.next()?;
+    let len = if first == '\\' {
+        match chars.next() {
+            Some((_, c)) if c.is_ascii_alphabetic() => {
+                1 + rest[1..]
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(rest.len() - 1)
+            }
+            Some((_, c)) => 1 + c.len_utf8(),
+            None => 1,
+        }
+    } else {
+        first.len_utf8()
+    };
+    Some((pos, pos + len))
+}
+
+/// Byte ranges of every atom in `source`, in order
+pub(crate) fn atoms(source: &str) -> Vec<(usize, usize)> {
+    let mut atoms = Vec::new();
+    let mut pos = 0;
+    while let Some(atom) = atom_at(source, pos) {
+        atoms.push(atom);
+        pos = atom.1;
+    }
+    atoms
+}
+
+/// Byte ranges of the tokens in `source` that render as symbols: atoms,
+/// with runs of digits merged into one number and text commands
+/// (`\text{ab}`) taken whole with their argument, leaving out braces,
+/// scripts, alignment marks and spaces
+pub(crate) fn tokens(source: &str) -> Vec<(usize, usize)> {
+    const TEXT_COMMANDS: [&str; 8] = [
+        "text",
+        "textrm",
+        "textit",
+        "textbf",
+        "textsf",
+        "texttt",
+        "mbox",
+        "operatorname",
+    ];
+    let is_number = |text: &str| text.chars().all(|c| c.is_ascii_digit() || c == '.');
+    let mut tokens: Vec<(usize, usize)> = Vec::new();
+    let mut skip_to = 0;
+    for (start, end) in atoms(source) {
+        if start < skip_to {
+            continue;
+        }
+        let text = &source[start..end];
+        let is_text_command = text
+            .strip_prefix('\\')
+            .is_some_and(|name| TEXT_COMMANDS.contains(&name));
+        if let Some((_, after)) = read_group(source, end).filter(|_| is_text_command) {
+            tokens.push((start, after));
+            skip_to = after;
+            continue;
+        }
+        if text
+            .chars()
+            .all(|c| c.is_whitespace() || "{}^_&".contains(c))
+        {
+            continue;
+        }
+        match tokens.last_mut() {
+            Some(last)
+                if last.1 == start && is_number(text) && is_number(&source[last.0..last.1]) =>
+            {
+                last.1 = end
+            }
+            _ => tokens.push((start, end)),
+        }
+    }
+    tokens
+}
+
+/// The text of `mathml` if it is a single `<mo>` or `<mi>` element
+fn single_token(mathml: &str) -> Option<&str> {
+    let mathml = mathml.trim();
+    ["mo", "mi"].into_iter().find_map(|tag| {
+        let rest = mathml.strip_prefix('<')?.strip_prefix(tag)?;
+        let (_, rest) = rest.split_once('>')?;
+        let text = rest.strip_suffix(&format!("</{}>", tag))?;
+        (!text.contains('<')).then_some(text)
+    })
+}
+
+/// Escape text for inclusion in MathML character data
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Strip math delimiters wrapping the whole input, as pasted from a document:
+/// `$...$` and `\(...\)` are inline, `$$...$$` and `\[...\]` display
+fn strip_delimiters(latex: &str) -> Option<(&str, DisplayStyle)> {
+    let trimmed = latex.trim();
+    let delimiters = [
+        ("$$", "$$", DisplayStyle::Block),
+        ("\\[", "\\]", DisplayStyle::Block),
+        ("\\(", "\\)", DisplayStyle::Inline),
+        ("$", "$", DisplayStyle::Inline),
+    ];
+    delimiters.into_iter().find_map(|(open, close, display)| {
+        let inner = trimmed.strip_prefix(open)?.strip_suffix(close)?;
+        // `$a$ and $b$` is two formulas, and `$5\$` ends in an escaped dollar
+        let dollars = open.starts_with('$');
+        let escaped_close = inner.ends_with('\\') && !inner.ends_with("\\\\");
+        (!dollars || !(inner.contains('$') || escaped_close)).then_some((inner, display))
+    })
+}
+
+/// Strip the `<math ...>` wrapper latex2mathml puts around its output
+fn strip_math_wrapper(mathml: &str) -> &str {
+    let start = mathml.find('>').map(|i| i + 1).unwrap_or(0);
+    let end = mathml.rfind("</math>").unwrap_or(mathml.len());
+    &mathml[start..end.max(start)]
+}
+
+/// Read a command name starting at the backslash at `pos`.
+/// Returns the name and the byte offset just after it.
+pub(crate) fn read_command(latex: &str, pos: usize) -> (&str, usize) {
+    let start = pos + 1;
+    let rest = &latex[start..];
+    let letters = rest
+        .char_indices()
+        .find(|(_, c)| !c.is_ascii_alphabetic())
+        .map(|(i, _)| i)
+        .unwrap_or(rest.len());
+    if letters > 0 {
+        return (&latex[start..start + letters], start + letters);
+    }
+    match rest.chars().next() {
+        Some(c) => (&latex[start..start + c.len_utf8()], start + c.len_utf8()),
+        None => ("", start),
+    }
+}
+
+/// Read a `{...}` group starting at `pos` (after optional whitespace).
+/// Returns the group contents and the byte offset just after the closing brace.
+pub(crate) fn read_group(latex: &str, pos: usize) -> Option<(&str, usize)> {
+    let trimmed = latex[pos..].trim_start();
+    let start = latex.len() - trimmed.len();
+    if !trimmed.starts_with('{') {
+        return None;
+    }
+
+    let mut depth = 0usize;
+    let mut escaped = false;
+    for (i, c) in trimmed.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((&latex[start + 1..start + i], start + i + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Read an optional `[...]` argument starting at `pos` (after optional whitespace).
+/// Returns the contents and the byte offset just after the closing bracket.
+pub(crate) fn read_optional(latex: &str, pos: usize) -> Option<(&str, usize)> {
+    let trimmed = latex[pos..].trim_start();
+    let start = latex.len() - trimmed.len();
+    if !trimmed.starts_with('[') {
+        return None;
+    }
+    let mut depth = 0usize;
+    for (i, c) in trimmed.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ']' if depth == 0 => return Some((&latex[start + 1..start + i], start + i + 1)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Read a command argument: a `{...}` group, a single command, or a single character.
+/// Returns the argument and the byte offset just after it.
+pub(crate) fn read_argument(latex: &str, pos: usize) -> Option<(&str, usize)> {
+    if let Some(group) = read_group(latex, pos) {
+        return Some(group);
+    }
+    let trimmed = latex[pos..].trim_start();
+    let start = latex.len() - trimmed.len();
+    if trimmed.starts_with('\\') {
+        let (_, end) = read_command(latex, start);
+        return Some((&latex[start..end], end));
+    }
+    let ch = trimmed.chars().next()?;
+    Some((&latex[start..start + ch.len_utf8()], start + ch.len_utf8()))
+}
+
+/// Split at top-level `|` bars (as in `\braket{a|b}`), ignoring bars inside braces
+fn split_bars(body: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            '|' if depth == 0 => {
+                parts.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&body[start..]);
+    parts
+}
+
+/// A parsed `array` column specification such as `{l|cr}`
+struct ColumnSpec {
+    aligns: Vec<&'static str>,
+    /// Rule between each pair of adjacent columns (`solid` or `none`)
+    lines: Vec<&'static str>,
+    left_rule: bool,
+    right_rule: bool,
+}
+
+/// Parse an `array` column spec: `l`/`c`/`r` columns separated by optional `|` rules.
+/// Paragraph columns (`p{..}`, `m{..}`, `b{..}`) are treated as left-aligned and
+/// `@{..}` inserts are skipped.
+fn parse_column_spec(spec: &str) -> ColumnSpec {
+    let mut aligns = Vec::new();
+    let mut lines = Vec::new();
+    let mut left_rule = false;
+    let mut rule = false;
+    let mut pos = 0;
+
+    while let Some(c) = spec[pos..].chars().next() {
+        pos += c.len_utf8();
+        let align = match c {
+            '|' => {
+                rule = true;
+                continue;
+            }
+            'l' => "left",
+            'c' => "center",
+            'r' => "right",
+            'p' | 'm' | 'b' | '@' => {
+                if let Some((_, end)) = read_group(spec, pos) {
+                    pos = end;
+                }
+                if c == '@' {
+                    continue;
+                }
+                "left"
+            }
+            _ => continue,
+        };
+        if aligns.is_empty() {
+            left_rule = rule;
+        } else {
+            lines.push(if rule { "solid" } else { "none" });
+        }
+        aligns.push(align);
+        rule = false;
+    }
+
+    ColumnSpec {
+        aligns,
+        lines,
+        left_rule,
+        right_rule: rule,
+    }
+}
+
+/// Find the matching `\end{env}` for a body starting at `pos`.
+/// Returns the body and the byte offset just after `\end{env}`.
+fn find_environment_end<'a>(latex: &'a str, pos: usize, env: &str) -> Option<(&'a str, usize)> {
+    let begin = format!("\\begin{{{}}}", env);
+    let end = format!("\\end{{{}}}", env);
+    let mut depth = 1usize;
+    let mut i = pos;
+
+    while i < latex.len() {
+        let rest = &latex[i..];
+        if rest.starts_with(&begin) {
+            depth += 1;
+            i += begin.len();
+        } else if rest.starts_with(&end) {
+            depth -= 1;
+            if depth == 0 {
+                return Some((&latex[pos..i], i + end.len()));
+            }
+            i += end.len();
+        } else {
+            i += rest.chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+        }
+    }
+    None
+}
+
+/// Split an environment body into rows (`\\`) and cells (`&`) at the top level,
+/// ignoring separators inside braces and nested environments.
+fn split_rows(body: &str) -> Vec<Vec<&str>> {
+    let mut rows = Vec::new();
+    let mut cells = Vec::new();
+    let mut depth = 0usize;
+    let mut cell_start = 0;
+    let mut i = 0;
+
+    while i < body.len() {
+        let rest = &body[i..];
+        if rest.starts_with("\\begin{") || rest.starts_with("\\end{") {
+            if rest.starts_with("\\begin{") {
+                depth += 1;
+            } else {
+                depth = depth.saturating_sub(1);
+            }
+            // Skip past the environment name so its braces don't affect the depth
+            i += rest.find('}').map_or(rest.len(), |end| end + 1);
+        } else if rest.starts_with("\\\\") && depth == 0 {
+            cells.push(&body[cell_start..i]);
+            rows.push(std::mem::take(&mut cells));
+            i += 2;
+            cell_start = i;
+        } else if let Some(escaped) = rest.strip_prefix('\\') {
+            // Skip escaped characters such as \{ \} \&
+            i += 1 + escaped.chars().next().map(|c| c.len_utf8()).unwrap_or(0);
+        } else if rest.starts_with('{') {
+            depth += 1;
+            i += 1;
+        } else if rest.starts_with('}') {
+            depth = depth.saturating_sub(1);
+            i += 1;
+        } else if rest.starts_with('&') && depth == 0 {
+            cells.push(&body[cell_start..i]);
+            i += 1;
+            cell_start = i;
+        } else {
+            i += rest.chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+        }
+    }
+
+    cells.push(&body[cell_start..]);
+    // A trailing `\\` leaves an empty final row
+    if !(cells.len() == 1 && cells[0].trim().is_empty()) || rows.is_empty() {
+        rows.push(cells);
+    }
+    rows
+}
+
+/// Strip `\hline`s from the start of rows, returning the rows and whether a rule
+/// comes before each row and after the last one
+fn take_hlines(rows: Vec<Vec<&str>>) -> (Vec<Vec<&str>>, Vec<bool>) {
+    let mut hlines = Vec::new();
+    let mut out = Vec::new();
+    for mut row in rows {
+        let mut ruled = false;
+        if let Some(first) = row.first_mut() {
+            while let Some(rest) = first.trim_start().strip_prefix("\\hline") {
+                *first = rest;
+                ruled = true;
+            }
+        }
+        // A row holding only a rule (after the last `\\`) closes the table
+        if row.len() == 1 && row[0].trim().is_empty() && !out.is_empty() {
+            hlines.push(ruled);
+            return (out, hlines);
+        }
+        hlines.push(ruled);
+        out.push(row);
+    }
+    hlines.push(false);
+    (out, hlines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passthrough() {
+        let mathml = to_mathml("x^2", DisplayStyle::Inline).unwrap();
+        assert!(mathml.contains("<msup><mi>x</mi><mn>2</mn></msup>"));
+    }
+
+    #[test]
+    fn test_text_preserves_spaces() {
+        let mathml = to_mathml(r"c \text{ speed of <light> }", DisplayStyle::Inline).unwrap();
+        assert!(mathml.contains("<mtext> speed of &lt;light&gt; </mtext>"));
+    }
+
+    #[test]
+    fn test_escapes_relations() {
+        let mathml = to_mathml("a < b > c", DisplayStyle::Inline).unwrap();
+        assert!(mathml.contains("<mo>&lt;</mo>"));
+        assert!(mathml.contains("<mo>&gt;</mo>"));
+    }
+
+    #[test]
+    fn test_parse_column_spec() {
+        let spec = parse_column_spec("|l|cr@{}p{2cm}");
+        assert_eq!(spec.aligns, ["left", "center", "right", "left"]);
+        assert_eq!(spec.lines, ["solid", "none", "none"]);
+        assert!(spec.left_rule);
+        assert!(!spec.right_rule);
+    }
+
+    #[test]
+    fn test_strip_delimiters() {
+        assert!(matches!(
+            strip_delimiters(" $x^2$ "),
+            Some(("x^2", DisplayStyle::Inline))
+        ));
+        assert!(matches!(
+            strip_delimiters("$$x$$"),
+            Some(("x", DisplayStyle::Block))
+        ));
+        assert!(matches!(
+            strip_delimiters(r"\[ a \]"),
+            Some((" a ", DisplayStyle::Block))
+        ));
+        assert!(matches!(
+            strip_delimiters(r"\(a\)"),
+            Some(("a", DisplayStyle::Inline))
+        ));
+        assert!(strip_delimiters("$a$ and $b$").is_none());
+        assert!(strip_delimiters("x^2").is_none());
+    }
+
+    #[test]
+    fn test_take_hlines() {
+        let (rows, hlines) = take_hlines(split_rows(r"\hline a \\ \hline b \\ c \\ \hline"));
+        assert_eq!(rows, vec![vec![" a "], vec![" b "], vec![" c "]]);
+        assert_eq!(hlines, [true, true, false, true]);
+    }
+
+    #[test]
+    fn test_split_rows() {
+        let rows = split_rows(r"a &= b \\ &= {c \\ d}");
+        assert_eq!(rows, vec![vec!["a ", "= b "], vec![" ", r"= {c \\ d}"]]);
+
+        let rows = split_rows(r"\begin{cases} x \\ y \end{cases} \\ z");
+        assert_eq!(
+            rows,
+            vec![vec![r"\begin{cases} x \\ y \end{cases} "], vec![" z"]]
+        );
+    }
+
+    #[test]
+    fn test_aligned_environment() {
+        let mathml = to_mathml(
+            r"\begin{aligned} a &= b \\ &= c \end{aligned}",
+            DisplayStyle::Inline,
+        )
+        .unwrap();
+        assert!(mathml.contains(r#"<mtable columnalign="right left""#));
+        assert_eq!(mathml.matches("<mtr>").count(), 2);
+        assert!(!mathml.contains('\u{E000}'));
+    }
+
+    #[test]
+    fn test_error_span() {
+        let error = |latex: &str| {
+            to_mathml(latex, DisplayStyle::Inline)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error_span(r"\frac{a", &error(r"\frac{a")), Some((7, 0)));
+        let latex = r"x + \begin{foo} y \end{foo}";
+        assert_eq!(error_span(latex, &error(latex)), Some((4, 11)));
+        assert_eq!(
+            error_span(
+                "{a)",
+                "The token \"RBrace\" is expected, but the token \"Paren(\")\")\" is found."
+            ),
+            Some((2, 1))
+        );
+    }
+}
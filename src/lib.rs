@@ -15,16 +15,53 @@
 //! let widget = MathWidget::new(r"\int_0^\infty e^{-x^2} dx");
 //! ```
 
+mod cache;
+mod calc;
 mod canvas_widget;
+mod capability;
+mod chem;
+mod editor;
+mod grapheme;
+#[cfg(feature = "image")]
+mod image_widget;
+mod latex;
+mod list;
+mod macros;
 mod mathbox;
+mod options;
+mod paragraph;
+mod raster;
 mod renderer;
+mod reveal;
+#[cfg(feature = "sixel")]
+mod sixel;
+#[cfg(feature = "svg")]
+mod svg;
+mod table;
 mod unicode_maps;
+mod units;
 mod widget;
 
-pub use canvas_widget::CanvasMathWidget;
-pub use mathbox::MathBox;
-pub use renderer::{MathRenderer, RenderError};
-pub use widget::{MathWidget, MathWidgetState, StatefulMathWidget};
+pub use cache::{clear_render_cache, set_render_cache_capacity};
+pub use canvas_widget::{paint_into, CanvasGrid, CanvasMathWidget};
+pub use capability::CapabilityProfile;
+pub use editor::{MathEditor, MathEditorState};
+pub use grapheme::Grapheme;
+#[cfg(feature = "image")]
+pub use image_widget::ImageMathWidget;
+pub use list::{MathList, MathListItem, MathListState};
+pub use macros::MacroSet;
+pub use mathbox::{BlitMode, CellChange, MathBox, Semantic};
+pub use options::{AccentStrategy, BracketSet, MathStyle, RendererOptions, Spacing};
+pub use paragraph::MathParagraph;
+#[cfg(feature = "image")]
+pub use ratatui_image::picker::{Picker, ProtocolType};
+pub use renderer::{MathRenderer, MathTheme, RenderError, TokenStyles};
+pub use reveal::{MathReveal, RevealBy, RevealState};
+pub use table::{math_cell, math_row};
+pub use widget::{
+    Background, ErrorDisplay, MathWidget, MathWidgetState, Overflow, SourceSpan, StatefulMathWidget,
+};
 
 /// Render LaTeX math to a Unicode string for terminal display
 pub fn render_latex(latex: &str) -> Result<String, RenderError> {
@@ -37,3 +74,42 @@ pub fn render_mathml(mathml: &str) -> Result<String, RenderError> {
     let renderer = MathRenderer::new();
     renderer.render_mathml(mathml)
 }
+
+/// Render single-row LaTeX math to styled spans, to embed a small formula in
+/// an existing [`Line`](ratatui::text::Line) alongside other text
+pub fn render_latex_spans(
+    latex: &str,
+    style: ratatui::style::Style,
+) -> Result<Vec<ratatui::text::Span<'static>>, RenderError> {
+    let renderer = MathRenderer::new();
+    renderer.render_latex_spans(latex, style)
+}
+
+/// Measure the width and height (columns, rows) LaTeX math needs when
+/// rendered, for building layout constraints before rendering
+pub fn measure_latex(latex: &str) -> Result<(u16, u16), RenderError> {
+    MathWidget::new(latex).required_size()
+}
+
+/// Render LaTeX math to an SVG document, with each grapheme of the terminal
+/// layout placed on a monospace grid and drawn lines as line segments
+#[cfg(feature = "svg")]
+pub fn render_latex_svg(latex: &str) -> Result<String, RenderError> {
+    let renderer = MathRenderer::new();
+    renderer.render_latex_svg(latex)
+}
+
+/// Render LaTeX math to a sixel image in white, for terminals with sixel
+/// graphics whose fonts can't show the Unicode rendering
+#[cfg(feature = "sixel")]
+pub fn render_latex_sixel(latex: &str) -> Result<String, RenderError> {
+    let renderer = MathRenderer::new();
+    renderer.render_latex_sixel(latex, ratatui::style::Color::White)
+}
+
+/// Translate a calculator-style expression (`x^2/(y+1) + sqrt(2)`, `2pi*r`,
+/// `abs(x) <= 1`) to LaTeX, for REPLs and calculators whose users don't write
+/// LaTeX. Divisions become stacked fractions and juxtaposition multiplies.
+pub fn calc_to_latex(expression: &str) -> Result<String, RenderError> {
+    calc::calc_to_latex(expression)
+}
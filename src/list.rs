@@ -0,0 +1,305 @@
+//! Scrollable list of equations, for derivations, formula sheets and
+//! equation pickers
+
+use crate::widget::paint_box;
+use crate::{MathBox, MathRenderer};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    widgets::{Block, StatefulWidget, Widget},
+};
+use std::collections::HashMap;
+
+/// One equation in a [`MathList`], with an optional tag shown flush right
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MathListItem<'a> {
+    latex: &'a str,
+    tag: Option<String>,
+}
+
+impl<'a> MathListItem<'a> {
+    /// Create an item from a LaTeX expression
+    pub fn new(latex: &'a str) -> Self {
+        Self { latex, tag: None }
+    }
+
+    /// Tag the equation, shown as `(tag)` flush right like `\tag`
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+}
+
+impl<'a> From<&'a str> for MathListItem<'a> {
+    fn from(latex: &'a str) -> Self {
+        Self::new(latex)
+    }
+}
+
+/// Selection and scroll position of a [`MathList`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MathListState {
+    selected: Option<usize>,
+    offset: usize,
+}
+
+impl MathListState {
+    /// Select the item at `index`, or nothing; the list scrolls to keep the
+    /// selection in view
+    pub fn select(&mut self, index: Option<usize>) {
+        self.selected = index;
+    }
+
+    /// The selected item, if any
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Select the next item (the first if nothing is selected); the list
+    /// stops at its last item when rendered
+    pub fn select_next(&mut self) {
+        self.selected = Some(self.selected.map_or(0, |i| i.saturating_add(1)));
+    }
+
+    /// Select the previous item (the first if nothing is selected)
+    pub fn select_previous(&mut self) {
+        self.selected = Some(self.selected.map_or(0, |i| i.saturating_sub(1)));
+    }
+
+    /// Index of the first item shown
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// A scrollable list of equations, one below the other. Only the items on
+/// screen (and those between the previous scroll position and the
+/// selection) are rendered each frame, so long lists stay cheap.
+#[derive(Clone)]
+pub struct MathList<'a> {
+    items: Vec<MathListItem<'a>>,
+    style: Style,
+    highlight_style: Style,
+    block: Option<Block<'a>>,
+    numbered: bool,
+    use_unicode_scripts: bool,
+}
+
+impl<'a> MathList<'a> {
+    /// Create a new MathList from LaTeX expressions or [`MathListItem`]s
+    pub fn new<I>(items: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<MathListItem<'a>>,
+    {
+        Self {
+            items: items.into_iter().map(Into::into).collect(),
+            style: Style::default(),
+            highlight_style: Style::default(),
+            block: None,
+            numbered: false,
+            use_unicode_scripts: true,
+        }
+    }
+
+    /// Set the style for the list
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Set the style patched over the selected item's rows
+    pub fn highlight_style(mut self, style: Style) -> Self {
+        self.highlight_style = style;
+        self
+    }
+
+    /// Wrap the widget in a block
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Number equations `(1)`, `(2)`, ... flush right. Items with their own
+    /// tag or `\tag` keep it.
+    pub fn numbered(mut self, numbered: bool) -> Self {
+        self.numbered = numbered;
+        self
+    }
+
+    /// Enable or disable Unicode superscript/subscript characters
+    pub fn use_unicode_scripts(mut self, use_unicode: bool) -> Self {
+        self.use_unicode_scripts = use_unicode;
+        self
+    }
+
+    /// Number of items in the list
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Whether the list has no items
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Render item `index` for a list `width` columns wide, with its tag
+    fn render_item(&self, index: usize, renderer: &MathRenderer, width: usize) -> MathBox {
+        let item = &self.items[index];
+        let mbox = renderer
+            .render_to_box(item.latex)
+            .unwrap_or_else(|e| MathBox::from_text(&format!("Error: {}", e)));
+        let tag = match &item.tag {
+            Some(tag) => Some(tag.clone()),
+            None if self.numbered && mbox.label().is_none() => Some((index + 1).to_string()),
+            None => None,
+        };
+        let mbox = match tag {
+            Some(tag) => mbox.with_label(MathBox::from_text(&format!("({})", tag))),
+            None => mbox,
+        };
+        mbox.place_label(width)
+    }
+}
+
+impl StatefulWidget for MathList<'_> {
+    type State = MathListState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut MathListState) {
+        buf.set_style(area, self.style);
+        let inner = match &self.block {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.clone().render(area, buf);
+                inner
+            }
+            None => area,
+        };
+        if self.items.is_empty() {
+            state.selected = None;
+            state.offset = 0;
+            return;
+        }
+
+        let renderer = MathRenderer::new().use_unicode_scripts(self.use_unicode_scripts);
+        let width = usize::from(inner.width);
+        let mut rendered: HashMap<usize, MathBox> = HashMap::new();
+        let mut item = |index: usize| -> MathBox {
+            rendered
+                .entry(index)
+                .or_insert_with(|| self.render_item(index, &renderer, width))
+                .clone()
+        };
+
+        // Scroll just far enough to bring the selection into view
+        let last = self.items.len() - 1;
+        let mut offset = state.offset.min(last);
+        state.selected = state.selected.map(|selected| selected.min(last));
+        if let Some(selected) = state.selected {
+            if selected < offset {
+                offset = selected;
+            } else {
+                let mut bottom: usize = (offset..=selected).map(|i| item(i).height).sum();
+                while bottom > usize::from(inner.height) && offset < selected {
+                    bottom -= item(offset).height;
+                    offset += 1;
+                }
+            }
+        }
+        state.offset = offset;
+
+        let mut y = 0;
+        for index in offset..self.items.len() {
+            if y >= inner.height {
+                break;
+            }
+            let mbox = item(index);
+            let height = u16::try_from(mbox.height)
+                .unwrap_or(u16::MAX)
+                .min(inner.height - y);
+            let rows = Rect::new(inner.x, inner.y + y, inner.width, height);
+            let style = if state.selected == Some(index) {
+                buf.set_style(rows, self.highlight_style);
+                self.style.patch(self.highlight_style)
+            } else {
+                self.style
+            };
+            paint_box(&mbox, rows, buf, style);
+            y += height;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Color;
+
+    fn rows(buf: &Buffer) -> Vec<String> {
+        let area = buf.area;
+        (0..area.height)
+            .map(|y| {
+                (0..area.width)
+                    .map(|x| buf[(x, y)].symbol())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_numbered_list() {
+        let items = [
+            MathListItem::new("a = b"),
+            MathListItem::new(r"\frac{1}{2}").tag("half"),
+            "c".into(),
+        ];
+        let area = Rect::new(0, 0, 12, 5);
+        let mut buf = Buffer::empty(area);
+        let mut state = MathListState::default();
+        MathList::new(items)
+            .numbered(true)
+            .render(area, &mut buf, &mut state);
+        assert_eq!(
+            rows(&buf),
+            ["a = b    (1)", "1", "─     (half)", "2", "c        (3)"]
+        );
+    }
+
+    #[test]
+    fn test_selection_scrolls_into_view() {
+        let highlight = Style::default().bg(Color::Blue);
+        let list = MathList::new(["x", r"\frac{1}{2}", "y", "z"]).highlight_style(highlight);
+        let area = Rect::new(0, 0, 3, 3);
+        let mut state = MathListState::default();
+        let render = |state: &mut MathListState| {
+            let mut buf = Buffer::empty(area);
+            list.clone().render(area, &mut buf, state);
+            buf
+        };
+
+        state.select(Some(2));
+        let buf = render(&mut state);
+        assert_eq!(
+            (state.offset(), rows(&buf)),
+            (2, vec!["y".into(), "z".into(), String::new()])
+        );
+        assert_eq!(
+            (buf[(2, 0)].bg, buf[(0, 1)].bg),
+            (Color::Blue, Color::Reset)
+        );
+
+        state.select_previous();
+        let buf = render(&mut state);
+        assert_eq!(
+            (state.offset(), rows(&buf)),
+            (1, vec!["1".into(), "─".into(), "2".into()])
+        );
+
+        state.select(Some(10));
+        render(&mut state);
+        assert_eq!((state.selected(), state.offset()), (Some(3), 2));
+    }
+}
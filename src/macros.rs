@@ -0,0 +1,379 @@
+//! Macro expansion in the LaTeX source, before conversion
+//!
+//! User macros come from `\newcommand` definitions, in the input itself or in a
+//! preamble given to the renderer. Macro sets add optional vocabularies on top
+//! of core LaTeX, such as the `physics` package's derivative and delimiter
+//! shorthands that KaTeX users are used to. Both are expanded into core LaTeX
+//! so the rest of the pipeline never sees them.
+
+use crate::latex::{read_argument, read_command, read_group, read_optional};
+use crate::RenderError;
+use std::collections::HashMap;
+
+/// How deeply macros may expand into other macros before expansion stops, so
+/// recursive definitions can't loop forever
+const MAX_DEPTH: usize = 32;
+
+/// How many expansions one source may take in all, and how long the expanded
+/// source may grow, so definitions that multiply (`\newcommand{\x}{\x\x}`)
+/// fail instead of expanding exponentially
+const MAX_EXPANSIONS: usize = 10_000;
+const MAX_LENGTH: usize = 1 << 20;
+
+/// Optional macro vocabularies, enabled with
+/// [`MathRenderer::with_macro_set`](crate::MathRenderer::with_macro_set)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroSet {
+    /// `physics` package shorthands: `\dv`, `\pdv`, `\abs`, `\norm` and `\qty`
+    Physics,
+}
+
+impl MacroSet {
+    /// Expand this set's macros in `latex`
+    pub(crate) fn expand(self, latex: &str) -> Result<String, RenderError> {
+        let mut budget = MAX_EXPANSIONS;
+        match self {
+            MacroSet::Physics => expand_with(latex, &physics_macro, 0, &mut budget),
+        }
+    }
+}
+
+/// A user-defined macro, as from `\newcommand`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Macro {
+    /// Replacement text, with parameters `#1` to `#9`
+    expansion: String,
+    /// Number of parameters
+    args: usize,
+    /// Default for an optional first parameter (`\newcommand{\f}[2][x]{...}`)
+    default: Option<String>,
+}
+
+impl Macro {
+    pub(crate) fn new(expansion: &str, args: usize) -> Self {
+        Self {
+            expansion: expansion.to_string(),
+            args: args.min(9),
+            default: None,
+        }
+    }
+}
+
+/// Remove `\newcommand`, `\renewcommand`, `\providecommand` and
+/// `\DeclareMathOperator` definitions from `latex`, adding them to `macros`.
+/// Returns the rest of the source.
+pub(crate) fn take_definitions(latex: &str, macros: &mut HashMap<String, Macro>) -> String {
+    let mut out = String::with_capacity(latex.len());
+    let mut pos = 0;
+    while let Some(offset) = latex[pos..].find('\\') {
+        let start = pos + offset;
+        out.push_str(&latex[pos..start]);
+        let (command, after) = read_command(latex, start);
+        match read_definition(command, latex, after) {
+            Some((name, definition, end)) => {
+                // \providecommand only defines macros that don't exist yet
+                if command != "providecommand" || !macros.contains_key(&name) {
+                    macros.insert(name, definition);
+                }
+                pos = end;
+            }
+            None => {
+                out.push_str(&latex[start..after]);
+                pos = after;
+            }
+        }
+    }
+    out.push_str(&latex[pos..]);
+    out
+}
+
+/// Parse the name and definition following a defining command at `pos`
+fn read_definition(command: &str, latex: &str, pos: usize) -> Option<(String, Macro, usize)> {
+    let starred = |pos: usize| pos + usize::from(latex[pos..].starts_with('*'));
+    match command {
+        "newcommand" | "renewcommand" | "providecommand" => {
+            let (name, mut end) = read_argument(latex, starred(pos))?;
+            let mut definition = Macro::new("", 0);
+            if let Some((args, after)) = read_optional(latex, end) {
+                definition.args = args.trim().parse::<usize>().ok()?.min(9);
+                end = after;
+                if let Some((default, after)) = read_optional(latex, end) {
+                    definition.default = Some(default.to_string());
+                    end = after;
+                }
+            }
+            let (expansion, end) = read_group(latex, end)?;
+            definition.expansion = expansion.to_string();
+            Some((
+                name.trim().trim_start_matches('\\').to_string(),
+                definition,
+                end,
+            ))
+        }
+        "DeclareMathOperator" => {
+            let (name, end) = read_argument(latex, starred(pos))?;
+            let (operator, end) = read_group(latex, end)?;
+            let expansion = format!(r"\operatorname{{{}}}", operator);
+            Some((
+                name.trim().trim_start_matches('\\').to_string(),
+                Macro::new(&expansion, 0),
+                end,
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Expand user macros in `latex`. Fails if expansion runs away.
+pub(crate) fn expand_macros(
+    latex: &str,
+    macros: &HashMap<String, Macro>,
+) -> Result<String, RenderError> {
+    if macros.is_empty() {
+        return Ok(latex.to_string());
+    }
+    let mut budget = MAX_EXPANSIONS;
+    expand_with(
+        latex,
+        &|name, latex, pos| user_macro(macros.get(name)?, latex, pos),
+        0,
+        &mut budget,
+    )
+}
+
+/// Substitute a user macro's arguments, read from `pos`, into its expansion
+fn user_macro(definition: &Macro, latex: &str, pos: usize) -> Option<(String, usize)> {
+    let mut args = Vec::new();
+    let mut end = pos;
+    if let Some(default) = &definition.default {
+        match read_optional(latex, end) {
+            Some((arg, after)) => {
+                args.push(arg);
+                end = after;
+            }
+            None => args.push(default.as_str()),
+        }
+    }
+    while args.len() < definition.args {
+        let (arg, after) = read_argument(latex, end)?;
+        args.push(arg);
+        end = after;
+    }
+
+    let mut expansion = String::with_capacity(definition.expansion.len());
+    let mut chars = definition.expansion.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().copied()) {
+            ('#', Some('#')) => {
+                chars.next();
+                expansion.push('#');
+            }
+            ('#', Some(digit @ '1'..='9')) => {
+                chars.next();
+                let index = digit as usize - '1' as usize;
+                expansion.push_str(args.get(index).copied().unwrap_or(""));
+            }
+            _ => expansion.push(c),
+        }
+    }
+    // Keep a command name ending the expansion from running into following letters
+    let letters = expansion
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .len();
+    let ends_in_command = letters < expansion.len() && expansion[..letters].ends_with('\\');
+    if ends_in_command && latex[end..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        expansion.push(' ');
+    }
+    Some((expansion, end))
+}
+
+/// Expands a command given its name, the source and the offset after its name,
+/// returning the replacement and the offset after its arguments
+type Expander<'a> = dyn Fn(&str, &str, usize) -> Option<(String, usize)> + 'a;
+
+/// Copy `latex`, replacing each command `expand` recognizes. `expand` gets the
+/// command name and the offset after it, and returns the replacement with the
+/// offset after the command's arguments. Replacements are expanded again, so
+/// macros can be nested in arguments. `budget` is the number of expansions
+/// left; running out of it or past [`MAX_LENGTH`] is an error.
+fn expand_with(
+    latex: &str,
+    expand: &Expander,
+    depth: usize,
+    budget: &mut usize,
+) -> Result<String, RenderError> {
+    if depth > MAX_DEPTH {
+        return Ok(latex.to_string());
+    }
+    let mut out = String::with_capacity(latex.len());
+    let mut pos = 0;
+    while let Some(offset) = latex[pos..].find('\\') {
+        let start = pos + offset;
+        out.push_str(&latex[pos..start]);
+        let (name, after) = read_command(latex, start);
+        match expand(name, latex, after) {
+            Some((replacement, end)) => {
+                if *budget == 0 {
+                    return Err(runaway());
+                }
+                *budget -= 1;
+                out.push_str(&expand_with(&replacement, expand, depth + 1, budget)?);
+                if out.len() > MAX_LENGTH {
+                    return Err(runaway());
+                }
+                pos = end;
+            }
+            None => {
+                out.push_str(&latex[start..after]);
+                pos = after;
+            }
+        }
+    }
+    out.push_str(&latex[pos..]);
+    Ok(out)
+}
+
+fn runaway() -> RenderError {
+    RenderError::LatexConversion("macro expansion too large (recursive definition?)".to_string())
+}
+
+/// Expand a `physics` macro whose arguments start at `pos`
+fn physics_macro(name: &str, latex: &str, pos: usize) -> Option<(String, usize)> {
+    match name {
+        "abs" => {
+            let (arg, end) = read_group(latex, pos)?;
+            Some((format!(r"\left| {} \right|", arg), end))
+        }
+        "norm" => {
+            let (arg, end) = read_group(latex, pos)?;
+            Some((format!(r"\left\| {} \right\|", arg), end))
+        }
+        "qty" => {
+            let trimmed = latex[pos..].trim_start();
+            let start = latex.len() - trimmed.len();
+            let (open, close, right) = match trimmed.chars().next()? {
+                '(' => ('(', ')', ")"),
+                '[' => ('[', ']', "]"),
+                '|' => ('|', '|', "|"),
+                '{' => {
+                    let (arg, end) = read_group(latex, start)?;
+                    return Some((format!(r"\left\{{ {} \right\}}", arg), end));
+                }
+                _ => return None,
+            };
+            let (arg, end) = read_delimited(latex, start, open, close)?;
+            Some((format!(r"\left{} {} \right{}", open, arg, right), end))
+        }
+        "dv" | "pdv" => {
+            let d = if name == "dv" {
+                r"\mathrm{d}"
+            } else {
+                r"\partial"
+            };
+            let (order, mut end) = match read_optional(latex, pos) {
+                Some((order, end)) => (Some(order.trim()), end),
+                None => (None, pos),
+            };
+            let mut args = Vec::new();
+            while let Some((arg, after)) = read_group(latex, end).filter(|_| args.len() < 3) {
+                args.push(arg);
+                end = after;
+            }
+            let power = |base: &str| match order {
+                Some(order) => format!("{}^{{{}}}", base, order),
+                None => base.to_string(),
+            };
+            let fraction = match args[..] {
+                // Operator form: \dv{x} is d/dx
+                [var] => format!(r"\frac{{{}}}{{{} {}}}", power(d), d, power(var)),
+                [f, var] => format!(r"\frac{{{} {}}}{{{} {}}}", power(d), f, d, power(var)),
+                // Mixed partials: \pdv{f}{x}{y}
+                [f, x, y] if name == "pdv" => {
+                    format!(r"\frac{{{}^{{2}} {}}}{{{} {} {} {}}}", d, f, d, x, d, y)
+                }
+                _ => return None,
+            };
+            Some((fraction, end))
+        }
+        _ => None,
+    }
+}
+
+/// Read `open ... close` starting at `pos`, skipping nested pairs (for `(` and `[`).
+/// Returns the contents and the byte offset just after the closing delimiter.
+fn read_delimited(latex: &str, pos: usize, open: char, close: char) -> Option<(&str, usize)> {
+    let body = pos + open.len_utf8();
+    let mut depth = 0usize;
+    let mut escaped = false;
+    for (i, c) in latex[body..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == close && depth == 0 => {
+                return Some((&latex[body..body + i], body + i + c.len_utf8()))
+            }
+            c if c == close => depth -= 1,
+            c if c == open => depth += 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_macros() {
+        let mut macros = HashMap::new();
+        let rest = take_definitions(
+            r"\newcommand{\R}{\mathbb{R}} \newcommand\ip[2]{\langle #1, #2 \rangle}\DeclareMathOperator{\tr}{tr}x \in \R",
+            &mut macros,
+        );
+        assert_eq!(rest, r" x \in \R");
+        assert_eq!(expand_macros(&rest, &macros).unwrap(), r" x \in \mathbb{R}");
+        assert_eq!(
+            expand_macros(r"\ip{u}{\R v}", &macros).unwrap(),
+            r"\langle u, \mathbb{R} v \rangle"
+        );
+        assert_eq!(
+            expand_macros(r"\tr A", &macros).unwrap(),
+            r"\operatorname{tr} A"
+        );
+    }
+
+    #[test]
+    fn test_recursive_macro_terminates() {
+        let macros = HashMap::from([("loop".to_string(), Macro::new(r"a\loop", 0))]);
+        assert!(expand_macros(r"\loop", &macros).unwrap().starts_with("aaa"));
+    }
+
+    #[test]
+    fn test_exponential_macro_fails() {
+        let mut macros = HashMap::new();
+        let rest = take_definitions(r"\newcommand{\x}{\x\x}\x", &mut macros);
+        assert!(matches!(
+            expand_macros(&rest, &macros),
+            Err(RenderError::LatexConversion(_))
+        ));
+    }
+
+    #[test]
+    fn test_physics_macros() {
+        let expand = |latex| MacroSet::Physics.expand(latex).unwrap();
+        assert_eq!(expand(r"\dv{f}{x}"), r"\frac{\mathrm{d} f}{\mathrm{d} x}");
+        assert_eq!(
+            expand(r"\pdv[2]{f}{t}"),
+            r"\frac{\partial^{2} f}{\partial t^{2}}"
+        );
+        assert_eq!(
+            expand(r"\qty(\abs{x} + 1)"),
+            r"\left( \left| x \right| + 1 \right)"
+        );
+        assert_eq!(
+            expand(r"\norm{v} \\ \alpha"),
+            r"\left\| v \right\| \\ \alpha"
+        );
+    }
+}
@@ -1,56 +1,280 @@
 //! MathBox - A 2D character grid for math rendering
 
+use crate::grapheme::Grapheme;
+use ratatui::layout::{Alignment, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::Padding;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
+/// How [`MathBox::blit_with`] composites blank cells of the box it copies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlitMode {
+    /// Copy every cell, blanks included, replacing what's underneath
+    Opaque,
+    /// Skip blank cells, so only drawn characters overwrite (what
+    /// [`MathBox::blit`] does); a styled blank still passes its style to a
+    /// blank cell
+    #[default]
+    TransparentSpace,
+    /// Skip only blank cells with no style, so styled blanks such as a
+    /// highlighted background overwrite along with everything drawn
+    TransparentEmpty,
+}
+
+/// A cell to redraw to turn one box into another, see [`MathBox::diff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellChange {
+    /// Column of the cell
+    pub x: usize,
+    /// Row of the cell
+    pub y: usize,
+    /// The new grapheme, empty for the continuation cell of a wide character
+    pub grapheme: Grapheme,
+    /// The new style
+    pub style: Style,
+}
+
 /// Represents a box of grapheme clusters for rendering math expressions.
 /// Uses a 2D grid with baseline tracking for proper vertical alignment.
 /// Each cell holds a grapheme cluster (base char + combining marks).
+/// A parallel style layer lets the renderer mark cells (e.g. bold) for widgets.
+///
+/// Cells and styles are stored row by row in flat buffers, the cells as
+/// interned [`Grapheme`]s so copying and cloning boxes doesn't allocate.
 #[derive(Clone, Debug)]
 pub struct MathBox {
-    content: Vec<Vec<String>>,
+    content: Vec<Grapheme>,
+    styles: Vec<Style>,
     pub width: usize,
     pub height: usize,
     /// The baseline row (0-indexed from top)
     pub baseline: usize,
+    /// Equation label (e.g. from `\tag`), kept apart from the content so widgets
+    /// can place it flush right
+    label: Option<Box<MathBox>>,
+    /// The regions drawn for each MathML element, if recorded
+    semantics: Vec<Semantic>,
+}
+
+/// The part of a [`MathBox`] drawn for one MathML element, recorded when
+/// rendering with [`MathRenderer::semantics`](crate::MathRenderer::semantics)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Semantic {
+    /// Cells the element's box covers
+    pub area: Rect,
+    /// Element indices from `<math>` down to the element
+    pub path: Vec<usize>,
+    /// The element's tag name, such as `"mfrac"` or `"mi"`
+    pub element: String,
+    /// Byte range of the element in the MathML
+    pub mathml: Range<usize>,
+    /// Byte range of the token in the LaTeX, for token elements of a render
+    /// from LaTeX that could be traced back to it
+    pub latex: Option<Range<usize>>,
+}
+
+impl Semantic {
+    /// Whether the element is a token (`<mi>`, `<mn>`, `<mo>`, `<mtext>`,
+    /// `<ms>`) rather than a layout element
+    pub fn is_token(&self) -> bool {
+        matches!(self.element.as_str(), "mi" | "mn" | "mo" | "mtext" | "ms")
+    }
+}
+
+/// Apply the parameters of an SGR escape (`ESC[...m`) to `style`
+fn apply_sgr(mut style: Style, params: &str) -> Style {
+    const COLORS: [Color; 8] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+    ];
+    const BRIGHT: [Color; 8] = [
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ];
+    let mut codes = params
+        .split([';', ':'])
+        .map(|code| code.parse::<u8>().unwrap_or(0));
+    // 38 and 48 take a color as `5;INDEX` or `2;R;G;B`
+    let extended = |codes: &mut dyn Iterator<Item = u8>| match codes.next() {
+        Some(5) => codes.next().map(Color::Indexed),
+        Some(2) => Some(Color::Rgb(codes.next()?, codes.next()?, codes.next()?)),
+        _ => None,
+    };
+    while let Some(code) = codes.next() {
+        match code {
+            0 => style = Style::default(),
+            1 => style = style.add_modifier(Modifier::BOLD),
+            2 => style = style.add_modifier(Modifier::DIM),
+            3 => style = style.add_modifier(Modifier::ITALIC),
+            4 => style = style.add_modifier(Modifier::UNDERLINED),
+            5 => style = style.add_modifier(Modifier::SLOW_BLINK),
+            6 => style = style.add_modifier(Modifier::RAPID_BLINK),
+            7 => style = style.add_modifier(Modifier::REVERSED),
+            8 => style = style.add_modifier(Modifier::HIDDEN),
+            9 => style = style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style = style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style = style.remove_modifier(Modifier::ITALIC),
+            24 => style = style.remove_modifier(Modifier::UNDERLINED),
+            25 => style = style.remove_modifier(Modifier::SLOW_BLINK | Modifier::RAPID_BLINK),
+            27 => style = style.remove_modifier(Modifier::REVERSED),
+            28 => style = style.remove_modifier(Modifier::HIDDEN),
+            29 => style = style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg = Some(COLORS[usize::from(code - 30)]),
+            38 => style.fg = extended(&mut codes).or(style.fg),
+            39 => style.fg = None,
+            40..=47 => style.bg = Some(COLORS[usize::from(code - 40)]),
+            48 => style.bg = extended(&mut codes).or(style.bg),
+            49 => style.bg = None,
+            90..=97 => style.fg = Some(BRIGHT[usize::from(code - 90)]),
+            100..=107 => style.bg = Some(BRIGHT[usize::from(code - 100)]),
+            _ => {}
+        }
+    }
+    style
+}
+
+/// Skip the rest of a non-CSI escape sequence: an OSC or other string
+/// sequence up to its terminator, or else the one character after `ESC`
+fn skip_escape(rest: &str) -> &str {
+    let mut chars = rest.chars();
+    match chars.next() {
+        Some(']' | 'P' | '_' | '^' | 'X') => {
+            let body = chars.as_str();
+            let end = body.find(['\x07', '\x1b']).unwrap_or(body.len());
+            let terminator = if body[end..].starts_with("\x1b\\") {
+                2
+            } else {
+                1
+            };
+            body.get(end + terminator..).unwrap_or("")
+        }
+        _ => chars.as_str(),
+    }
+}
+
+/// `area` moved by (dx, dy) and clipped to a `width` by `height` box, if any
+/// of it is left
+fn place(area: Rect, dx: i64, dy: i64, width: usize, height: usize) -> Option<Rect> {
+    let clip = |start: u16, len: u16, offset: i64, limit: usize| {
+        let moved = i64::from(start) + offset;
+        let (start, end) = (moved.max(0), (moved + i64::from(len)).min(limit as i64));
+        (start < end).then(|| (u16::try_from(start).ok(), u16::try_from(end - start).ok()))
+    };
+    let (x, w) = clip(area.x, area.width, dx, width)?;
+    let (y, h) = clip(area.y, area.height, dy, height)?;
+    Some(Rect::new(x?, y?, w?, h?))
 }
 
 impl MathBox {
     /// Create a MathBox from a single-line string
     pub fn from_text(text: &str) -> Self {
-        let graphemes: Vec<String> = text.graphemes(true).map(|g| g.to_string()).collect();
         let width = text.width();
 
         // Pad to match display width (handles wide chars)
         let mut cells = Vec::with_capacity(width);
-        for g in graphemes {
-            let g_width = g.width();
-            cells.push(g);
+        for g in text.graphemes(true) {
+            cells.push(Grapheme::new(g));
             // Add empty cells for wide characters
-            for _ in 1..g_width {
-                cells.push(String::new());
+            for _ in 1..g.width() {
+                cells.push(Grapheme::default());
             }
         }
         // Ensure we have exactly 'width' cells
-        while cells.len() < width {
-            cells.push(" ".to_string());
-        }
+        cells.resize(width, Grapheme::SPACE);
 
         Self {
-            content: vec![cells],
+            content: cells,
+            styles: vec![Style::default(); width],
             width,
             height: 1,
             baseline: 0,
+            label: None,
+            semantics: Vec::new(),
         }
     }
 
+    /// Create a MathBox from text colored with ANSI escape codes, such as the
+    /// output of another tool, keeping the colors and attributes (bold,
+    /// italic, underline, ...) in the style layer so it can be composed into a
+    /// formula. Lines are split at `\n` with the baseline on the middle one;
+    /// escape sequences other than SGR (`ESC[...m`) are dropped.
+    ///
+    /// ```
+    /// use ratatui::style::{Color, Modifier};
+    /// use tui_math::MathBox;
+    ///
+    /// let value = MathBox::from_ansi("\x1b[1;31m9.81\x1b[0m");
+    /// assert_eq!(value.to_string(), "9.81");
+    /// assert_eq!(value.style(0, 0).fg, Some(Color::Red));
+    /// assert!(value.style(0, 0).add_modifier.contains(Modifier::BOLD));
+    /// ```
+    pub fn from_ansi(text: &str) -> Self {
+        let mut style = Style::default();
+        let rows: Vec<Vec<(String, Style)>> = text
+            .split('\n')
+            .map(|line| {
+                let mut cells = Vec::new();
+                let mut rest = line;
+                while !rest.is_empty() {
+                    let (plain, after) = rest.split_at(rest.find('\x1b').unwrap_or(rest.len()));
+                    for g in plain.graphemes(true).filter(|g| g.width() > 0) {
+                        cells.push((g.to_string(), style));
+                        cells.extend((1..g.width()).map(|_| (String::new(), style)));
+                    }
+                    rest = match after.strip_prefix("\x1b[") {
+                        Some(csi) => {
+                            let end = csi
+                                .find(|c: char| ('@'..='~').contains(&c))
+                                .unwrap_or(csi.len());
+                            if csi[end..].starts_with('m') {
+                                style = apply_sgr(style, &csi[..end]);
+                            }
+                            csi.get(end + 1..).unwrap_or("")
+                        }
+                        None => after.get(1..).map_or("", skip_escape),
+                    };
+                }
+                cells
+            })
+            .collect();
+
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut result = MathBox::empty(width, rows.len(), rows.len() / 2);
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, (g, style)) in row.into_iter().enumerate() {
+                result.set_grapheme(x, y, &g);
+                result.set_style(x, y, style);
+            }
+        }
+        result
+    }
+
     /// Create an empty MathBox with specified dimensions
     pub fn empty(width: usize, height: usize, baseline: usize) -> Self {
         Self {
-            content: vec![vec![" ".to_string(); width]; height],
+            content: vec![Grapheme::SPACE; width * height],
+            styles: vec![Style::default(); width * height],
             width,
             height,
             baseline,
+            label: None,
+            semantics: Vec::new(),
         }
     }
 
@@ -58,37 +282,37 @@ impl MathBox {
     pub fn from_lines(lines: Vec<String>, baseline: usize) -> Self {
         let height = lines.len();
         let width = lines.iter().map(|l| l.width()).max().unwrap_or(0);
-        let mut content = vec![vec![" ".to_string(); width]; height];
+        let mut result = MathBox::empty(width, height, baseline);
 
         for (y, line) in lines.iter().enumerate() {
             let mut x = 0;
             for g in line.graphemes(true) {
                 if x < width {
                     let g_width = g.width();
-                    content[y][x] = g.to_string();
+                    result.set_grapheme(x, y, g);
                     // Mark continuation cells for wide chars
                     for i in 1..g_width {
-                        if x + i < width {
-                            content[y][x + i] = String::new();
-                        }
+                        result.set_grapheme(x + i, y, "");
                     }
                     x += g_width;
                 }
             }
         }
+        result
+    }
 
-        Self {
-            content,
-            width,
-            height,
-            baseline,
-        }
+    /// The cells of row `y`
+    fn row(&self, y: usize) -> &[Grapheme] {
+        &self.content[y * self.width..(y + 1) * self.width]
     }
 
     /// Get grapheme at position (returns space if out of bounds or empty)
     pub fn get(&self, x: usize, y: usize) -> char {
         if y < self.height && x < self.width {
-            self.content[y][x].chars().next().unwrap_or(' ')
+            self.content[y * self.width + x]
+                .chars()
+                .next()
+                .unwrap_or(' ')
         } else {
             ' '
         }
@@ -97,7 +321,7 @@ impl MathBox {
     /// Get full grapheme cluster at position
     pub fn get_grapheme(&self, x: usize, y: usize) -> &str {
         if y < self.height && x < self.width {
-            &self.content[y][x]
+            self.content[y * self.width + x].as_str()
         } else {
             " "
         }
@@ -106,31 +330,83 @@ impl MathBox {
     /// Set character at position
     pub fn set(&mut self, x: usize, y: usize, ch: char) {
         if y < self.height && x < self.width {
-            self.content[y][x] = ch.to_string();
+            self.content[y * self.width + x] = Grapheme::from(ch);
         }
     }
 
     /// Set grapheme cluster at position
     pub fn set_grapheme(&mut self, x: usize, y: usize, g: &str) {
         if y < self.height && x < self.width {
-            self.content[y][x] = g.to_string();
+            self.content[y * self.width + x] = Grapheme::new(g);
         }
     }
 
-    /// Copy another MathBox into this one at the specified offset
+    /// Get the style overlay at position (default style if out of bounds)
+    pub fn style(&self, x: usize, y: usize) -> Style {
+        if y < self.height && x < self.width {
+            self.styles[y * self.width + x]
+        } else {
+            Style::default()
+        }
+    }
+
+    /// Set the style overlay at position
+    pub fn set_style(&mut self, x: usize, y: usize, style: Style) {
+        if y < self.height && x < self.width {
+            self.styles[y * self.width + x] = style;
+        }
+    }
+
+    /// Patch a style onto every cell of the box
+    pub fn patch_style(&mut self, style: Style) {
+        for cell in &mut self.styles {
+            *cell = cell.patch(style);
+        }
+    }
+
+    /// Copy another MathBox into this one at the specified offset. Blank cells
+    /// don't overwrite; a styled blank only passes its style to a blank cell.
     pub fn blit(&mut self, other: &MathBox, x_offset: usize, y_offset: usize) {
+        self.blit_with(other, x_offset, y_offset, BlitMode::TransparentSpace);
+    }
+
+    /// Copy another MathBox into this one at the specified offset, with `mode`
+    /// deciding which of its blank cells show what's underneath
+    pub fn blit_with(&mut self, other: &MathBox, x_offset: usize, y_offset: usize, mode: BlitMode) {
         for y in 0..other.height {
             for x in 0..other.width {
                 let target_x = x_offset + x;
                 let target_y = y_offset + y;
                 if target_y < self.height && target_x < self.width {
-                    let g = other.get_grapheme(x, y);
-                    if !g.is_empty() && g != " " {
-                        self.set_grapheme(target_x, target_y, g);
+                    let g = other.content[y * other.width + x];
+                    let style = other.style(x, y);
+                    let blank = g.is_empty() || g == " ";
+                    let opaque = match mode {
+                        BlitMode::Opaque => true,
+                        BlitMode::TransparentSpace => !blank,
+                        BlitMode::TransparentEmpty => !blank || style != Style::default(),
+                    };
+                    if opaque {
+                        self.content[target_y * self.width + target_x] = g;
+                        self.set_style(target_x, target_y, style);
+                    } else if self.get_grapheme(target_x, target_y) == " "
+                        && style != Style::default()
+                    {
+                        // Styled blanks (a highlighted region) keep their style over blanks
+                        self.set_style(target_x, target_y, style);
                     }
                 }
             }
         }
+        for semantic in &other.semantics {
+            let (dx, dy) = (x_offset as i64, y_offset as i64);
+            if let Some(area) = place(semantic.area, dx, dy, self.width, self.height) {
+                self.semantics.push(Semantic {
+                    area,
+                    ..semantic.clone()
+                });
+            }
+        }
     }
 
     /// Concatenate horizontally, aligning by baseline
@@ -185,6 +461,183 @@ impl MathBox {
         result
     }
 
+    /// Lay boxes out side by side on a shared baseline, for composing
+    /// rendered fragments into custom layouts. Labels are dropped.
+    ///
+    /// ```
+    /// use tui_math::MathBox;
+    ///
+    /// let row = MathBox::hbox(&[MathBox::from_text("x"), MathBox::from_text(" = "), MathBox::from_text("1")]);
+    /// assert_eq!(row.to_string(), "x = 1");
+    /// ```
+    pub fn hbox(boxes: &[MathBox]) -> MathBox {
+        Self::concat_horizontal(boxes)
+    }
+
+    /// Stack boxes top to bottom, each aligned left, centered or right within
+    /// the widest, with the baseline on the first box's baseline so the
+    /// stack lines up with text beside it like the first box would (e.g. an
+    /// equation with a note under it). Labels are dropped.
+    ///
+    /// ```
+    /// use ratatui::layout::Alignment;
+    /// use tui_math::MathBox;
+    ///
+    /// let note = MathBox::vbox(&[MathBox::from_text("a = b"), MathBox::from_text("↑ def")], Alignment::Left);
+    /// assert_eq!(note.to_string(), "a = b\n↑ def");
+    /// assert_eq!(note.baseline, 0);
+    /// ```
+    pub fn vbox(boxes: &[MathBox], align: Alignment) -> MathBox {
+        let width = boxes.iter().map(|b| b.width).max().unwrap_or(0);
+        let height: usize = boxes.iter().map(|b| b.height).sum();
+        let baseline = boxes.first().map_or(0, |b| b.baseline);
+        let mut result = MathBox::empty(width, height.max(1), baseline);
+        let mut y = 0;
+        for b in boxes {
+            let x = match align {
+                Alignment::Left => 0,
+                Alignment::Center => (width - b.width) / 2,
+                Alignment::Right => width - b.width,
+            };
+            result.blit(b, x, y);
+            y += b.height;
+        }
+        result
+    }
+
+    /// Return a copy with blank columns and rows added around the content, the
+    /// baseline moving down with it. The label is kept.
+    pub fn pad(&self, padding: Padding) -> MathBox {
+        let (left, right) = (usize::from(padding.left), usize::from(padding.right));
+        let (top, bottom) = (usize::from(padding.top), usize::from(padding.bottom));
+        let mut result = MathBox::empty(
+            self.width + left + right,
+            self.height + top + bottom,
+            self.baseline + top,
+        );
+        result.blit(self, left, top);
+        result.label = self.label.clone();
+        result
+    }
+
+    /// Return a copy with a line of `ch` drawn in a new row above the content
+    pub fn overline(&self, ch: char) -> MathBox {
+        let mut result = MathBox::empty(self.width, self.height + 1, self.baseline + 1);
+        result.fill_row(0, ch);
+        result.blit(self, 0, 1);
+        result
+    }
+
+    /// Return a copy with a line of `ch` drawn in a new row below the content
+    pub fn underline(&self, ch: char) -> MathBox {
+        let mut result = MathBox::empty(self.width, self.height + 1, self.baseline);
+        result.blit(self, 0, 0);
+        result.fill_row(self.height, ch);
+        result
+    }
+
+    /// Return a copy surrounded by a box-drawing frame (┌─┐ │ │ └─┘)
+    pub fn frame(&self) -> MathBox {
+        let width = self.width + 2;
+        let height = self.height + 2;
+        let mut result = MathBox::empty(width, height, self.baseline + 1);
+
+        result.fill_row(0, '─');
+        result.fill_row(height - 1, '─');
+        result.fill_col(0, '│');
+        result.fill_col(width - 1, '│');
+        result.set(0, 0, '┌');
+        result.set(width - 1, 0, '┐');
+        result.set(0, height - 1, '└');
+        result.set(width - 1, height - 1, '┘');
+        result.blit(self, 1, 1);
+        result
+    }
+
+    /// Every cell with its column and row, row by row, skipping the blank
+    /// continuation cells after wide characters. The label isn't included.
+    ///
+    /// ```
+    /// use tui_math::MathBox;
+    ///
+    /// let mb = MathBox::from_text("语x");
+    /// let cells: Vec<_> = mb.cells().collect();
+    /// assert_eq!(cells, [(0, 0, "语"), (2, 0, "x")]);
+    /// ```
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize, &str)> {
+        let width = self.width.max(1);
+        self.content
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| !g.is_empty())
+            .map(move |(i, g)| (i % width, i / width, g.as_str()))
+    }
+
+    /// Every cell with its column and row, for changing graphemes in place
+    /// (see [`MathBox::cells`]). Replacements should be as wide as what they
+    /// replace to keep the grid aligned.
+    ///
+    /// ```
+    /// use tui_math::MathBox;
+    ///
+    /// let mut mb = MathBox::from_text("a - b");
+    /// for (_, _, g) in mb.cells_mut() {
+    ///     if g == "-" {
+    ///         *g = "−".into();
+    ///     }
+    /// }
+    /// assert_eq!(mb.to_string(), "a − b");
+    /// ```
+    pub fn cells_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut Grapheme)> {
+        let width = self.width.max(1);
+        self.content
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, g)| !g.is_empty())
+            .map(move |(i, g)| (i % width, i / width, g))
+    }
+
+    /// The cells to redraw to turn this box into `other`, row by row: those
+    /// whose grapheme or style differ, plus blanks for cells outside `other`
+    /// when it is smaller. Stateful widgets can keep the last box drawn and
+    /// update only these cells when an expression is edited. Labels aren't
+    /// compared; diff the boxes from [`MathBox::place_label`] to include them.
+    ///
+    /// ```
+    /// use tui_math::MathBox;
+    ///
+    /// let before = MathBox::from_text("x + 1");
+    /// let after = MathBox::from_text("x + 2");
+    /// let changes = before.diff(&after);
+    /// assert_eq!(changes.len(), 1);
+    /// assert_eq!((changes[0].x, changes[0].y, changes[0].grapheme.as_str()), (4, 0, "2"));
+    /// ```
+    pub fn diff(&self, other: &MathBox) -> Vec<CellChange> {
+        let (width, height) = (self.width.max(other.width), self.height.max(other.height));
+        let cell = |mbox: &MathBox, x: usize, y: usize| {
+            (x < mbox.width && y < mbox.height)
+                .then(|| (mbox.content[y * mbox.width + x], mbox.style(x, y)))
+        };
+        let mut changes = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let (old, new) = (cell(self, x, y), cell(other, x, y));
+                if old == new || (old.is_none() && new == Some((Grapheme::SPACE, Style::default())))
+                {
+                    continue;
+                }
+                let (grapheme, style) = new.unwrap_or((Grapheme::SPACE, Style::default()));
+                changes.push(CellChange {
+                    x,
+                    y,
+                    grapheme,
+                    style,
+                });
+            }
+        }
+        changes
+    }
+
     /// Fill a row with a character
     pub fn fill_row(&mut self, y: usize, ch: char) {
         if y < self.height {
@@ -203,27 +656,503 @@ impl MathBox {
         }
     }
 
-    /// Convert to string representation
-    pub fn to_string(&self) -> String {
-        self.content
+    /// Get the equation label, if any
+    pub fn label(&self) -> Option<&MathBox> {
+        self.label.as_deref()
+    }
+
+    /// Attach an equation label
+    pub fn with_label(mut self, label: MathBox) -> Self {
+        self.label = Some(Box::new(label));
+        self
+    }
+
+    /// The region drawn for each MathML element, recorded when rendering
+    /// with [`MathRenderer::semantics`](crate::MathRenderer::semantics) and
+    /// kept through layout (blitting, cropping, placing the label). Each
+    /// element comes after the elements inside it.
+    pub fn semantics(&self) -> &[Semantic] {
+        &self.semantics
+    }
+
+    /// The elements whose regions cover cell (`x`, `y`), innermost first,
+    /// e.g. the token clicked on followed by the fraction holding it
+    pub fn semantics_at(&self, x: usize, y: usize) -> impl Iterator<Item = &Semantic> {
+        self.semantics.iter().filter(move |s| {
+            let area = s.area;
+            (usize::from(area.x)..usize::from(area.right())).contains(&x)
+                && (usize::from(area.y)..usize::from(area.bottom())).contains(&y)
+        })
+    }
+
+    /// Record the region drawn for an element
+    pub(crate) fn add_semantic(&mut self, semantic: Semantic) {
+        self.semantics.push(semantic);
+    }
+
+    /// The recorded regions, for filling in their LaTeX spans
+    pub(crate) fn semantics_mut(&mut self) -> &mut [Semantic] {
+        &mut self.semantics
+    }
+
+    /// Break an expression wider than `width` columns into lines at `+`, `−`,
+    /// `=` and relation operators, each continuation line starting with its
+    /// operator and indented by `indent` columns. Only operators standing alone
+    /// in their column (not inside a fraction or matrix) are break points; a
+    /// stretch with none is left overlong. The label is kept.
+    pub fn wrap_at_operators(&self, width: usize, indent: usize) -> MathBox {
+        if self.width <= width {
+            return self.clone();
+        }
+        let breaks: Vec<usize> = (1..self.width)
+            .filter(|&x| self.is_break_column(x))
+            .collect();
+
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < self.width {
+            let available = if start == 0 {
+                width
+            } else {
+                width.saturating_sub(indent)
+            };
+            if self.width - start <= available {
+                pieces.push(self.columns(start, self.width));
+                break;
+            }
+            let after = breaks.iter().copied().filter(|&x| x > start);
+            let end = match after.clone().take_while(|&x| x - start <= available).last() {
+                Some(end) => end,
+                None => after.min().unwrap_or(self.width),
+            };
+            pieces.push(self.columns(start, end));
+            start = end;
+        }
+
+        let result_width = pieces
             .iter()
-            .map(|row| row.join("").trim_end().to_string())
-            .collect::<Vec<_>>()
-            .join("\n")
+            .enumerate()
+            .map(|(i, piece)| piece.width + if i == 0 { 0 } else { indent })
+            .max()
+            .unwrap_or(0);
+        let height = pieces.iter().map(|piece| piece.height).sum();
+        let mut result = MathBox::empty(result_width, height, pieces[0].baseline);
+        let mut y = 0;
+        for (i, piece) in pieces.iter().enumerate() {
+            result.blit(piece, if i == 0 { 0 } else { indent }, y);
+            y += piece.height;
+        }
+        result.label = self.label.clone();
+        result
+    }
+
+    /// Cut a box wider than `width` columns at the last token boundary (a
+    /// blank column, not right after an operator) that leaves room for a `…`,
+    /// appended on the baseline.
+    /// A label keeps its place: the content is cut to leave room for it.
+    pub fn ellipsize(&self, width: usize) -> MathBox {
+        let width = match &self.label {
+            Some(label) => width.saturating_sub(label.width + 2),
+            None => width,
+        };
+        if self.width <= width {
+            return self.clone();
+        }
+        let cut = (1..width)
+            .rev()
+            .find(|&x| {
+                (0..self.height).all(|y| self.get_grapheme(x, y) == " ")
+                    && !self.is_break_column(x - 1)
+            })
+            .unwrap_or(0);
+        let kept = self.columns(0, cut);
+        let mut result = MathBox::empty(kept.width + 1, kept.height, kept.baseline);
+        result.blit(&kept, 0, 0);
+        result.set(kept.width, kept.baseline, '…');
+        result.label = self.label.clone();
+        result
+    }
+
+    /// Whether column `x` holds a breakable operator on the baseline, after a
+    /// space, with nothing else in the column
+    fn is_break_column(&self, x: usize) -> bool {
+        const BREAK_OPERATORS: &[&str] = &[
+            "+", "−", "-", "±", "∓", "=", "≠", "<", ">", "≤", "≥", "≈", "≡", "∼", "≃", "≅", "→",
+            "⇒", "⇔",
+        ];
+        let blank = |x: usize, y: usize| matches!(self.get_grapheme(x, y), " " | "");
+        x > 0
+            && BREAK_OPERATORS.contains(&self.get_grapheme(x, self.baseline))
+            && blank(x - 1, self.baseline)
+            && (0..self.height).all(|y| y == self.baseline || blank(x, y))
+    }
+
+    /// Columns `start..end` as a box, with trailing blank columns and blank
+    /// rows above and below the content removed
+    fn columns(&self, start: usize, end: usize) -> MathBox {
+        let blank = |x: usize, y: usize| matches!(self.get_grapheme(x, y), " " | "");
+        let end = (start..end)
+            .rev()
+            .find(|&x| (0..self.height).any(|y| !blank(x, y)))
+            .map_or(start, |x| x + 1);
+        let used = |y: &usize| (start..end).any(|x| !blank(x, *y));
+        let top = (0..self.height)
+            .find(used)
+            .unwrap_or(self.baseline)
+            .min(self.baseline);
+        let bottom = (0..self.height)
+            .rev()
+            .find(used)
+            .unwrap_or(self.baseline)
+            .max(self.baseline);
+
+        self.region(start, top, end - start, bottom - top + 1)
+    }
+
+    /// The columns and rows holding anything but blanks, if any do
+    fn content_bounds(&self) -> Option<(Range<usize>, Range<usize>)> {
+        let blank = |x: usize, y: usize| matches!(self.get_grapheme(x, y), " " | "");
+        let used_col = |x: &usize| (0..self.height).any(|y| !blank(*x, y));
+        let used_row = |y: &usize| (0..self.width).any(|x| !blank(x, *y));
+        let (left, right) = (
+            (0..self.width).find(used_col)?,
+            (0..self.width).rfind(used_col)?,
+        );
+        let (top, bottom) = (
+            (0..self.height).find(used_row)?,
+            (0..self.height).rfind(used_row)?,
+        );
+        Some((left..right + 1, top..bottom + 1))
+    }
+
+    /// Return a copy with blank rows and columns around the content removed,
+    /// such as padding left by a layout. The baseline keeps its row, or moves
+    /// to the nearest row kept if it's blank; the label is kept. A box with
+    /// nothing drawn trims to an empty box.
+    ///
+    /// ```
+    /// use ratatui::widgets::Padding;
+    /// use tui_math::MathBox;
+    ///
+    /// let x = MathBox::from_text("x");
+    /// let padded = x.pad(Padding::new(2, 1, 1, 1));
+    /// assert_eq!(padded.trim().to_lines(), x.to_lines());
+    /// ```
+    pub fn trim(&self) -> MathBox {
+        let mut result = match self.content_bounds() {
+            Some((cols, rows)) => self.region(cols.start, rows.start, cols.len(), rows.len()),
+            None => MathBox::empty(0, 1, 0),
+        };
+        result.label = self.label.clone();
+        result
+    }
+
+    /// Drop blank columns on the right and blank rows at the bottom in place.
+    /// Unlike [`MathBox::trim`] every cell keeps its position, so semantic
+    /// regions and [`MathBox::diff`]s against the old box still line up.
+    pub fn shrink_to_content(&mut self) {
+        let (width, height) = self
+            .content_bounds()
+            .map_or((0, 1), |(cols, rows)| (cols.end, rows.end));
+        if (width, height) != (self.width, self.height) {
+            let label = self.label.take();
+            *self = MathBox {
+                label,
+                ..self.region(0, 0, width, height)
+            };
+        }
+    }
+
+    /// The part of the box inside `area`, clipped to the box, e.g. to show a
+    /// window onto a big render. The baseline keeps its row, or moves to the
+    /// nearest row kept if it's cut off; the label is dropped. Wide characters
+    /// cut in half are blanked.
+    pub fn crop(&self, area: Rect) -> MathBox {
+        self.region(
+            usize::from(area.x),
+            usize::from(area.y),
+            usize::from(area.width),
+            usize::from(area.height),
+        )
+    }
+
+    /// Rows `range` of the box (see [`MathBox::crop`])
+    pub fn slice_rows(&self, range: Range<usize>) -> MathBox {
+        self.region(0, range.start, self.width, range.len())
+    }
+
+    /// Columns `range` of the box (see [`MathBox::crop`])
+    pub fn slice_cols(&self, range: Range<usize>) -> MathBox {
+        self.region(range.start, 0, range.len(), self.height)
+    }
+
+    /// Copy the `width` by `height` cells at (x, y), clipped to the box
+    fn region(&self, x: usize, y: usize, width: usize, height: usize) -> MathBox {
+        let (left, top) = (x.min(self.width), y.min(self.height));
+        let (right, bottom) = (
+            x.saturating_add(width).min(self.width),
+            y.saturating_add(height).min(self.height),
+        );
+        let baseline = self
+            .baseline
+            .saturating_sub(top)
+            .min((bottom - top).saturating_sub(1));
+        let mut result = MathBox::empty(right - left, bottom - top, baseline);
+        for y in top..bottom {
+            for x in left..right {
+                let g = self.content[y * self.width + x];
+                let cut = (g.is_empty() && x == left) || x + g.width().max(1) > right;
+                result.content[(y - top) * result.width + x - left] =
+                    if cut { Grapheme::SPACE } else { g };
+                result.set_style(x - left, y - top, self.style(x, y));
+            }
+        }
+        for semantic in &self.semantics {
+            let (dx, dy) = (-(left as i64), -(top as i64));
+            if let Some(area) = place(semantic.area, dx, dy, result.width, result.height) {
+                result.semantics.push(Semantic {
+                    area,
+                    ..semantic.clone()
+                });
+            }
+        }
+        result
+    }
+
+    /// Return the content with its label drawn flush right in a box `width` columns
+    /// wide, or two columns after the content if that is too narrow.
+    /// The label is vertically aligned with the baseline.
+    pub fn place_label(&self, width: usize) -> MathBox {
+        let Some(label) = &self.label else {
+            return self.clone();
+        };
+        let x = (self.width + 2).max(width.saturating_sub(label.width));
+        let ascent = self.baseline.max(label.baseline);
+        let descent = (self.height - self.baseline).max(label.height - label.baseline);
+        let mut result = MathBox::empty(x + label.width, ascent + descent, ascent);
+        result.blit(self, 0, ascent - self.baseline);
+        result.blit(label, x, ascent - label.baseline);
+        result
     }
 
     /// Get lines as vector of strings
     pub fn to_lines(&self) -> Vec<String> {
-        self.content.iter().map(|row| row.join("")).collect()
+        if self.label.is_some() {
+            return self.place_label(0).to_lines();
+        }
+        (0..self.height)
+            .map(|y| self.row(y).iter().map(Grapheme::as_str).collect())
+            .collect()
     }
 }
 
+impl MathBox {
+    /// Encode the box (cells, styles and label) as plain text, so pre-rendered
+    /// equations can be cached on disk or sent between processes. Read it back
+    /// with [`MathBox::decode`].
+    ///
+    /// The format is a `mathbox WIDTH HEIGHT BASELINE` header, one line per row
+    /// with tab-separated cells, a `style X Y FG BG ADD SUB` line per styled
+    /// cell and, for a labelled box, a `label` line followed by the label.
+    pub fn encode(&self) -> String {
+        let mut out = format!("mathbox {} {} {}\n", self.width, self.height, self.baseline);
+        for y in 0..self.height {
+            let cells: Vec<String> = self.row(y).iter().map(|cell| escape(cell)).collect();
+            out.push_str(&cells.join("\t"));
+            out.push('\n');
+        }
+        for (i, style) in self
+            .styles
+            .iter()
+            .enumerate()
+            .filter(|(_, style)| **style != Style::default())
+        {
+            let color = |c: Option<Color>| c.map_or("-".to_string(), |c| c.to_string());
+            out.push_str(&format!(
+                "style {} {} {} {} {} {}\n",
+                i % self.width,
+                i / self.width,
+                color(style.fg),
+                color(style.bg),
+                style.add_modifier.bits(),
+                style.sub_modifier.bits()
+            ));
+        }
+        if let Some(label) = &self.label {
+            out.push_str("label\n");
+            out.push_str(&label.encode());
+        }
+        out
+    }
+
+    /// Decode a box written by [`MathBox::encode`], or `None` if the text is
+    /// not a valid encoding
+    pub fn decode(text: &str) -> Option<MathBox> {
+        let mut lines = text.lines();
+        let mbox = decode_lines(&mut lines)?;
+        lines.next().is_none().then_some(mbox)
+    }
+}
+
+/// Serialized as its [`MathBox::encode`] text
+#[cfg(feature = "serde")]
+impl serde::Serialize for MathBox {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.encode())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MathBox {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        MathBox::decode(&text).ok_or_else(|| serde::de::Error::custom("invalid MathBox encoding"))
+    }
+}
+
+/// Decode one box from `lines`, leaving anything after it
+fn decode_lines(lines: &mut std::str::Lines) -> Option<MathBox> {
+    let header = lines.next()?.strip_prefix("mathbox ")?;
+    let header: Vec<usize> = header
+        .split(' ')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let [width, height, baseline] = header[..] else {
+        return None;
+    };
+    // The baseline is a row of the box (an empty box keeps baseline 0)
+    if baseline >= height.max(1) {
+        return None;
+    }
+    // Read the rows before allocating, so a header can't claim more cells
+    // than the text holds
+    width.checked_mul(height)?;
+    let rows: Vec<Vec<String>> = lines
+        .by_ref()
+        .take(height)
+        .map(|line| line.split('\t').map(unescape).collect())
+        .collect();
+    if rows.len() != height
+        || rows
+            .iter()
+            .any(|cells| cells.len() != width && !(width == 0 && *cells == [""]))
+    {
+        return None;
+    }
+    let mut mbox = MathBox::empty(width, height, baseline);
+    for (y, cells) in rows.into_iter().enumerate() {
+        for (x, cell) in cells.into_iter().enumerate().take(width) {
+            mbox.set_grapheme(x, y, &cell);
+        }
+    }
+    for line in lines.by_ref() {
+        if line == "label" {
+            let label = decode_lines(lines)?;
+            return Some(mbox.with_label(label));
+        }
+        let fields: Vec<&str> = line.strip_prefix("style ")?.split(' ').collect();
+        let [x, y, fg, bg, add, sub] = fields[..] else {
+            return None;
+        };
+        let color = |c: &str| {
+            if c == "-" {
+                Some(None)
+            } else {
+                Color::from_str(c).ok().map(Some)
+            }
+        };
+        let (x, y): (usize, usize) = (x.parse().ok()?, y.parse().ok()?);
+        if x >= width || y >= height {
+            return None;
+        }
+        let style = Style {
+            fg: color(fg)?,
+            bg: color(bg)?,
+            add_modifier: Modifier::from_bits(add.parse().ok()?)?,
+            sub_modifier: Modifier::from_bits(sub.parse().ok()?)?,
+            ..Style::default()
+        };
+        mbox.set_style(x, y, style);
+    }
+    Some(mbox)
+}
+
+/// Escape a cell so it holds no tabs or line breaks
+fn escape(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(cell: &str) -> String {
+    let mut out = String::new();
+    let mut chars = cell.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        out.push(match chars.next() {
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some(other) => other,
+            None => '\\',
+        });
+    }
+    out
+}
+
 impl Default for MathBox {
     fn default() -> Self {
         Self::empty(0, 1, 0)
     }
 }
 
+/// The rows of the box (with its label placed, if any) joined by newlines,
+/// trailing spaces trimmed
+impl fmt::Display for MathBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.label.is_some() {
+            return self.place_label(0).fmt(f);
+        }
+        for y in 0..self.height {
+            if y > 0 {
+                f.write_str("\n")?;
+            }
+            f.write_str(
+                self.row(y)
+                    .iter()
+                    .map(Grapheme::as_str)
+                    .collect::<String>()
+                    .trim_end(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a block of text, one row per line, with the baseline on the middle
+/// row (the lower middle for an even number of rows)
+///
+/// ```
+/// use tui_math::MathBox;
+///
+/// let fraction: MathBox = "1\n─\n2".parse().unwrap();
+/// assert_eq!((fraction.width, fraction.baseline), (1, 1));
+/// assert_eq!(fraction.to_string(), "1\n─\n2");
+/// ```
+impl FromStr for MathBox {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines: Vec<String> = s.lines().map(String::from).collect();
+        let baseline = lines.len() / 2;
+        Ok(MathBox::from_lines(lines, baseline))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -245,6 +1174,168 @@ mod tests {
         assert_eq!(mb.get_grapheme(0, 0), "T\u{0304}");
     }
 
+    #[test]
+    fn test_overline_underline() {
+        let mb = MathBox::from_text("x+y");
+        let over = mb.overline('_');
+        assert_eq!(over.to_string(), "___\nx+y");
+        assert_eq!(over.baseline, 1);
+        let under = mb.underline('¯');
+        assert_eq!(under.to_string(), "x+y\n¯¯¯");
+        assert_eq!(under.baseline, 0);
+    }
+
+    #[test]
+    fn test_frame() {
+        let framed = MathBox::from_text("E=mc²").frame();
+        assert_eq!(framed.to_string(), "┌─────┐\n│E=mc²│\n└─────┘");
+        assert_eq!(framed.baseline, 1);
+    }
+
+    #[test]
+    fn test_layout_combinators() {
+        let eq = MathBox::hbox(&[
+            MathBox::from_text("E"),
+            MathBox::from_lines(vec!["  ".into(), "=m".into()], 1),
+        ]);
+        let note = MathBox::from_text("mass").overline('‾');
+        let annotated = MathBox::vbox(&[eq, note], Alignment::Right)
+            .pad(Padding::new(1, 0, 0, 1))
+            .frame();
+        assert_eq!(
+            annotated.to_string(),
+            "┌─────┐\n│     │\n│  E=m│\n│ ‾‾‾‾│\n│ mass│\n│     │\n└─────┘"
+        );
+        assert_eq!(annotated.baseline, 2);
+    }
+
+    #[test]
+    fn test_crop_and_slice() {
+        let mb = MathBox::from_lines(vec!["  1  ".into(), "x=─语".into(), "  2  ".into()], 1);
+        let window = mb.crop(Rect::new(1, 1, 3, 5));
+        assert_eq!(
+            (window.to_string(), window.height, window.baseline),
+            ("=─\n 2".to_string(), 2, 0)
+        );
+        assert_eq!(mb.slice_rows(0..1).to_string(), "  1");
+        assert_eq!(mb.slice_rows(0..1).baseline, 0);
+        assert_eq!(mb.slice_cols(2..4).to_string(), "1\n─\n2");
+        assert_eq!(mb.slice_cols(4..5).to_string(), "\n\n");
+    }
+
+    #[test]
+    fn test_blit_modes() {
+        let mut patch = MathBox::from_text("a  b");
+        patch.set_style(1, 0, Style::default().bg(Color::Blue));
+        let blit = |mode| {
+            let mut mb = MathBox::from_text("xxxxx");
+            mb.blit_with(&patch, 1, 0, mode);
+            (mb.to_string(), mb.style(2, 0).bg)
+        };
+        assert_eq!(
+            blit(BlitMode::Opaque),
+            ("xa  b".to_string(), Some(Color::Blue))
+        );
+        assert_eq!(
+            blit(BlitMode::TransparentSpace),
+            ("xaxxb".to_string(), None)
+        );
+        assert_eq!(
+            blit(BlitMode::TransparentEmpty),
+            ("xa xb".to_string(), Some(Color::Blue))
+        );
+    }
+
+    #[test]
+    fn test_cells() {
+        let mut mb = MathBox::from_lines(vec!["T\u{0304}语".into(), "─ x".into()], 1);
+        let cells: Vec<_> = mb.cells().map(|(x, y, g)| (x, y, g.to_string())).collect();
+        assert_eq!(
+            cells[..2],
+            [(0, 0, "T\u{0304}".to_string()), (1, 0, "语".to_string())]
+        );
+        assert_eq!(cells.len(), 5);
+        for (_, _, g) in mb.cells_mut() {
+            if g == "x" {
+                *g = "y".into();
+            }
+        }
+        assert_eq!(mb.get(2, 1), 'y');
+    }
+
+    #[test]
+    fn test_trim_and_shrink() {
+        let mut mb = MathBox::from_lines(
+            vec![
+                "".into(),
+                "  a  ".into(),
+                "".into(),
+                "   b ".into(),
+                "".into(),
+            ],
+            2,
+        );
+        let trimmed = mb.trim();
+        assert_eq!(
+            (trimmed.to_lines(), trimmed.baseline),
+            (vec!["a ".to_string(), "  ".into(), " b".into()], 1)
+        );
+        mb.shrink_to_content();
+        assert_eq!((mb.width, mb.height, mb.baseline), (4, 4, 2));
+        assert_eq!(mb.get(3, 3), 'b');
+        assert_eq!(MathBox::empty(3, 2, 1).trim().width, 0);
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = MathBox::from_text("a + bc");
+        let mut after = MathBox::from_lines(vec!["a − b".into(), " 2".into()], 0);
+        after.set_style(0, 0, Style::default().fg(Color::Red));
+        let changes: Vec<_> = before
+            .diff(&after)
+            .iter()
+            .map(|c| (c.x, c.y, c.grapheme.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            [(0, 0, "a"), (2, 0, "−"), (5, 0, " "), (1, 1, "2")]
+        );
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn test_display_and_from_str() {
+        let mbox: MathBox = "a\n语b  \nc".parse().unwrap();
+        assert_eq!((mbox.width, mbox.height, mbox.baseline), (5, 3, 1));
+        assert_eq!(mbox.get_grapheme(1, 1), "");
+        assert_eq!(format!("{mbox}"), "a\n语b\nc");
+        let labelled = MathBox::from_str("x\ny")
+            .unwrap()
+            .with_label(MathBox::from_text("(1)"));
+        assert_eq!(labelled.to_string(), "x\ny  (1)");
+    }
+
+    #[test]
+    fn test_from_ansi() {
+        let value =
+            MathBox::from_ansi("\x1b[38;2;1;2;3m语\x1b[39;4mx\x1b]8;;http://a\x07 \x1b[0mkg");
+        assert_eq!((value.to_string(), value.width), ("语x kg".to_string(), 6));
+        assert_eq!(value.style(0, 0).fg, Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(
+            value.style(2, 0),
+            Style::default().add_modifier(Modifier::UNDERLINED)
+        );
+        assert_eq!(value.style(4, 0), Style::default());
+
+        let formula = MathBox::hbox(&[
+            MathBox::from_text("g = "),
+            MathBox::from_ansi("\x1b[32m9.81\x1b[m"),
+        ]);
+        assert_eq!(formula.to_string(), "g = 9.81");
+        assert_eq!(formula.style(4, 0).fg, Some(Color::Green));
+        assert_eq!(MathBox::from_ansi("a\nbb\nc").baseline, 1);
+    }
+
     #[test]
     fn test_concat_horizontal() {
         let a = MathBox::from_text("x");
@@ -253,4 +1344,89 @@ mod tests {
         let result = MathBox::concat_horizontal(&[a, b, c]);
         assert_eq!(result.to_string(), "x+y");
     }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let mut mb = MathBox::from_lines(vec!["x\\²".into(), "─语".into()], 0);
+        mb.set_style(
+            0,
+            0,
+            Style::default()
+                .fg(Color::Rgb(1, 2, 3))
+                .add_modifier(Modifier::BOLD),
+        );
+        mb.set_style(1, 1, Style::default().bg(Color::Indexed(42)));
+        let mb = mb.with_label(MathBox::from_text("(1)"));
+
+        let decoded = MathBox::decode(&mb.encode()).unwrap();
+        assert_eq!(decoded.encode(), mb.encode());
+        assert_eq!(decoded.to_lines(), mb.to_lines());
+        assert_eq!(decoded.style(0, 0), mb.style(0, 0));
+        assert_eq!(decoded.style(1, 1).bg, Some(Color::Indexed(42)));
+        assert_eq!(
+            decoded.label().map(MathBox::to_string),
+            Some("(1)".to_string())
+        );
+
+        assert!(MathBox::decode("mathbox 2 1 0\nab\n").is_none());
+        assert!(MathBox::decode("mathbox 4294967296 4294967296 0\n").is_none());
+        assert!(MathBox::decode("mathbox 3000000000 1 0\n").is_none());
+        assert!(MathBox::decode("mathbox 1 1 1\nx\n").is_none());
+        assert!(MathBox::decode("mathbox 0 0 0\n").is_some());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        use serde::de::{value, Deserialize, IntoDeserializer};
+
+        let mb = MathBox::from_lines(vec!["a".into(), "─".into(), "b".into()], 1);
+        let text = mb.encode();
+        let de: value::StrDeserializer<value::Error> = text.as_str().into_deserializer();
+        assert_eq!(MathBox::deserialize(de).unwrap().to_lines(), mb.to_lines());
+
+        let de: value::StrDeserializer<value::Error> = "mathbox 1 1 1\nx\n".into_deserializer();
+        assert!(MathBox::deserialize(de).is_err());
+    }
+
+    #[test]
+    fn test_wrap_at_operators() {
+        let mb = MathBox::from_lines(
+            vec![
+                "    b            ".into(),
+                "a + ─ + c = d + e".into(),
+                "    2            ".into(),
+            ],
+            1,
+        );
+        let wrapped = mb.wrap_at_operators(10, 2);
+        assert_eq!(wrapped.to_string(), "    b\na + ─ + c\n    2\n  = d + e");
+
+        // Terms wider than the space left stay whole, one per line
+        let narrow = mb.wrap_at_operators(4, 2);
+        assert_eq!(
+            narrow.to_string(),
+            "a\n    b\n  + ─\n    2\n  + c\n  = d\n  + e"
+        );
+        assert_eq!(wrapped.baseline, 1);
+        assert_eq!(
+            MathBox::from_text("a + b")
+                .wrap_at_operators(10, 2)
+                .to_string(),
+            "a + b"
+        );
+    }
+
+    #[test]
+    fn test_ellipsize() {
+        let mb = MathBox::from_text("a + bc + d");
+        assert_eq!(mb.ellipsize(8).to_string(), "a + bc…");
+        assert_eq!(mb.ellipsize(5).to_string(), "a…");
+        assert_eq!(mb.ellipsize(10).to_string(), "a + bc + d");
+        let labelled = mb.with_label(MathBox::from_text("(1)"));
+        assert_eq!(
+            labelled.ellipsize(12).place_label(12).to_string(),
+            "a + bc…  (1)"
+        );
+    }
 }
@@ -0,0 +1,104 @@
+//! Rendering options shared by the renderer and the widgets
+
+use crate::CapabilityProfile;
+
+/// Whether math is laid out for display on its own lines or for running
+/// text, as with TeX's `\displaystyle` and `\textstyle`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MathStyle {
+    /// Fractions stacked and big operators (∑, ∫) grown to their operand
+    #[default]
+    Display,
+    /// Single-line fractions written `a/b` and big operators kept one row
+    /// high, as for `\tfrac`, so math fits in a line of text where it can.
+    /// `\dfrac` still stacks.
+    Text,
+}
+
+/// How TeX's thin, medium and thick spaces between atoms (after
+/// punctuation and around big operators, binary operators and relations)
+/// are drawn in cells
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Spacing {
+    /// Only thick spaces, one cell: `f(a,b) = a+b`
+    Tight,
+    /// Medium and thick spaces, one cell each: `f(a,b) = a + b`
+    #[default]
+    Normal,
+    /// Thin and medium spaces one cell, thick spaces two: `f(a, b)  =  a + b`
+    Loose,
+}
+
+/// The pieces tall brackets are drawn from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BracketSet {
+    /// Unicode bracket pieces (`⎛ ⎜ ⎝`)
+    #[default]
+    Unicode,
+    /// ASCII pieces (`/ | \`), for fonts without the bracket pieces
+    Ascii,
+}
+
+/// How accents such as `\hat` and `\vec` are drawn
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AccentStrategy {
+    /// A combining mark on a single-row base (`x̂`)
+    #[default]
+    Combining,
+    /// The accent on its own row above the base
+    Stacked,
+    /// The accent written out by name around the base, as `hat(x)` or
+    /// `vec(v)`, for terminals that draw neither combining marks nor
+    /// stacked accents legibly
+    Ascii,
+}
+
+/// Everything that changes how math is laid out, for
+/// [`MathRenderer::with_options`](crate::MathRenderer::with_options) and the
+/// widgets' `options` setters. Build on the defaults:
+///
+/// ```
+/// use tui_math::{MathRenderer, MathStyle, RendererOptions};
+///
+/// let options = RendererOptions { style: MathStyle::Text, ..RendererOptions::default() };
+/// let renderer = MathRenderer::with_options(options);
+/// assert_eq!(renderer.render_latex(r"\frac{1}{2}").unwrap(), "1/2");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RendererOptions {
+    /// Display or text style (display by default)
+    pub style: MathStyle,
+    /// Use Unicode superscript and subscript characters where they exist
+    /// (on by default)
+    pub unicode_scripts: bool,
+    /// Restrict output to ASCII (see
+    /// [`MathRenderer::ascii_only`](crate::MathRenderer::ascii_only))
+    pub ascii: bool,
+    /// Space around operators
+    pub spacing: Spacing,
+    /// Break expressions wider than this many columns at operators, with
+    /// continuation lines indented two columns
+    pub max_width: Option<usize>,
+    /// The pieces tall brackets are drawn from
+    pub brackets: BracketSet,
+    /// How accents are drawn
+    pub accents: AccentStrategy,
+    /// What the terminal can show; anything it can't is drawn with simpler
+    /// glyphs (full Unicode by default)
+    pub profile: CapabilityProfile,
+}
+
+impl Default for RendererOptions {
+    fn default() -> Self {
+        Self {
+            style: MathStyle::Display,
+            unicode_scripts: true,
+            ascii: false,
+            spacing: Spacing::Normal,
+            max_width: None,
+            brackets: BracketSet::Unicode,
+            accents: AccentStrategy::Combining,
+            profile: CapabilityProfile::FullUnicode,
+        }
+    }
+}
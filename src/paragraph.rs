@@ -0,0 +1,218 @@
+//! Prose with inline `$...$` math, for chat, markdown and note-taking TUIs
+
+use crate::widget::box_to_lines;
+use crate::{MathBox, MathRenderer};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    text::Line,
+    widgets::{Block, Paragraph, Widget},
+};
+
+/// A widget showing text with inline math between `$` signs. Prose wraps at
+/// spaces to the area's width, formulas are kept whole and sit on the text's
+/// baseline, and rows grow to fit tall formulas such as `$\frac{a}{b}$`.
+/// Write `\$` for a literal dollar sign; math that fails to render is shown
+/// as its source.
+#[derive(Clone)]
+pub struct MathParagraph<'a> {
+    text: &'a str,
+    style: Style,
+    block: Option<Block<'a>>,
+    use_unicode_scripts: bool,
+}
+
+impl<'a> MathParagraph<'a> {
+    /// Create a new MathParagraph from text with `$...$` math fragments
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            style: Style::default(),
+            block: None,
+            use_unicode_scripts: true,
+        }
+    }
+
+    /// Set the style for the text and math
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Wrap the widget in a block
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Enable or disable Unicode superscript/subscript characters in the math
+    pub fn use_unicode_scripts(mut self, use_unicode: bool) -> Self {
+        self.use_unicode_scripts = use_unicode;
+        self
+    }
+
+    /// Lay the text out in `width` columns, one box per visual line
+    pub fn layout(&self, width: usize) -> Vec<MathBox> {
+        let renderer = MathRenderer::new().use_unicode_scripts(self.use_unicode_scripts);
+        self.text
+            .split('\n')
+            .flat_map(|line| wrap_units(units(line, &renderer), width))
+            .collect()
+    }
+
+    /// Rows the text takes when wrapped to `width` columns
+    pub fn required_height(&self, width: u16) -> u16 {
+        let rows: usize = self
+            .layout(usize::from(width))
+            .iter()
+            .map(|line| line.height)
+            .sum();
+        u16::try_from(rows).unwrap_or(u16::MAX)
+    }
+}
+
+/// Split one line of text into unbreakable units: runs of prose and math
+/// with no whitespace between them, such as `$x$,`
+fn units(line: &str, renderer: &MathRenderer) -> Vec<Vec<MathBox>> {
+    let mut units: Vec<Vec<MathBox>> = Vec::new();
+    let mut unit: Vec<MathBox> = Vec::new();
+    let mut word = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if chars.peek() == Some(&'$') => word.push(chars.next().unwrap_or('$')),
+            '$' => {
+                let mut latex = String::new();
+                let mut closed = false;
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '\\' if chars.peek() == Some(&'$') => {
+                            latex.push('\\');
+                            latex.push(chars.next().unwrap_or('$'));
+                        }
+                        '$' => {
+                            closed = true;
+                            break;
+                        }
+                        _ => latex.push(ch),
+                    }
+                }
+                let math = closed
+                    .then(|| renderer.render_to_box(&latex).ok())
+                    .flatten();
+                match math {
+                    Some(mbox) => {
+                        if !word.is_empty() {
+                            unit.push(MathBox::from_text(&std::mem::take(&mut word)));
+                        }
+                        unit.push(mbox);
+                    }
+                    None => {
+                        word.push('$');
+                        word.push_str(&latex);
+                        if closed {
+                            word.push('$');
+                        }
+                    }
+                }
+            }
+            _ if ch.is_whitespace() => {
+                if !word.is_empty() {
+                    unit.push(MathBox::from_text(&std::mem::take(&mut word)));
+                }
+                if !unit.is_empty() {
+                    units.push(std::mem::take(&mut unit));
+                }
+            }
+            _ => word.push(ch),
+        }
+    }
+    if !word.is_empty() {
+        unit.push(MathBox::from_text(&word));
+    }
+    if !unit.is_empty() {
+        units.push(unit);
+    }
+    units
+}
+
+/// Greedily fill lines of `width` columns with units separated by spaces. A
+/// unit wider than `width` gets a line of its own and is clipped.
+fn wrap_units(units: Vec<Vec<MathBox>>, width: usize) -> Vec<MathBox> {
+    let mut lines = Vec::new();
+    let mut line: Vec<MathBox> = Vec::new();
+    let mut line_width = 0;
+    for unit in units {
+        let unit_width: usize = unit.iter().map(|b| b.width).sum();
+        if !line.is_empty() && line_width + 1 + unit_width > width {
+            lines.push(MathBox::concat_horizontal(&line));
+            line.clear();
+            line_width = 0;
+        }
+        if !line.is_empty() {
+            line.push(MathBox::from_text(" "));
+            line_width += 1;
+        }
+        line.extend(unit);
+        line_width += unit_width;
+    }
+    lines.push(MathBox::concat_horizontal(&line));
+    lines
+}
+
+impl Widget for MathParagraph<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let width = self.block.as_ref().map_or(area, |b| b.inner(area)).width;
+        let lines: Vec<Line> = self
+            .layout(usize::from(width))
+            .iter()
+            .flat_map(|line| box_to_lines(line, self.style))
+            .collect();
+
+        let mut paragraph = Paragraph::new(lines);
+        if let Some(block) = self.block {
+            paragraph = paragraph.block(block);
+        }
+        paragraph.render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(paragraph: MathParagraph, width: u16, height: u16) -> Vec<String> {
+        let area = Rect::new(0, 0, width, height);
+        let mut buf = Buffer::empty(area);
+        paragraph.render(area, &mut buf);
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| buf[(x, y)].symbol())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_inline_math_on_baseline() {
+        let paragraph = MathParagraph::new(r"half is $\frac{1}{2}$, and \$5 is $x^2$");
+        assert_eq!(
+            rows(paragraph, 40, 3),
+            ["        1", "half is ─, and $5 is x²", "        2"]
+        );
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let paragraph = MathParagraph::new("let $a+b$ be the sum\nof $x$ and $y$ $oops");
+        assert_eq!(
+            rows(paragraph, 12, 5),
+            ["let a + b be", "the sum", "of x and y", "$oops", ""]
+        );
+        assert_eq!(MathParagraph::new("a $b$ c").required_height(3), 2);
+    }
+}
@@ -0,0 +1,376 @@
+//! Rasterizing the layout for graphics output (sixel, terminal image protocols,
+//! block-character scaling)
+//!
+//! Drawing characters (bars, radicals, brackets, arrows) become strokes and
+//! text is drawn with an embedded 5×7 bitmap font. Characters the font doesn't
+//! cover are drawn as hollow boxes.
+
+use crate::canvas_widget::glyph_strokes;
+use crate::unicode_maps::to_ascii;
+use crate::MathBox;
+use ratatui::style::{Color, Style};
+
+/// Pixel geometry of one grid cell
+pub(crate) struct CellSize {
+    width: usize,
+    height: usize,
+    /// Area the 5×7 font is stretched over, at the cell's left edge and centred vertically
+    glyph_width: usize,
+    glyph_height: usize,
+    /// Thickness of drawing strokes
+    stroke: usize,
+}
+
+impl CellSize {
+    /// 12×24 pixel cells with the font at twice its size, for images
+    #[cfg(feature = "sixel")]
+    pub(crate) const IMAGE: CellSize = CellSize {
+        width: 12,
+        height: 24,
+        glyph_width: 10,
+        glyph_height: 14,
+        stroke: 2,
+    };
+
+    /// Cells the pixel size of the terminal's font, with the glyphs and strokes
+    /// in the same proportions as [`CellSize::IMAGE`]
+    #[cfg(feature = "image")]
+    pub(crate) fn font(width: usize, height: usize) -> Self {
+        CellSize {
+            width,
+            height,
+            glyph_width: (width * 5 / 6).max(1),
+            glyph_height: (height * 7 / 12).max(1),
+            stroke: (width / 6).max(1),
+        }
+    }
+
+    /// Cells covering `scale` × `scale` terminal cells of quadrant-block
+    /// pixels, which are twice as tall as they are wide
+    pub(crate) fn quadrants(scale: usize) -> Self {
+        let side = 2 * scale;
+        CellSize {
+            width: side,
+            height: side,
+            glyph_width: (side * 5 / 6).max(1),
+            glyph_height: (side * 7 / 8).max(1),
+            stroke: 1,
+        }
+    }
+}
+
+/// 5×7 glyphs for printable ASCII, one byte per column with the top row in bit 0
+const ASCII_FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5F, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00],
+    [0x14, 0x08, 0x3E, 0x08, 0x14],
+    [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E],
+    [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3C, 0x4A, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1E],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E],
+    [0x7F, 0x49, 0x49, 0x49, 0x36],
+    [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C],
+    [0x7F, 0x49, 0x49, 0x49, 0x41],
+    [0x7F, 0x09, 0x09, 0x09, 0x01],
+    [0x3E, 0x41, 0x49, 0x49, 0x7A],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F],
+    [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01],
+    [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F],
+    [0x7F, 0x04, 0x08, 0x10, 0x7F],
+    [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06],
+    [0x3E, 0x41, 0x51, 0x21, 0x5E],
+    [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01],
+    [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F],
+    [0x3F, 0x40, 0x38, 0x40, 0x3F],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7F, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7E, 0x09, 0x01, 0x02],
+    [0x0C, 0x52, 0x52, 0x52, 0x3E],
+    [0x7F, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7D, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00],
+    [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7C],
+    [0x7C, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20],
+    [0x3C, 0x40, 0x40, 0x20, 0x7C],
+    [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x10, 0x08, 0x08, 0x10, 0x08],
+];
+
+/// 5×7 glyphs for common Greek letters and symbols
+const SYMBOL_FONT: &[(char, [u8; 5])] = &[
+    ('α', [0x38, 0x44, 0x44, 0x38, 0x44]),
+    ('β', [0x7E, 0x49, 0x49, 0x36, 0x00]),
+    ('δ', [0x30, 0x4B, 0x4D, 0x49, 0x30]),
+    ('ε', [0x00, 0x1C, 0x2A, 0x22, 0x00]),
+    ('θ', [0x3E, 0x49, 0x49, 0x49, 0x3E]),
+    ('λ', [0x43, 0x34, 0x08, 0x30, 0x40]),
+    ('μ', [0x7C, 0x20, 0x20, 0x10, 0x3C]),
+    ('π', [0x04, 0x7C, 0x04, 0x7C, 0x04]),
+    ('σ', [0x38, 0x44, 0x44, 0x3C, 0x04]),
+    ('φ', [0x18, 0x24, 0x7E, 0x24, 0x18]),
+    ('ω', [0x3C, 0x40, 0x30, 0x40, 0x3C]),
+    ('Δ', [0x60, 0x58, 0x46, 0x58, 0x60]),
+    ('Σ', [0x63, 0x55, 0x49, 0x41, 0x41]),
+    ('Ω', [0x5C, 0x62, 0x02, 0x62, 0x5C]),
+    ('∞', [0x18, 0x24, 0x18, 0x24, 0x18]),
+    ('∂', [0x30, 0x48, 0x49, 0x49, 0x3E]),
+    ('∈', [0x1C, 0x2A, 0x49, 0x49, 0x41]),
+    ('×', [0x22, 0x14, 0x08, 0x14, 0x22]),
+    ('·', [0x00, 0x00, 0x08, 0x00, 0x00]),
+    ('±', [0x44, 0x44, 0x5F, 0x44, 0x44]),
+    ('≤', [0x44, 0x4A, 0x51, 0x40, 0x40]),
+    ('≥', [0x40, 0x40, 0x51, 0x4A, 0x44]),
+    ('≠', [0x14, 0x34, 0x1C, 0x16, 0x14]),
+];
+
+/// A 5×7 glyph for `ch`: Greek and symbols from the symbol font, math
+/// alphabets, scripts and other symbols through their ASCII spelling
+fn glyph(ch: char) -> Option<[u8; 5]> {
+    if let Some((_, glyph)) = SYMBOL_FONT.iter().find(|(c, _)| *c == ch) {
+        return Some(*glyph);
+    }
+    let mut ascii = if ch.is_ascii() {
+        ch.to_string()
+    } else {
+        to_ascii(&ch.to_string())
+    }
+    .into_bytes();
+    match ascii.pop() {
+        Some(byte @ b' '..=b'~') if ascii.is_empty() => Some(ASCII_FONT[usize::from(byte - b' ')]),
+        _ => None,
+    }
+}
+
+/// A two-dimensional image with a palette index (or nothing) per pixel
+pub(crate) struct Raster {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) pixels: Vec<Option<usize>>,
+    pub(crate) palette: Vec<Color>,
+}
+
+impl Raster {
+    fn set(&mut self, x: usize, y: usize, color: usize) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = Some(color);
+        }
+    }
+
+    /// Palette index for a color, adding it if needed
+    fn color(&mut self, color: Color) -> usize {
+        match self.palette.iter().position(|&c| c == color) {
+            Some(index) => index,
+            None => {
+                self.palette.push(color);
+                self.palette.len() - 1
+            }
+        }
+    }
+
+    /// Draw a line `thickness` pixels thick
+    fn line(&mut self, (x1, y1): (f64, f64), (x2, y2): (f64, f64), thickness: usize, color: usize) {
+        let steps = (x2 - x1).abs().max((y2 - y1).abs()).ceil().max(1.0) as usize;
+        for i in 0..=steps {
+            let t = i as f64 / steps as f64;
+            let x = (x1 + (x2 - x1) * t).round() as usize;
+            let y = (y1 + (y2 - y1) * t).round() as usize;
+            for dx in 0..thickness {
+                for dy in 0..thickness {
+                    self.set(x + dx, y + dy, color);
+                }
+            }
+        }
+    }
+}
+
+/// Rasterize a MathBox (and its label). Text without a color of its own is
+/// drawn in `foreground`.
+pub(crate) fn rasterize(mbox: &MathBox, foreground: Color, cell: &CellSize) -> Raster {
+    let mbox = mbox.place_label(0);
+    let mut raster = Raster {
+        width: mbox.width * cell.width,
+        height: mbox.height * cell.height,
+        pixels: vec![None; mbox.width * cell.width * mbox.height * cell.height],
+        palette: Vec::new(),
+    };
+
+    for row in 0..mbox.height {
+        for col in 0..mbox.width {
+            let grapheme = mbox.get_grapheme(col, row);
+            let Some(ch) = grapheme.chars().next().filter(|&c| c != ' ') else {
+                continue;
+            };
+            let color = raster.color(mbox.style(col, row).fg.unwrap_or(foreground));
+            let (left, top) = (col * cell.width, row * cell.height);
+
+            if let Some(strokes) = glyph_strokes(ch) {
+                // Strokes are in cell units with y up; keep them inside the cell
+                let point = |u: f64, v: f64| {
+                    let x = left as f64 + u * cell.width.saturating_sub(cell.stroke) as f64;
+                    let y = top as f64 + (1.0 - v) * cell.height.saturating_sub(cell.stroke) as f64;
+                    (x, y)
+                };
+                for (x1, y1, x2, y2) in strokes {
+                    raster.line(point(x1, y1), point(x2, y2), cell.stroke, color);
+                }
+                continue;
+            }
+
+            // Glyphs sit on the baseline, centred vertically in the cell
+            let glyph_top = top + (cell.height - cell.glyph_height) / 2;
+            let bitmap = glyph(ch).unwrap_or([0x7F, 0x41, 0x41, 0x41, 0x7F]);
+            for x in 0..cell.glyph_width {
+                let column = bitmap[(2 * x + 1) * 5 / (2 * cell.glyph_width)];
+                for y in (0..cell.glyph_height)
+                    .filter(|y| column & (1 << ((2 * y + 1) * 7 / (2 * cell.glyph_height))) != 0)
+                {
+                    raster.set(left + x, glyph_top + y, color);
+                }
+            }
+        }
+    }
+
+    raster
+}
+
+/// Quadrant-block characters indexed by their pixels: top-left, top-right,
+/// bottom-left and bottom-right in bits 0 to 3
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+/// Scale a MathBox (and its label) up by `scale` in each direction, drawing it
+/// with quadrant-block characters. Each block takes the color of the first
+/// pixel drawn in it; text without a color of its own keeps the default.
+pub(crate) fn scale_box(mbox: &MathBox, scale: usize) -> MathBox {
+    let raster = rasterize(mbox, Color::Reset, &CellSize::quadrants(scale));
+    let (width, height) = (raster.width / 2, raster.height / 2);
+    let mut scaled = MathBox::empty(width, height, mbox.baseline * scale + scale / 2);
+    for y in 0..height {
+        for x in 0..width {
+            let pixels = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .map(|(dx, dy)| raster.pixels[(2 * y + dy) * raster.width + 2 * x + dx]);
+            let index = pixels
+                .iter()
+                .rev()
+                .fold(0, |bits, pixel| bits << 1 | usize::from(pixel.is_some()));
+            if index == 0 {
+                continue;
+            }
+            scaled.set(x, y, QUADRANTS[index]);
+            let color = raster.palette[pixels.iter().flatten().next().copied().unwrap_or(0)];
+            if color != Color::Reset {
+                scaled.set_style(x, y, Style::default().fg(color));
+            }
+        }
+    }
+    scaled
+}
+
+/// RGB for a terminal color, with white for colors without a fixed value
+#[cfg(any(feature = "sixel", feature = "image"))]
+pub(crate) fn rgb(color: Color) -> (u8, u8, u8) {
+    match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Black => (0, 0, 0),
+        Color::Red => (205, 0, 0),
+        Color::Green => (0, 205, 0),
+        Color::Yellow => (205, 205, 0),
+        Color::Blue => (0, 0, 238),
+        Color::Magenta => (205, 0, 205),
+        Color::Cyan => (0, 205, 205),
+        Color::Gray => (229, 229, 229),
+        Color::DarkGray => (127, 127, 127),
+        _ => (255, 255, 255),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glyph() {
+        assert_eq!(glyph('A'), Some([0x7E, 0x11, 0x11, 0x11, 0x7E]));
+        assert_eq!(glyph('𝐀'), glyph('A'));
+        assert_eq!(glyph('²'), glyph('2'));
+        assert_eq!(glyph('π'), Some([0x04, 0x7C, 0x04, 0x7C, 0x04]));
+        assert_eq!(glyph('ℵ'), None);
+    }
+
+    #[test]
+    fn test_scale_box() {
+        let mut mbox = MathBox::from_text("-");
+        mbox.set_style(0, 0, Style::default().fg(Color::Red));
+        let scaled = scale_box(&mbox, 3);
+        assert_eq!((scaled.width, scaled.height, scaled.baseline), (3, 3, 1));
+        // The dash sits on the middle row of the 6×6 pixel cell, in the top half of a block
+        assert_eq!(scaled.to_lines(), vec!["   ", "▀▀▘", "   "]);
+        assert_eq!(scaled.style(0, 1).fg, Some(Color::Red));
+
+        let fraction = scale_box(&MathBox::from_lines(vec!["─".into()], 0), 2);
+        assert_eq!(fraction.to_lines(), vec!["  ", "▀▀"]);
+    }
+}
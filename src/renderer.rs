@@ -1,10 +1,28 @@
 //! MathML to Unicode terminal renderer
 
-use crate::mathbox::MathBox;
-use crate::unicode_maps::{get_greek, get_symbol, to_subscript, to_superscript, BRACKETS};
-use latex2mathml::{latex_to_mathml, DisplayStyle};
+use crate::cache;
+use crate::calc::calc_to_latex;
+use crate::latex;
+use crate::macros::{expand_macros, take_definitions, Macro, MacroSet};
+use crate::mathbox::{MathBox, Semantic};
+use crate::options::{AccentStrategy, BracketSet, MathStyle, RendererOptions, Spacing};
+use crate::unicode_maps::{
+    accent_name, ascii_glyph, atom_space, collapse_primes, from_mathvariant, get_greek, get_symbol,
+    operator_class, stretch_arrow, stretch_vertical, to_ascii, to_mathvariant, to_subscript,
+    to_superscript, AtomSpace, BracketChars, OperatorClass, ASCII_BRACKETS, BRACKETS,
+};
+use crate::widget::{box_to_lines, paint_box};
+use crate::CapabilityProfile;
+use latex2mathml::DisplayStyle;
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Span, Text};
 use roxmltree::{Document, Node};
+use std::collections::HashMap;
 use std::fmt;
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
 
 /// Errors that can occur during math rendering
 #[derive(Debug)]
@@ -12,6 +30,7 @@ pub enum RenderError {
     LatexConversion(String),
     MathMLParse(String),
     InvalidStructure(String),
+    ExpressionParse(String),
 }
 
 impl fmt::Display for RenderError {
@@ -20,117 +39,651 @@ impl fmt::Display for RenderError {
             RenderError::LatexConversion(e) => write!(f, "LaTeX conversion error: {}", e),
             RenderError::MathMLParse(e) => write!(f, "MathML parse error: {}", e),
             RenderError::InvalidStructure(e) => write!(f, "Invalid math structure: {}", e),
+            RenderError::ExpressionParse(e) => write!(f, "Expression parse error: {}", e),
         }
     }
 }
 
 impl std::error::Error for RenderError {}
 
+/// Styles for each kind of token, for syntax-colored math
+/// (see [`MathRenderer::token_styles`]). The default leaves every token unstyled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenStyles {
+    /// Numbers (`<mn>`)
+    pub number: Style,
+    /// Identifiers other than Greek letters and function names (`x`, `y`)
+    pub identifier: Style,
+    /// Operators, relations and separators (`<mo>`)
+    pub operator: Style,
+    /// Greek letters
+    pub greek: Style,
+    /// Fences, brackets and bars, plain or stretched (`(`, `⟨`, `|`)
+    pub delimiter: Style,
+    /// Function names (`sin`, `log`, `lim`)
+    pub function: Style,
+}
+
+impl TokenStyles {
+    /// A ready-made palette: yellow numbers, cyan identifiers, magenta
+    /// operators and delimiters, green Greek letters and blue function names
+    pub fn colored() -> Self {
+        Self {
+            number: Style::default().fg(Color::Yellow),
+            identifier: Style::default().fg(Color::Cyan),
+            operator: Style::default().fg(Color::Magenta),
+            greek: Style::default().fg(Color::Green),
+            delimiter: Style::default().fg(Color::Magenta),
+            function: Style::default().fg(Color::Blue),
+        }
+    }
+
+    /// The style for an operator, or a delimiter if `op` is one
+    fn operator_style(&self, op: &str) -> Style {
+        if is_delimiter(op) {
+            self.delimiter
+        } else {
+            self.operator
+        }
+    }
+}
+
+/// Colors for each kind of token, to syntax-highlight equations in the host
+/// application's palette (see [`MathWidget::theme`](crate::MathWidget::theme)).
+/// Token kinds are as in [`TokenStyles`]; kinds left as `None` keep the
+/// widget's style.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MathTheme {
+    /// Color of numbers
+    pub number: Option<Color>,
+    /// Color of identifiers other than Greek letters and function names
+    pub identifier: Option<Color>,
+    /// Color of operators, relations and separators
+    pub operator: Option<Color>,
+    /// Color of Greek letters
+    pub greek: Option<Color>,
+    /// Color of fences, brackets and bars
+    pub delimiter: Option<Color>,
+    /// Color of function names
+    pub function: Option<Color>,
+}
+
+impl MathTheme {
+    /// The token styles that draw each kind of token in its color
+    pub fn token_styles(&self) -> TokenStyles {
+        let style =
+            |color: Option<Color>| color.map_or(Style::default(), |c| Style::default().fg(c));
+        TokenStyles {
+            number: style(self.number),
+            identifier: style(self.identifier),
+            operator: style(self.operator),
+            greek: style(self.greek),
+            delimiter: style(self.delimiter),
+            function: style(self.function),
+        }
+    }
+}
+
 /// Math renderer that converts LaTeX/MathML to Unicode terminal output
 pub struct MathRenderer {
-    use_unicode_scripts: bool,
+    options: RendererOptions,
+    use_unicode_bold: bool,
+    eqn: bool,
+    action_toggles: HashMap<String, usize>,
+    macro_sets: Vec<MacroSet>,
+    macros: HashMap<String, Macro>,
+    /// Replacement glyphs, keyed by the glyph replaced
+    symbols: HashMap<String, String>,
+    token_styles: TokenStyles,
+    highlights: Vec<(Vec<usize>, Style)>,
+    hidden: Vec<Vec<usize>>,
+    semantics: bool,
 }
 
 impl MathRenderer {
     pub fn new() -> Self {
+        Self::with_options(RendererOptions::default())
+    }
+
+    /// Create a renderer laying math out with `options`
+    pub fn with_options(options: RendererOptions) -> Self {
         Self {
-            use_unicode_scripts: true,
+            options,
+            use_unicode_bold: true,
+            eqn: false,
+            action_toggles: HashMap::new(),
+            macro_sets: Vec::new(),
+            macros: HashMap::new(),
+            symbols: HashMap::new(),
+            token_styles: TokenStyles::default(),
+            highlights: Vec::new(),
+            hidden: Vec::new(),
+            semantics: false,
         }
     }
 
     /// Set whether to use Unicode superscript/subscript characters when possible
     pub fn use_unicode_scripts(mut self, use_unicode: bool) -> Self {
-        self.use_unicode_scripts = use_unicode;
+        self.options.unicode_scripts = use_unicode;
+        self
+    }
+
+    /// The layout options in use
+    pub fn options(&self) -> &RendererOptions {
+        &self.options
+    }
+
+    /// Set whether bold math (`\mathbf`, `\boldsymbol`) uses Unicode bold characters.
+    /// When disabled, plain characters are emitted with a bold style modifier instead,
+    /// for terminals whose fonts don't cover the Mathematical Alphanumeric Symbols block.
+    pub fn use_unicode_bold(mut self, use_unicode: bool) -> Self {
+        self.use_unicode_bold = use_unicode;
+        self
+    }
+
+    /// Set whether output is restricted to ASCII, for log files, plain-text email
+    /// and terminals with broken Unicode fonts: fraction bars are drawn with `-`,
+    /// roots as `sqrt(...)`, scripts as `x^2` and `a_i`, tall delimiters from
+    /// `/ | \` pieces, and Greek letters and symbols are spelled out (`alpha`, `<=`).
+    pub fn ascii_only(mut self, ascii_only: bool) -> Self {
+        self.options.ascii = ascii_only;
+        self
+    }
+
+    /// Set what the terminal can show (see [`CapabilityProfile`]), e.g. from
+    /// [`CapabilityProfile::detect`]: the renderer avoids whatever the
+    /// profile rules out, down to plain ASCII
+    pub fn capability_profile(mut self, profile: CapabilityProfile) -> Self {
+        self.options.profile = profile;
+        self
+    }
+
+    /// Set how accents such as `\hat` and `\vec` are drawn: as combining
+    /// marks, on a row above the base, or written out as `hat(x)` (see
+    /// [`AccentStrategy`])
+    pub fn accents(mut self, strategy: AccentStrategy) -> Self {
+        self.options.accents = strategy;
+        self
+    }
+
+    /// Set whether output mimics the typewriter output of troff's eqn, as a
+    /// retro option for man-page-style documents: ASCII only, as with
+    /// [`ascii_only`](Self::ascii_only), but with scripts stacked above and
+    /// below their base and roots drawn as `\/` under an overbar.
+    pub fn eqn_style(mut self, eqn: bool) -> Self {
+        self.eqn = eqn;
+        self
+    }
+
+    /// Whether output is restricted to ASCII (plain or eqn-style)
+    fn ascii(&self) -> bool {
+        self.options.ascii || self.eqn || self.options.profile == CapabilityProfile::Ascii
+    }
+
+    /// Character for a full-width overline row: `‾`, or `_` in ASCII output
+    fn overline_char(&self) -> char {
+        if self.ascii() {
+            '_'
+        } else {
+            '‾'
+        }
+    }
+
+    /// Highlight a subexpression, e.g. for step-by-step tutoring. `path`
+    /// addresses it in the MathML tree by element index at each level below
+    /// `<math>`: in `\frac{a+1}{b} + c`, `[0]` is the fraction, `[0, 0]` its
+    /// numerator and `[2]` the `c`. `style` is patched over the whole region,
+    /// spaces included. Can be called repeatedly to highlight several parts.
+    pub fn highlight(mut self, path: &[usize], style: Style) -> Self {
+        self.highlights.push((path.to_vec(), style));
+        self
+    }
+
+    /// Leave the subexpression at `path` (as for [`highlight`](Self::highlight))
+    /// blank but keep its space, like `\phantom`, e.g. for parts of a
+    /// derivation not revealed yet
+    pub(crate) fn hide(mut self, path: &[usize]) -> Self {
+        self.hidden.push(path.to_vec());
+        self
+    }
+
+    /// Record which MathML element drew each region of the rendering, and for
+    /// renders from LaTeX which token of the source, in
+    /// [`MathBox::semantics`], for hit testing, selection and accessibility
+    /// output. Off by default; tracing tokens back to the LaTeX converts it
+    /// once more per token.
+    pub fn semantics(mut self, semantics: bool) -> Self {
+        self.semantics = semantics;
+        self
+    }
+
+    /// Set how many times each `<maction actiontype="toggle">` has been toggled,
+    /// keyed by action id (see [`MathWidgetState::toggle`](crate::MathWidgetState::toggle)).
+    /// Each toggle advances the action to its next branch, wrapping around.
+    pub fn action_toggles(mut self, toggles: HashMap<String, usize>) -> Self {
+        self.action_toggles = toggles;
+        self
+    }
+
+    /// Enable an optional macro vocabulary, such as [`MacroSet::Physics`]
+    /// (`\dv{f}{x}`, `\abs{x}`, ...). Its macros are expanded before conversion.
+    pub fn with_macro_set(mut self, set: MacroSet) -> Self {
+        if !self.macro_sets.contains(&set) {
+            self.macro_sets.push(set);
+        }
+        self
+    }
+
+    /// Set the styles given to numbers, identifiers, operators and Greek letters.
+    /// They show in widgets and [`render_latex_styled`](Self::render_latex_styled).
+    pub fn token_styles(mut self, styles: TokenStyles) -> Self {
+        self.token_styles = styles;
+        self
+    }
+
+    /// Define macros for every render from a preamble of `\newcommand`,
+    /// `\renewcommand` and `\DeclareMathOperator` definitions, such as
+    /// `\newcommand{\R}{\mathbb{R}}`. Definitions can also appear in the input itself.
+    pub fn with_preamble(mut self, preamble: &str) -> Self {
+        take_definitions(preamble, &mut self.macros);
+        self
+    }
+
+    /// Register a macro expanded on every render: `name` (with or without the
+    /// backslash) is replaced by `expansion`, whose parameters `#1` to `#9` take
+    /// the macro's `n_args` arguments. Redefining a macro replaces it.
+    ///
+    /// ```
+    /// # use tui_math::MathRenderer;
+    /// let mut renderer = MathRenderer::new();
+    /// renderer.define_macro("avg", r"\langle #1 \rangle", 1);
+    /// assert_eq!(renderer.render_latex(r"\avg{x}").unwrap(), "⟨x⟩");
+    /// ```
+    pub fn define_macro(&mut self, name: &str, expansion: &str, n_args: usize) {
+        let name = name.trim().trim_start_matches('\\');
+        self.macros
+            .insert(name.to_string(), Macro::new(expansion, n_args));
+    }
+
+    /// Draw the symbol a command produces as `glyph` instead, e.g. `φ` for
+    /// `\phi` where a font's `ϕ` is hard to tell apart, or an ASCII substitute
+    /// such as `"phi"`. The symbol is replaced wherever it appears, including
+    /// typed directly or produced by another command. A command the renderer
+    /// doesn't know is added as with [`add_symbol`](Self::add_symbol).
+    ///
+    /// ```
+    /// # use tui_math::MathRenderer;
+    /// let renderer = MathRenderer::new().override_symbol("phi", "φ").override_symbol("infty", "inf");
+    /// assert_eq!(renderer.render_latex(r"\phi \to \infty").unwrap(), "φ → inf");
+    /// ```
+    pub fn override_symbol(mut self, command: &str, glyph: &str) -> Self {
+        let command = command.trim().trim_start_matches('\\');
+        match command_glyph(command) {
+            Some(default) => {
+                self.symbols.insert(default, glyph.to_string());
+                self
+            }
+            None => self.add_symbol(command, glyph),
+        }
+    }
+
+    /// Add a command producing `glyph`, for symbols the renderer has no
+    /// command for. The glyph is drawn like an identifier; it is read as
+    /// LaTeX, so characters such as `{` and `^` need escaping.
+    ///
+    /// ```
+    /// # use tui_math::MathRenderer;
+    /// let renderer = MathRenderer::new().add_symbol("degC", "℃");
+    /// assert_eq!(renderer.render_latex(r"T = 20\degC").unwrap(), "T = 20℃");
+    /// ```
+    pub fn add_symbol(mut self, command: &str, glyph: &str) -> Self {
+        self.define_macro(command, &format!("{{{}}}", glyph), 0);
         self
     }
 
     /// Render LaTeX math to Unicode string
     pub fn render_latex(&self, latex: &str) -> Result<String, RenderError> {
-        let mathml = latex_to_mathml(latex, DisplayStyle::Inline)
-            .map_err(|e| RenderError::LatexConversion(e.to_string()))?;
-        self.render_mathml(&mathml)
+        Ok(self.render_to_box(latex)?.to_string())
+    }
+
+    /// Render LaTeX math to a Unicode string and its baseline row (0 is the
+    /// top line), to line up ordinary text composed next to it
+    ///
+    /// ```
+    /// # use tui_math::MathRenderer;
+    /// let (math, baseline) = MathRenderer::new().render_with_baseline(r"\frac{a}{b}").unwrap();
+    /// assert_eq!(math.lines().nth(baseline), Some("─"));
+    /// ```
+    pub fn render_with_baseline(&self, latex: &str) -> Result<(String, usize), RenderError> {
+        let math_box = self.render_to_box(latex)?.place_label(0);
+        Ok((math_box.to_string(), math_box.baseline))
+    }
+
+    /// Render LaTeX math to ratatui [`Text`], with each token carrying its style
+    /// from [`token_styles`](Self::token_styles)
+    pub fn render_latex_styled(&self, latex: &str) -> Result<Text<'static>, RenderError> {
+        let math_box = self.render_to_box(latex)?;
+        Ok(Text::from(box_to_lines(&math_box, Style::default())))
+    }
+
+    /// Render LaTeX math to styled spans for splicing into an existing
+    /// [`Line`](ratatui::text::Line) next to other text, such as labels or
+    /// units. Each cell's style is patched onto `style`. Only expressions
+    /// that fit on one row can be rendered this way; taller ones (e.g. a
+    /// stacked fraction) are an [`RenderError::InvalidStructure`].
+    pub fn render_latex_spans(
+        &self,
+        latex: &str,
+        style: Style,
+    ) -> Result<Vec<Span<'static>>, RenderError> {
+        let math_box = self.render_to_box(latex)?.place_label(0);
+        if math_box.height > 1 {
+            return Err(RenderError::InvalidStructure(format!(
+                "expression needs {} rows, spans must fit on one",
+                math_box.height
+            )));
+        }
+        Ok(box_to_lines(&math_box, style)
+            .pop()
+            .map_or_else(Vec::new, |line| line.spans))
+    }
+
+    /// Render LaTeX math to a sixel image, drawing text without a color of its
+    /// own in `foreground`. Print the result to a terminal with sixel support.
+    #[cfg(feature = "sixel")]
+    pub fn render_latex_sixel(
+        &self,
+        latex: &str,
+        foreground: Color,
+    ) -> Result<String, RenderError> {
+        Ok(crate::sixel::box_to_sixel(
+            &self.render_to_box(latex)?,
+            foreground,
+        ))
+    }
+
+    /// Render LaTeX math to an SVG document laid out like the terminal output
+    #[cfg(feature = "svg")]
+    pub fn render_latex_svg(&self, latex: &str) -> Result<String, RenderError> {
+        Ok(crate::svg::box_to_svg(&self.render_to_box(latex)?))
+    }
+
+    /// Render a calculator-style expression such as `x^2/(y+1) + sqrt(2)`, for
+    /// users who don't write LaTeX (see [`calc_to_latex`](crate::calc_to_latex))
+    pub fn render_calc(&self, expression: &str) -> Result<String, RenderError> {
+        self.render_latex(&calc_to_latex(expression)?)
     }
 
     /// Render MathML to Unicode string
     pub fn render_mathml(&self, mathml: &str) -> Result<String, RenderError> {
-        let doc = Document::parse(mathml)
-            .map_err(|e| RenderError::MathMLParse(e.to_string()))?;
-        let root = doc.root_element();
-        let math_box = self.process_element(&root)?;
-        Ok(math_box.to_string())
+        Ok(self.render_mathml_to_box(mathml)?.to_string())
     }
 
-    /// Render to MathBox (for advanced usage)
+    /// Render to MathBox (for advanced usage). With the render cache enabled
+    /// (see [`set_render_cache_capacity`](crate::set_render_cache_capacity)),
+    /// repeated renders of the same input and options are served from it.
     pub fn render_to_box(&self, latex: &str) -> Result<MathBox, RenderError> {
-        let mathml = latex_to_mathml(latex, DisplayStyle::Inline)
-            .map_err(|e| RenderError::LatexConversion(e.to_string()))?;
-        let doc = Document::parse(&mathml)
-            .map_err(|e| RenderError::MathMLParse(e.to_string()))?;
+        if !cache::enabled() {
+            return self.render_latex_box(latex);
+        }
+        let key = (latex.to_string(), self.fingerprint());
+        if let Some(mbox) = cache::get(&key) {
+            return Ok(mbox);
+        }
+        let mbox = self.render_latex_box(latex)?;
+        cache::insert(key, mbox.clone());
+        Ok(mbox)
+    }
+
+    fn render_latex_box(&self, latex: &str) -> Result<MathBox, RenderError> {
+        let mathml = self.latex_to_mathml(latex)?;
+        let mut mbox = self.render_mathml_to_box(&mathml)?;
+        if self.semantics {
+            self.trace_latex(latex, &mathml, &mut mbox);
+        }
+        Ok(mbox)
+    }
+
+    /// Fill in the LaTeX spans of the token regions recorded in `mbox`
+    fn trace_latex(&self, latex: &str, mathml: &str, mbox: &mut MathBox) {
+        let spans = self.token_spans(latex, mathml);
+        for semantic in mbox.semantics_mut() {
+            semantic.latex = spans.get(&semantic.path).cloned();
+        }
+    }
+
+    /// Every option that affects rendering, as a string for cache keys
+    pub(crate) fn fingerprint(&self) -> String {
+        let mut toggles: Vec<_> = self.action_toggles.iter().collect();
+        toggles.sort();
+        let mut macros: Vec<_> = self.macros.iter().collect();
+        macros.sort_by(|a, b| a.0.cmp(b.0));
+        let mut symbols: Vec<_> = self.symbols.iter().collect();
+        symbols.sort();
+        format!(
+            "{:?}",
+            (
+                &self.options,
+                (self.use_unicode_bold, self.eqn),
+                toggles,
+                &self.macro_sets,
+                macros,
+                symbols,
+                self.token_styles,
+                &self.highlights,
+                &self.hidden,
+                self.semantics,
+            )
+        )
+    }
+
+    /// Render LaTeX straight into a region of a ratatui [`Buffer`], for custom
+    /// widgets and overlays. Output is clipped to `area`, blank cells keep
+    /// whatever the buffer already holds, and each cell's own style is patched
+    /// onto `style`. A `\tag` label is placed flush right in `area`.
+    pub fn render_to_buffer(
+        &self,
+        latex: &str,
+        area: Rect,
+        buf: &mut Buffer,
+        style: Style,
+    ) -> Result<(), RenderError> {
+        let math_box = self.render_to_box(latex)?;
+        paint_box(&math_box.place_label(area.width as usize), area, buf, style);
+        Ok(())
+    }
+
+    /// Render MathML to MathBox
+    pub fn render_mathml_to_box(&self, mathml: &str) -> Result<MathBox, RenderError> {
+        let doc = Document::parse(mathml).map_err(|e| RenderError::MathMLParse(e.to_string()))?;
         let root = doc.root_element();
-        self.process_element(&root)
+        let mut math_box = self.process_element(&root)?;
+        if self.ascii() {
+            asciify(&mut math_box);
+        } else if !self.options.profile.supports_combining() {
+            degrade(&mut math_box, self.options.profile);
+        }
+        if let Some(width) = self.options.max_width {
+            math_box = math_box.wrap_at_operators(width, 2);
+        }
+        Ok(math_box)
+    }
+
+    /// Render several equations stacked, padded so that their first top-level
+    /// relation (`=`, `≤`, `→`, ...) lines up in a single column.
+    /// Equations without a relation are right-aligned against that column.
+    pub fn render_aligned(&self, equations: &[&str]) -> Result<String, RenderError> {
+        let mut table = String::from(
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML"><mtable columnalign="right left" columnspacing="0em">"#,
+        );
+        for latex in equations {
+            let mathml = self.latex_to_mathml(latex)?;
+            let doc =
+                Document::parse(&mathml).map_err(|e| RenderError::MathMLParse(e.to_string()))?;
+
+            // Look through rows that only wrap another row
+            let mut row = doc.root_element();
+            let mut children: Vec<_> = row.children().filter(|n| n.is_element()).collect();
+            while let [only] = children[..] {
+                if only.tag_name().name() != "mrow" {
+                    break;
+                }
+                row = only;
+                children = row.children().filter(|n| n.is_element()).collect();
+            }
+
+            let split = children
+                .iter()
+                .position(|n| n.tag_name().name() == "mo" && is_relation(&self.get_text_content(n)))
+                .unwrap_or(children.len());
+            let cell =
+                |nodes: &[Node]| nodes.iter().map(|n| &mathml[n.range()]).collect::<String>();
+            table.push_str(&format!(
+                "<mtr><mtd>{}</mtd><mtd>{}</mtd></mtr>",
+                cell(&children[..split]),
+                cell(&children[split..])
+            ));
+        }
+        table.push_str("</mtable></math>");
+        self.render_mathml(&table)
+    }
+
+    /// Convert LaTeX to the intermediate MathML this renderer lays out, with
+    /// user macros and enabled macro sets expanded. Math is inline unless the
+    /// input is wrapped in display delimiters (`$$...$$`, `\[...\]`).
+    ///
+    /// ```
+    /// # use tui_math::MathRenderer;
+    /// let mathml = MathRenderer::new().latex_to_mathml("x^2").unwrap();
+    /// assert!(mathml.contains("<msup>"));
+    /// ```
+    pub fn latex_to_mathml(&self, latex: &str) -> Result<String, RenderError> {
+        let mut macros = self.macros.clone();
+        let source = take_definitions(latex, &mut macros);
+        let expanded = self
+            .macro_sets
+            .iter()
+            .try_fold(expand_macros(&source, &macros)?, |latex, set| {
+                set.expand(&latex)
+            })?;
+        latex::to_mathml(&expanded, DisplayStyle::Inline)
+    }
+
+    /// MathML index path (as for [`highlight`](Self::highlight)) of the token
+    /// at byte range `span` of `latex`, found by swapping the token for
+    /// [`TOKEN_MARK`](latex::TOKEN_MARK) and looking for it in the converted
+    /// MathML
+    pub(crate) fn token_path(&self, latex: &str, span: (usize, usize)) -> Option<Vec<usize>> {
+        let mark = latex::TOKEN_MARK.to_string();
+        let marked = format!("{}{}{}", latex.get(..span.0)?, mark, latex.get(span.1..)?);
+        let mathml = self.latex_to_mathml(&marked).ok()?;
+        let doc = Document::parse(&mathml).ok()?;
+        let node = doc
+            .descendants()
+            .find(|n| n.is_element() && n.text() == Some(mark.as_str()))?;
+        Some(node_path(&node))
+    }
+
+    /// The LaTeX span of each token of `latex`, by the MathML index path of
+    /// the token element it renders as in `mathml` (its conversion). A token
+    /// replaced by a command with arguments (`\frac`) changes the structure,
+    /// so only paths that lead to a token in the real MathML are kept.
+    pub(crate) fn token_spans(
+        &self,
+        latex: &str,
+        mathml: &str,
+    ) -> HashMap<Vec<usize>, Range<usize>> {
+        let Ok(doc) = Document::parse(mathml) else {
+            return HashMap::new();
+        };
+        let mut spans = HashMap::new();
+        for (start, end) in latex::tokens(latex) {
+            let Some(path) = self.token_path(latex, (start, end)) else {
+                continue;
+            };
+            if node_at(&doc, &path).is_some_and(|node| is_token(&node)) {
+                spans.entry(path).or_insert(start..end);
+            }
+        }
+        spans
+    }
+
+    /// MathML index path of the token element (`<mi>`, `<mn>`, `<mo>`,
+    /// `<mtext>`, `<ms>`) drawn at cell (`x`, `y`) of the rendering of
+    /// `mathml`, with any label placed as by `place_label(0)`
+    pub(crate) fn token_at(self, mathml: &str, x: usize, y: usize) -> Option<Vec<usize>> {
+        let traced = self
+            .semantics(true)
+            .render_mathml_to_box(mathml)
+            .ok()?
+            .place_label(0);
+        let token = traced.semantics_at(x, y).find(|s| s.is_token())?;
+        Some(token.path.clone())
     }
 
     fn process_element(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let mut result = self.process_node(node)?;
+        if !self.hidden.is_empty() && self.hidden.contains(&node_path(node)) {
+            return Ok(MathBox::empty(result.width, result.height, result.baseline));
+        }
+        if self.semantics {
+            let size = |n: usize| u16::try_from(n).unwrap_or(u16::MAX);
+            result.add_semantic(Semantic {
+                area: Rect::new(0, 0, size(result.width), size(result.height)),
+                path: node_path(node),
+                element: node.tag_name().name().to_string(),
+                mathml: node.range(),
+                latex: None,
+            });
+        }
+        if !self.highlights.is_empty() {
+            let path = node_path(node);
+            for (target, style) in &self.highlights {
+                if *target == path {
+                    result.patch_style(*style);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn process_node(&self, node: &Node) -> Result<MathBox, RenderError> {
         let tag = node.tag_name().name();
 
         match tag {
-            "math" | "mrow" | "mstyle" | "mpadded" | "mphantom" => {
-                self.process_row(node)
-            }
-            "mi" | "mn" | "mtext" => {
-                self.process_text(node)
-            }
-            "mo" => {
-                self.process_operator(node)
-            }
-            "msup" => {
-                self.process_superscript(node)
-            }
-            "msub" => {
-                self.process_subscript(node)
-            }
-            "msubsup" => {
-                self.process_subsup(node)
-            }
-            "mfrac" => {
-                self.process_fraction(node)
-            }
-            "msqrt" => {
-                self.process_sqrt(node)
-            }
-            "mroot" => {
-                self.process_nthroot(node)
-            }
-            "mover" => {
-                self.process_over(node)
-            }
-            "munder" => {
-                self.process_under(node)
-            }
-            "munderover" => {
-                self.process_underover(node)
-            }
-            "mtable" => {
-                self.process_table(node)
-            }
-            "mtr" => {
-                self.process_table_row(node)
-            }
-            "mtd" => {
-                self.process_row(node)
-            }
-            "mfenced" => {
-                self.process_fenced(node)
-            }
-            "menclose" => {
-                self.process_row(node) // Simplified
-            }
+            "math" | "mrow" | "mstyle" | "mphantom" => self.process_row(node),
+            "mpadded" => self.process_padded(node),
+            "mi" | "mn" => self.process_text(node),
+            "mtext" => self.process_mtext(node),
+            "ms" => self.process_string_literal(node),
+            "mo" => self.process_operator(node),
+            "msup" => self.process_superscript(node),
+            "msub" => self.process_subscript(node),
+            "msubsup" => self.process_subsup(node),
+            "mfrac" => self.process_fraction(node),
+            "msqrt" => self.process_sqrt(node),
+            "mroot" => self.process_nthroot(node),
+            "mover" => self.process_over(node),
+            "munder" => self.process_under(node),
+            "munderover" => self.process_underover(node),
+            "mmultiscripts" => self.process_multiscripts(node),
+            "mtable" => self.process_table(node),
+            "mtr" => self.process_table_row(node),
+            "mtd" => self.process_row(node),
+            "mfenced" => self.process_fenced(node),
+            "menclose" => self.process_enclose(node),
+            "merror" => self.process_error(node),
+            "maction" => self.process_action(node),
             "mspace" => {
-                Ok(MathBox::from_text(" "))
+                let width = node
+                    .attribute("width")
+                    .and_then(length_to_cells)
+                    .unwrap_or(1);
+                Ok(MathBox::empty(width, 1, 0))
             }
             "semantics" => {
                 // Process first child only
-                if let Some(child) = node.children().filter(|n| n.is_element()).next() {
+                if let Some(child) = node.children().find(|n| n.is_element()) {
                     self.process_element(&child)
                 } else {
                     Ok(MathBox::empty(0, 1, 0))
@@ -166,123 +719,607 @@ impl MathRenderer {
             return Ok(MathBox::empty(0, 1, 0));
         }
 
-        let mut boxes = Vec::new();
-        let mut prev_multiline = false;
+        // \left ... \right pairs arrive as a row bracketed by stretchy prefix/postfix operators
+        let n = child_nodes.len();
+        if n >= 2 && is_fence(&child_nodes[0], "prefix") && is_fence(&child_nodes[n - 1], "postfix")
+        {
+            let open = self.get_text_content(&child_nodes[0]);
+            let close = self.get_text_content(&child_nodes[n - 1]);
+            let inner = self.layout_fenced_row(&child_nodes[1..n - 1], add_spacing)?;
+            return Ok(self.fence(&open, &close, inner));
+        }
 
-        for (i, child) in child_nodes.iter().enumerate() {
-            let child_box = self.process_element(child)?;
-            let is_multiline = child_box.height > 1;
+        self.layout_row(&child_nodes, add_spacing)
+    }
 
-            // Add spacing between multi-line elements
-            if add_spacing && i > 0 && (prev_multiline || is_multiline) {
-                boxes.push(MathBox::from_text(" "));
+    /// Lay out the contents of a fenced row, stretching middle delimiters
+    /// (such as the bar in `⟨ψ|φ⟩`) to the height of the whole row
+    fn layout_fenced_row(
+        &self,
+        child_nodes: &[Node],
+        add_spacing: bool,
+    ) -> Result<MathBox, RenderError> {
+        let mut segments = Vec::new();
+        let mut bars = Vec::new();
+        let mut start = 0;
+        for (i, child) in child_nodes.iter().enumerate() {
+            if is_fence(child, "infix") {
+                segments.push(self.layout_row(&child_nodes[start..i], add_spacing)?);
+                bars.push(self.get_text_content(child));
+                start = i + 1;
+            }
+        }
+        if bars.is_empty() {
+            return self.layout_row(child_nodes, add_spacing);
+        }
+        segments.push(self.layout_row(&child_nodes[start..], add_spacing)?);
+
+        let ascent = segments.iter().map(|s| s.baseline).max().unwrap_or(0);
+        let descent = segments
+            .iter()
+            .map(|s| s.height - s.baseline)
+            .max()
+            .unwrap_or(1);
+        let height = ascent + descent;
+        let mut parts = Vec::new();
+        for (segment, bar) in segments
+            .into_iter()
+            .zip(bars.iter().map(Some).chain([None]))
+        {
+            parts.push(segment);
+            if let Some(bar) = bar {
+                let chars = if matches!(bar.as_str(), ")" | "]" | "}" | "⟩") {
+                    self.brackets().get_right(bar, height)
+                } else {
+                    self.brackets().get_left(bar, height)
+                };
+                let lines = chars.iter().map(|c| c.to_string()).collect();
+                let mut bar_box = MathBox::from_lines(lines, ascent);
+                bar_box.patch_style(self.token_styles.delimiter);
+                parts.push(bar_box);
             }
+        }
+        Ok(MathBox::concat_horizontal(&parts))
+    }
 
-            // Add spacing around binary operators in row context (not in compact mode)
-            if add_spacing && child.tag_name().name() == "mo" {
-                let op = self.get_text_content(child);
-                let is_first = i == 0;
-                let is_binary_op = !is_first && matches!(op.as_str(), "+" | "-" | "±" | "∓");
-                let is_relation = matches!(
-                    op.as_str(),
-                    "=" | "≤" | "≥" | "≠" | "≈" | "≡" | "→" | "⇒" | "⟹" | "×" | "÷" | "·"
-                );
+    /// Lay out a sequence of sibling elements horizontally
+    fn layout_row(&self, child_nodes: &[Node], add_spacing: bool) -> Result<MathBox, RenderError> {
+        if child_nodes.is_empty() {
+            return Ok(MathBox::empty(0, 1, 0));
+        }
+        // A lone child passes through untouched, keeping any label it carries
+        if let [child] = child_nodes {
+            return self.process_element(child);
+        }
 
-                if is_binary_op || is_relation {
-                    // Don't add extra space if we just added one for multiline
-                    if !prev_multiline && !is_multiline {
-                        boxes.push(MathBox::from_text(" "));
+        let mut boxes = Vec::new();
+        let mut prev_multiline = false;
+        // Text runs that already start/end with a space need no extra operator padding
+        let text_spaced = |i: usize, at_end: bool| {
+            child_nodes.get(i).is_some_and(|n| {
+                let text = n.text().unwrap_or("");
+                n.tag_name().name() == "mtext"
+                    && if at_end {
+                        text.ends_with(' ')
+                    } else {
+                        text.starts_with(' ')
                     }
-                    boxes.push(child_box);
-                    boxes.push(MathBox::from_text(" "));
-                    prev_multiline = is_multiline;
-                    continue;
+            })
+        };
+
+        let mut items = self.attach_floating_scripts(child_nodes)?;
+        self.stretch_big_operators(child_nodes, &mut items)?;
+        self.stretch_operators(child_nodes, &mut items);
+        // The previous item's node index, class (deciding whether a binary
+        // operator is unary and the space before the next item) and any
+        // explicit space after it. A row starts and ends as if next to an
+        // ordinary atom, so `= b` in an aligned column keeps its space.
+        let mut prev: Option<(usize, OperatorClass, Option<usize>)> = None;
+        let mut trailing = 0;
+        for (i, child_box) in items {
+            let child = &child_nodes[i];
+            let is_multiline = child_box.height > 1;
+            let (class, lspace, rspace) = if child.tag_name().name() == "mo" {
+                self.operator_spacing(child, prev.map(|(_, class, _)| class))
+            } else {
+                // Scripted operators (∑ with limits) keep the class of their base
+                let base = child
+                    .first_element_child()
+                    .filter(|_| is_scripted(child) || is_limits(child));
+                let class = match base {
+                    Some(base) if base.tag_name().name() == "mo" => {
+                        operator_class(&self.get_text_content(&base))
+                    }
+                    _ => OperatorClass::Ordinary,
+                };
+                (class, None, None)
+            };
+
+            if add_spacing {
+                let (prev_class, prev_rspace) =
+                    prev.map_or((OperatorClass::Ordinary, None), |(_, c, r)| (c, r));
+                let mut gap = match (prev_rspace, lspace) {
+                    (None, None) => self.space_cells(atom_space(prev_class, class)),
+                    (r, l) => r.unwrap_or(0) + l.unwrap_or(0),
+                };
+                // Leave spacing next to text runs to the text itself
+                if prev.is_some_and(|(j, _, _)| text_spaced(j, true)) || text_spaced(i, false) {
+                    gap = 0;
+                }
+                // Keep multi-line elements apart
+                if prev.is_some() && (prev_multiline || is_multiline) {
+                    gap = gap.max(1);
                 }
+                // A row's first item only gets space before it if it's an operator
+                if prev.is_some() || child.tag_name().name() == "mo" {
+                    boxes.push(MathBox::empty(gap, 1, 0));
+                }
+                trailing = match rspace {
+                    _ if child.tag_name().name() != "mo" => 0,
+                    Some(rspace) => rspace,
+                    None => self.space_cells(atom_space(class, OperatorClass::Ordinary)),
+                };
             }
             boxes.push(child_box);
+            prev = Some((i, class, rspace));
             prev_multiline = is_multiline;
         }
+        boxes.push(MathBox::empty(trailing, 1, 0));
 
         Ok(MathBox::concat_horizontal(&boxes))
     }
 
-    fn process_text(&self, node: &Node) -> Result<MathBox, RenderError> {
-        let text = self.get_text_content(node);
+    /// Process the children of a row, attaching scripts written on empty bases
+    /// (`{}^{a}T_{b}{}^{c}`) to their neighbour as tensor-style pre- and postscripts.
+    /// Returns the index of each resulting item's base node with its box.
+    fn attach_floating_scripts(
+        &self,
+        child_nodes: &[Node],
+    ) -> Result<Vec<(usize, MathBox)>, RenderError> {
+        let n = child_nodes.len();
+        let floating = |i: usize| {
+            child_nodes
+                .get(i)
+                .is_some_and(|node| is_floating_script(node))
+        };
+        let mut items = Vec::new();
+        let mut i = 0;
 
-        // Handle Greek letters and special identifiers
-        if let Some(greek) = get_greek(&text) {
-            return Ok(MathBox::from_text(&greek.to_string()));
+        while i < n {
+            let pre_start = i;
+            while floating(i) {
+                i += 1;
+            }
+            let base = child_nodes
+                .get(i)
+                .filter(|node| node.tag_name().name() != "mo");
+            let Some(base) = base else {
+                // Nothing to attach to: leave the scripts as they are
+                for (j, node) in child_nodes.iter().enumerate().take(i + 1).skip(pre_start) {
+                    items.push((j, self.process_element(node)?));
+                }
+                i += 1;
+                continue;
+            };
+            let base_index = i;
+            i += 1;
+            let post_start = i;
+            while floating(i) {
+                i += 1;
+            }
+            if pre_start == base_index && post_start == i {
+                items.push((base_index, self.process_element(base)?));
+                continue;
+            }
+
+            let mut pre = Vec::new();
+            for node in &child_nodes[pre_start..base_index] {
+                pre.push(self.script_pair(node)?.1);
+            }
+            let (base_box, own) = if is_scripted(base) {
+                self.script_pair(base)?
+            } else {
+                (self.process_element(base)?, (None, None))
+            };
+            let mut post = vec![own];
+            for node in &child_nodes[post_start..i] {
+                post.push(self.script_pair(node)?.1);
+            }
+            items.push((base_index, multiscripts(base_box, &pre, &post)));
         }
 
-        Ok(MathBox::from_text(&text))
+        Ok(items)
     }
 
-    fn process_operator(&self, node: &Node) -> Result<MathBox, RenderError> {
-        let text = self.get_text_content(node);
-
-        // Handle special operators
-        let rendered = match text.as_str() {
-            "∑" | "∏" | "∫" | "∬" | "∭" | "∮" | "⋃" | "⋂" => {
-                // Big operators - keep as is
-                text
-            }
-            _ => {
-                // Check if it's a LaTeX command
-                if text.starts_with('\\') {
-                    let cmd = &text[1..];
-                    if let Some(sym) = get_symbol(cmd) {
-                        sym.to_string()
-                    } else if let Some(greek) = get_greek(cmd) {
-                        greek.to_string()
-                    } else {
-                        text
-                    }
-                } else {
-                    text
+    /// Redraw big operators in front of a multi-line operand, which runs up to the
+    /// next relation or big operator: integral signs become tall signs (⌠ ⎮ ⌡)
+    /// spanning the integrand, and ∑, ∏, ∐ are drawn large around its baseline.
+    /// Text style keeps them one row high.
+    fn stretch_big_operators(
+        &self,
+        child_nodes: &[Node],
+        items: &mut [(usize, MathBox)],
+    ) -> Result<(), RenderError> {
+        if self.options.style == MathStyle::Text {
+            return Ok(());
+        }
+        for k in 0..items.len() {
+            let node = &child_nodes[items[k].0];
+            let Some((sign, under, over)) = self.big_operator(node) else {
+                continue;
+            };
+            let operand = items[k + 1..].iter().take_while(|(i, _)| {
+                let next = &child_nodes[*i];
+                let is_relation_op =
+                    next.tag_name().name() == "mo" && is_relation(&self.get_text_content(next));
+                !is_relation_op && self.big_operator(next).is_none()
+            });
+            let (ascent, descent) = operand.fold((0, 0), |(a, d), (_, b)| {
+                (a.max(b.baseline), d.max(b.height - b.baseline - 1))
+            });
+
+            if matches!(sign.as_str(), "∫" | "∬" | "∭" | "∮") {
+                if ascent + descent == 0 {
+                    continue;
                 }
+                let under = under.map(|n| self.process_script(&n)).transpose()?;
+                let over = over.map(|n| self.process_script(&n)).transpose()?;
+                items[k].1 = tall_integral(&sign, under, over, ascent, descent);
+            } else if ascent > 0 && descent > 0 {
+                let Some(sign) = large_operator(&sign, ascent, descent) else {
+                    continue;
+                };
+                let under = under.map(|n| self.process_element(&n)).transpose()?;
+                let over = over.map(|n| self.process_element(&n)).transpose()?;
+                items[k].1 = stack_limits(
+                    over.unwrap_or_else(|| MathBox::empty(0, 0, 0)),
+                    sign,
+                    under.unwrap_or_else(|| MathBox::empty(0, 0, 0)),
+                );
             }
-        };
+        }
+        Ok(())
+    }
 
-        // Spacing is handled in process_row for context-aware operator spacing
-        Ok(MathBox::from_text(&rendered))
+    /// Class of an operator following an item of class `prev` (None at the
+    /// start of a row), with the (left, right) spacing in cells its `lspace`
+    /// and `rspace` attributes ask for. A binary operator used as a prefix
+    /// (`-x`, `= -1`) or with an explicit prefix or postfix `form` is ordinary.
+    fn operator_spacing(
+        &self,
+        node: &Node,
+        prev: Option<OperatorClass>,
+    ) -> (OperatorClass, Option<usize>, Option<usize>) {
+        let mut class = operator_class(&self.get_text_content(node));
+        if class == OperatorClass::Binary {
+            let unary = match node.attribute("form") {
+                Some("prefix" | "postfix") => true,
+                Some(_) => false,
+                None => matches!(
+                    prev,
+                    None | Some(
+                        OperatorClass::Binary
+                            | OperatorClass::Relation
+                            | OperatorClass::Open
+                            | OperatorClass::Punctuation
+                            | OperatorClass::Large
+                    )
+                ),
+            };
+            if unary {
+                class = OperatorClass::Ordinary;
+            }
+        }
+        let space = |attr: &str| node.attribute(attr).and_then(length_to_cells);
+        (class, space("lspace"), space("rspace"))
     }
 
-    fn process_superscript(&self, node: &Node) -> Result<MathBox, RenderError> {
-        let children: Vec<_> = node.children().filter(|n| n.is_element()).collect();
-        if children.len() != 2 {
-            return Err(RenderError::InvalidStructure(
-                "msup requires exactly 2 children".to_string(),
-            ));
+    /// Width in cells of a space between atoms under the spacing policy
+    fn space_cells(&self, space: AtomSpace) -> usize {
+        match (space, self.options.spacing) {
+            (AtomSpace::None, _)
+            | (AtomSpace::Thin | AtomSpace::Medium, Spacing::Tight)
+            | (AtomSpace::Thin, Spacing::Normal) => 0,
+            (AtomSpace::Thick, Spacing::Loose) => 2,
+            _ => 1,
         }
+    }
 
-        let base = self.process_element(&children[0])?;
-        // Use compact mode for superscript content (no spacing around operators)
-        let sup = if children[1].tag_name().name() == "mrow" {
-            self.process_row_compact(&children[1])?
+    /// The pieces tall delimiters are drawn from
+    fn brackets(&self) -> &'static BracketChars {
+        if self.ascii() || self.options.brackets == BracketSet::Ascii {
+            &ASCII_BRACKETS
         } else {
-            self.process_element(&children[1])?
+            &BRACKETS
+        }
+    }
+
+    /// Stretch `<mo stretchy="true">` bars, vertical arrows and brackets (such as
+    /// `\middle|`) to the height of the rest of the row, centred on its baseline
+    fn stretch_operators(&self, child_nodes: &[Node], items: &mut [(usize, MathBox)]) {
+        let is_stretchy = |node: &Node| {
+            node.tag_name().name() == "mo" && node.attribute("stretchy") == Some("true")
         };
+        let (ascent, descent) = items
+            .iter()
+            .filter(|(i, _)| !is_stretchy(&child_nodes[*i]))
+            .fold((0, 0), |(a, d), (_, b)| {
+                (a.max(b.baseline), d.max(b.height - b.baseline - 1))
+            });
+        if ascent + descent == 0 {
+            return;
+        }
 
-        // Try Unicode superscript for simple cases
-        if self.use_unicode_scripts && base.height == 1 && sup.height == 1 {
-            let sup_text = sup.to_string();
-            if let Some(unicode_sup) = to_superscript(sup_text.trim()) {
-                let combined = format!("{}{}", base.to_string(), unicode_sup);
-                return Ok(MathBox::from_text(&combined));
+        let height = ascent + 1 + descent;
+        for (i, item) in items.iter_mut() {
+            let node = &child_nodes[*i];
+            if !is_stretchy(node) {
+                continue;
             }
+            let op = self.get_text_content(node);
+            let column = match op.as_str() {
+                "(" | "[" | "{" | "⟨" | "〈" => self.brackets().get_left(&op, height),
+                ")" | "]" | "}" | "⟩" | "〉" => self.brackets().get_right(&op, height),
+                _ => match stretch_vertical(&op, height) {
+                    Some(column) => column,
+                    None => continue,
+                },
+            };
+            let lines = column.into_iter().map(String::from).collect();
+            *item = MathBox::from_lines(lines, ascent);
+            item.patch_style(self.token_styles.operator_style(&op));
         }
+    }
 
-        // Fall back to 2D rendering
-        let width = base.width + sup.width;
-        let height = base.height + 1;
-        let mut result = MathBox::empty(width, height, base.baseline + 1);
+    /// The sign and (lower, upper) limit nodes of a stretchable big operator
+    /// (∫ ∬ ∭ ∮ ∑ ∏ ∐), with or without limits
+    fn big_operator<'a, 'input>(
+        &self,
+        node: &Node<'a, 'input>,
+    ) -> Option<(String, Option<Node<'a, 'input>>, Option<Node<'a, 'input>>)> {
+        let children: Vec<_> = node.children().filter(|n| n.is_element()).collect();
+        let (op, under, over) = match node.tag_name().name() {
+            "mo" => (*node, None, None),
+            "msub" | "munder" => (*children.first()?, children.get(1).copied(), None),
+            "msup" | "mover" => (*children.first()?, None, children.get(1).copied()),
+            "msubsup" | "munderover" => (
+                *children.first()?,
+                children.get(1).copied(),
+                children.get(2).copied(),
+            ),
+            _ => return None,
+        };
+        let sign = self.get_text_content(&op);
+        let stretchable = matches!(sign.as_str(), "∫" | "∬" | "∭" | "∮" | "∑" | "∏" | "∐");
+        (op.tag_name().name() == "mo" && stretchable).then_some((sign, under, over))
+    }
+
+    /// Split an msub/msup/msubsup into its base and (subscript, superscript)
+    fn script_pair(&self, node: &Node) -> Result<(MathBox, ScriptPair), RenderError> {
+        let children: Vec<_> = node.children().filter(|n| n.is_element()).collect();
+        let script = |i: usize| children.get(i).map(|n| self.process_script(n)).transpose();
+        let base = match children.first() {
+            Some(base) => self.process_element(base)?,
+            None => MathBox::empty(0, 1, 0),
+        };
+        let pair = match node.tag_name().name() {
+            "msub" => (script(1)?, None),
+            "msup" => (None, script(1)?),
+            _ => (script(1)?, script(2)?),
+        };
+        Ok((base, pair))
+    }
+
+    /// Process a script, in compact mode (no spacing around operators) if it is a row
+    fn process_script(&self, node: &Node) -> Result<MathBox, RenderError> {
+        if node.tag_name().name() == "mrow" {
+            self.process_row_compact(node)
+        } else {
+            self.process_element(node)
+        }
+    }
+
+    fn process_multiscripts(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let children: Vec<_> = node.children().filter(|n| n.is_element()).collect();
+        let Some(base) = children.first() else {
+            return Err(RenderError::InvalidStructure(
+                "mmultiscripts requires a base".to_string(),
+            ));
+        };
+        let base = self.process_element(base)?;
+
+        // Scripts come in (subscript, superscript) pairs, prescripts after <mprescripts/>
+        let mut post = Vec::new();
+        let mut pre = Vec::new();
+        let mut in_pre = false;
+        let mut pending: Option<Option<MathBox>> = None;
+        for child in &children[1..] {
+            let script = match child.tag_name().name() {
+                "mprescripts" => {
+                    in_pre = true;
+                    pending = None;
+                    continue;
+                }
+                "none" => None,
+                _ => Some(self.process_script(child)?),
+            };
+            match pending.take() {
+                None => pending = Some(script),
+                Some(sub) if in_pre => pre.push((sub, script)),
+                Some(sub) => post.push((sub, script)),
+            }
+        }
+
+        Ok(multiscripts(base, &pre, &post))
+    }
+
+    fn process_text(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let text = self.get_text_content(node);
+
+        // Handle Greek letters and special identifiers
+        let text = match get_greek(&text) {
+            Some(greek) => greek.to_string(),
+            None => text,
+        };
+        let text = self.symbols.get(&text).cloned().unwrap_or(text);
+
+        let mut variant = self.mathvariant(node).unwrap_or("normal");
+        let mut bold_modifier = false;
+        let astral = self.options.profile.supports_astral();
+        if (!self.use_unicode_bold || self.ascii() || !astral) && variant.contains("bold") {
+            // Keep the non-bold form and let the widget apply a bold modifier
+            variant = match variant {
+                "bold" | "bold-italic" => "normal",
+                "bold-script" => "script",
+                "bold-fraktur" => "fraktur",
+                "bold-sans-serif" => "sans-serif",
+                _ => "sans-serif-italic",
+            };
+            bold_modifier = true;
+        }
+
+        let mapped: String = if self.ascii() {
+            to_ascii(&text)
+        } else {
+            let styled =
+                |c| to_mathvariant(c, variant).filter(|&m| astral || u32::from(m) <= 0xFFFF);
+            text.chars().map(|c| styled(c).unwrap_or(c)).collect()
+        };
+        let mut result = MathBox::from_text(&mapped);
+        let token_style = if node.tag_name().name() == "mn" {
+            self.token_styles.number
+        } else if mapped.chars().all(is_greek) {
+            self.token_styles.greek
+        } else if mapped.chars().count() > 1 && mapped.chars().all(char::is_alphabetic) {
+            self.token_styles.function
+        } else {
+            self.token_styles.identifier
+        };
+        result.patch_style(token_style);
+        if bold_modifier {
+            result.patch_style(Style::default().add_modifier(Modifier::BOLD));
+        }
+        Ok(result)
+    }
+
+    fn process_mtext(&self, node: &Node) -> Result<MathBox, RenderError> {
+        // Text is rendered upright and verbatim: interior and edge spaces are kept
+        let text: String = node
+            .children()
+            .filter(|n| n.is_text())
+            .filter_map(|n| n.text())
+            .collect::<String>()
+            .replace(['\n', '\t', '\r'], " ");
+
+        if self.ascii() {
+            return Ok(MathBox::from_text(&to_ascii(&text)));
+        }
+        Ok(MathBox::from_text(&text))
+    }
+
+    /// String literals are set verbatim between their `lquote`/`rquote` marks
+    fn process_string_literal(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let text = self.process_mtext(node)?.to_string();
+        let lquote = node.attribute("lquote").unwrap_or("\"");
+        let rquote = node.attribute("rquote").unwrap_or("\"");
+        Ok(MathBox::from_text(&format!("{}{}{}", lquote, text, rquote)))
+    }
+
+    fn process_operator(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let text = self.get_text_content(node);
+
+        // Handle special operators
+        let rendered = match text.as_str() {
+            "∑" | "∏" | "∫" | "∬" | "∭" | "∮" | "⋃" | "⋂" => {
+                // Big operators - keep as is
+                text
+            }
+            _ => {
+                // Check if it's a LaTeX command
+                if let Some(cmd) = text.strip_prefix('\\') {
+                    if let Some(sym) = get_symbol(cmd) {
+                        sym.to_string()
+                    } else if let Some(greek) = get_greek(cmd) {
+                        greek.to_string()
+                    } else {
+                        text
+                    }
+                } else {
+                    text
+                }
+            }
+        };
+
+        let rendered = self.symbols.get(&rendered).cloned().unwrap_or(rendered);
+
+        // Spacing is handled in process_row for context-aware operator spacing
+        let rendered = if self.ascii() {
+            to_ascii(&rendered)
+        } else {
+            rendered
+        };
+        let mut result = MathBox::from_text(&rendered);
+        result.patch_style(self.token_styles.operator_style(&rendered));
+        Ok(result)
+    }
+
+    fn process_superscript(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let children: Vec<_> = node.children().filter(|n| n.is_element()).collect();
+        if children.len() != 2 {
+            return Err(RenderError::InvalidStructure(
+                "msup requires exactly 2 children".to_string(),
+            ));
+        }
+
+        let base = self.process_element(&children[0])?;
+        // Use compact mode for superscript content (no spacing around operators)
+        let sup = if children[1].tag_name().name() == "mrow" {
+            self.process_row_compact(&children[1])?
+        } else {
+            self.process_element(&children[1])?
+        };
+
+        // Primes attach directly to a single-line base (f′, x″) with any
+        // remaining superscript after them
+        if base.height == 1 && sup.height == 1 && !self.ascii() {
+            if let Some((primes, rest)) = collapse_primes(sup.to_string().trim()) {
+                let primed = MathBox::from_text(&format!("{}{}", base, primes));
+                if rest.is_empty() {
+                    return Ok(primed);
+                }
+                return Ok(self.attach_superscript(primed, MathBox::from_text(rest)));
+            }
+        }
+
+        Ok(self.attach_superscript(base, sup))
+    }
+
+    /// Place `sup` as a superscript of `base`, using Unicode superscript characters
+    /// when possible
+    fn attach_superscript(&self, base: MathBox, sup: MathBox) -> MathBox {
+        if self.options.ascii && !self.eqn && base.height == 1 && sup.height == 1 {
+            return inline_scripts(&base, &[(ascii_script("^", &sup), &sup)]);
+        }
+
+        // Try Unicode superscript for simple cases
+        if self.options.unicode_scripts && !self.ascii() && base.height == 1 && sup.height == 1 {
+            let sup_text = sup.to_string();
+            if let Some(unicode_sup) = to_superscript(sup_text.trim()) {
+                return inline_scripts(&base, &[(unicode_sup, &sup)]);
+            }
+        }
+
+        // Fall back to 2D rendering
+        let width = base.width + sup.width;
+        let height = base.height + sup.height;
+        let mut result = MathBox::empty(width, height, base.baseline + sup.height);
 
         // Place base at bottom
-        result.blit(&base, 0, 1);
+        result.blit(&base, 0, sup.height);
         // Place superscript at top-right
         result.blit(&sup, base.width, 0);
 
-        Ok(result)
+        result
     }
 
     fn process_subscript(&self, node: &Node) -> Result<MathBox, RenderError> {
@@ -301,18 +1338,26 @@ impl MathRenderer {
             self.process_element(&children[1])?
         };
 
+        if self.options.ascii && !self.eqn && base.height == 1 && sub.height == 1 {
+            return Ok(inline_scripts(&base, &[(ascii_script("_", &sub), &sub)]));
+        }
+
         // Try Unicode subscript for simple cases
-        if self.use_unicode_scripts && base.height == 1 && sub.height == 1 {
+        if self.options.unicode_scripts && !self.ascii() && base.height == 1 && sub.height == 1 {
             let sub_text = sub.to_string();
             if let Some(unicode_sub) = to_subscript(sub_text.trim()) {
-                let combined = format!("{}{}", base.to_string(), unicode_sub);
-                return Ok(MathBox::from_text(&combined));
+                return Ok(inline_scripts(&base, &[(unicode_sub, &sub)]));
             }
         }
 
+        // Evaluation bars (\left. ... \right|_{x=0}) take the script at their foot
+        if base.height > 1 && self.ends_with_fence(&children[0]) {
+            return Ok(fence_scripts(base, Some(sub), None));
+        }
+
         // Fall back to 2D rendering
         let width = base.width + sub.width;
-        let height = base.height + 1;
+        let height = base.height + sub.height;
         let mut result = MathBox::empty(width, height, base.baseline);
 
         // Place base at top
@@ -339,39 +1384,83 @@ impl MathRenderer {
         );
 
         let base = self.process_element(&children[0])?;
-        let sub = self.process_element(&children[1])?;
-        let sup = self.process_element(&children[2])?;
+        let sub = self.process_script(&children[1])?;
+        let sup = self.process_script(&children[2])?;
 
         // For big operators, stack limits vertically (centered)
         if is_big_operator {
-            return Ok(MathBox::stack_vertical(&[sup, base, sub]));
+            return Ok(stack_limits(sup, base, sub));
+        }
+
+        if self.options.ascii && !self.eqn && base.height == 1 && sub.height == 1 && sup.height == 1
+        {
+            let scripts = [
+                (ascii_script("_", &sub), &sub),
+                (ascii_script("^", &sup), &sup),
+            ];
+            return Ok(inline_scripts(&base, &scripts));
         }
 
         // Try Unicode scripts for simple cases
-        if self.use_unicode_scripts && base.height == 1 && sub.height == 1 && sup.height == 1 {
+        if self.options.unicode_scripts
+            && !self.ascii()
+            && base.height == 1
+            && sub.height == 1
+            && sup.height == 1
+        {
             let sub_text = sub.to_string();
             let sup_text = sup.to_string();
-            if let (Some(unicode_sub), Some(unicode_sup)) =
-                (to_subscript(sub_text.trim()), to_superscript(sup_text.trim()))
-            {
-                let combined = format!("{}{}{}", base.to_string(), unicode_sub, unicode_sup);
-                return Ok(MathBox::from_text(&combined));
+            // Primes follow the subscript on the baseline (x₁′)
+            if let (Some(unicode_sub), Some((primes, ""))) = (
+                to_subscript(sub_text.trim()),
+                collapse_primes(sup_text.trim()),
+            ) {
+                return Ok(inline_scripts(
+                    &base,
+                    &[(unicode_sub, &sub), (primes, &sup)],
+                ));
             }
+            if let (Some(unicode_sub), Some(unicode_sup)) = (
+                to_subscript(sub_text.trim()),
+                to_superscript(sup_text.trim()),
+            ) {
+                return Ok(inline_scripts(
+                    &base,
+                    &[(unicode_sub, &sub), (unicode_sup, &sup)],
+                ));
+            }
+        }
+
+        if base.height > 1 && self.ends_with_fence(&children[0]) {
+            return Ok(fence_scripts(base, Some(sub), Some(sup)));
         }
 
         // 2D rendering with both
         let script_width = sub.width.max(sup.width);
         let width = base.width + script_width;
-        let height = base.height + 2;
-        let mut result = MathBox::empty(width, height, base.baseline + 1);
+        let height = sup.height + base.height + sub.height;
+        let mut result = MathBox::empty(width, height, sup.height + base.baseline);
 
-        result.blit(&base, 0, 1);
+        result.blit(&base, 0, sup.height);
         result.blit(&sup, base.width, 0);
-        result.blit(&sub, base.width, height - 1);
+        result.blit(&sub, base.width, sup.height + base.height);
 
         Ok(result)
     }
 
+    /// Whether a script base ends in a `\right` delimiter, which scripts attach beside
+    fn ends_with_fence(&self, node: &Node) -> bool {
+        node.tag_name().name() == "mrow"
+            && node
+                .children()
+                .rfind(|n| n.is_element())
+                .is_some_and(|last| {
+                    last.tag_name().name() == "mo"
+                        && last.attribute("stretchy") == Some("true")
+                        && last.attribute("form") == Some("postfix")
+                })
+    }
+
     fn process_fraction(&self, node: &Node) -> Result<MathBox, RenderError> {
         let children: Vec<_> = node.children().filter(|n| n.is_element()).collect();
         if children.len() != 2 {
@@ -383,6 +1472,34 @@ impl MathRenderer {
         let num = self.process_element(&children[0])?;
         let den = self.process_element(&children[1])?;
 
+        // Zero thickness (e.g. \binom) stacks the parts without a bar
+        let has_bar = !node.attribute("linethickness").is_some_and(|t| {
+            t.trim_end_matches(|c: char| c.is_ascii_alphabetic())
+                .parse::<f32>()
+                == Ok(0.0)
+        });
+        if !has_bar {
+            let width = num.width.max(den.width);
+            let mut result = MathBox::empty(width, num.height + den.height, num.height);
+            result.blit(&num, (width - num.width) / 2, 0);
+            result.blit(&den, (width - den.width) / 2, num.height);
+            return Ok(result);
+        }
+
+        // Text-style fractions (\tfrac) use a compact a/b form when both parts fit on one line
+        let display = self
+            .displaystyle(node)
+            .unwrap_or(self.options.style == MathStyle::Display);
+        let compact = node.attribute("bevelled") == Some("true") || !display;
+        if compact && num.height == 1 && den.height == 1 {
+            let slash = MathBox::from_text("/");
+            return Ok(MathBox::concat_horizontal(&[
+                self.slash_operand(&children[0], num),
+                slash,
+                self.slash_operand(&children[1], den),
+            ]));
+        }
+
         let width = num.width.max(den.width);
         let height = num.height + 1 + den.height;
         let baseline = num.height;
@@ -405,6 +1522,15 @@ impl MathRenderer {
 
     fn process_sqrt(&self, node: &Node) -> Result<MathBox, RenderError> {
         let inner = self.process_row(node)?;
+        if self.eqn {
+            return Ok(eqn_radical(&inner));
+        }
+        if self.ascii() {
+            return Ok(MathBox::concat_horizontal(&[
+                MathBox::from_text("sqrt"),
+                self.fence("(", ")", inner),
+            ]));
+        }
 
         // Simple sqrt rendering: √ followed by content with overline
         // Layout: ___
@@ -463,10 +1589,26 @@ impl MathRenderer {
         let inner = self.process_element(&children[0])?;
         let index = self.process_element(&children[1])?;
 
+        // eqn roots carry a short index over the tick of the radical
+        if self.eqn && index.height == 1 && index.width <= 2 {
+            let mut result = eqn_radical(&inner);
+            result.blit(&index, 2 - index.width, 0);
+            return Ok(result);
+        }
+
+        // ASCII roots name their index: sqrt[3](x)
+        if self.ascii() && index.height == 1 {
+            let name = MathBox::from_text(&format!("sqrt[{}]", index.to_string().trim()));
+            return Ok(MathBox::concat_horizontal(&[
+                name,
+                self.fence("(", ")", inner),
+            ]));
+        }
+
         // Try Unicode superscript for index
         let index_text = index.to_string();
         if let Some(unicode_idx) = to_superscript(index_text.trim()) {
-            let text = format!("{}√{}", unicode_idx, inner.to_string());
+            let text = format!("{}√{}", unicode_idx, inner);
             return Ok(MathBox::from_text(&text));
         }
 
@@ -496,20 +1638,41 @@ impl MathRenderer {
             ));
         }
 
-        let base = self.process_element(&children[0])?;
         let over = self.process_element(&children[1])?;
+        if let Some(arrow) = self.stretchy_arrow(&children[0], over.width) {
+            return Ok(MathBox::stack_vertical(&[over, arrow]));
+        }
+        let base = self.process_element(&children[0])?;
 
         let over_text = over.to_string().trim().to_string();
+        let is_line = matches!(over_text.as_str(), "_" | "¯" | "‾");
+
+        // Overlines wider than one cell get a full-width line row
+        if is_line && (base.width > 1 || base.height > 1) {
+            return Ok(base.overline(self.overline_char()));
+        }
+
+        // Accents written out by name, on bases of any height
+        if self.options.accents == AccentStrategy::Ascii {
+            if let Some(name) = accent_name(&over_text) {
+                return Ok(MathBox::concat_horizontal(&[
+                    MathBox::from_text(name),
+                    self.fence("(", ")", base),
+                ]));
+            }
+        }
 
         // Handle common accents on single-height bases
-        if base.height == 1 {
+        let combining = self.options.accents == AccentStrategy::Combining
+            && self.options.profile.supports_combining();
+        if base.height == 1 && combining {
             let accent = match over_text.as_str() {
-                "^" | "ˆ" => Some("̂"),  // Combining circumflex
-                "~" | "˜" => Some("̃"),  // Combining tilde
-                "¯" | "-" => Some("̄"),  // Combining macron (bar)
-                "." => Some("̇"),        // Combining dot above
-                ".." | "¨" => Some("̈"), // Combining diaeresis
-                "→" => Some("⃗"),        // Combining right arrow
+                "^" | "ˆ" => Some("̂"),             // Combining circumflex
+                "~" | "˜" => Some("̃"),             // Combining tilde
+                "¯" | "-" | "_" | "‾" => Some("̄"), // Combining macron (bar)
+                "." => Some("̇"),                   // Combining dot above
+                ".." | "¨" => Some("̈"),            // Combining diaeresis
+                "→" => Some("⃗"),                   // Combining right arrow
                 _ => None,
             };
             if let Some(combining) = accent {
@@ -542,7 +1705,7 @@ impl MathRenderer {
             let under_trimmed = under_text.trim();
 
             // Try full Unicode subscript conversion
-            if let Some(subscript) = to_subscript(under_trimmed) {
+            if let Some(subscript) = to_subscript(under_trimmed).filter(|_| !self.ascii()) {
                 let combined = format!("{}{}", base_text, subscript);
                 return Ok(MathBox::from_text(&combined));
             }
@@ -552,6 +1715,12 @@ impl MathRenderer {
             return Ok(MathBox::from_text(&combined));
         }
 
+        // Underlines get a full-width line row below the base
+        let under_text = under.to_string();
+        if matches!(under_text.trim(), "_" | "¯" | "‾") {
+            return Ok(base.underline('¯'));
+        }
+
         // For other elements, stack with baseline at the base element
         let width = base.width.max(under.width);
         let height = base.height + under.height;
@@ -576,24 +1745,46 @@ impl MathRenderer {
             ));
         }
 
-        let base = self.process_element(&children[0])?;
         let under = self.process_element(&children[1])?;
         let over = self.process_element(&children[2])?;
+        if let Some(arrow) = self.stretchy_arrow(&children[0], over.width.max(under.width)) {
+            return Ok(stack_limits(over, arrow, under));
+        }
+        let base = self.process_element(&children[0])?;
 
-        Ok(MathBox::stack_vertical(&[over, base, under]))
+        Ok(stack_limits(over, base, under))
     }
 
     fn process_table(&self, node: &Node) -> Result<MathBox, RenderError> {
-        let rows: Vec<Vec<MathBox>> = node
+        let row_nodes: Vec<_> = node
             .children()
-            .filter(|n| n.is_element() && n.tag_name().name() == "mtr")
-            .map(|row| {
-                row.children()
-                    .filter(|n| n.is_element() && n.tag_name().name() == "mtd")
-                    .map(|cell| self.process_row(&cell))
-                    .collect::<Result<Vec<_>, _>>()
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+            .filter(|n| n.is_element() && matches!(n.tag_name().name(), "mtr" | "mlabeledtr"))
+            .collect();
+        let mut labels = Vec::new();
+        let mut rows: Vec<Vec<MathBox>> = Vec::new();
+        for row in &row_nodes {
+            let mut cells = row
+                .children()
+                .filter(|n| n.is_element() && n.tag_name().name() == "mtd")
+                .map(|cell| self.process_row(&cell))
+                .collect::<Result<Vec<_>, _>>()?;
+            // The first cell of a labeled row is its label
+            let label = if row.tag_name().name() == "mlabeledtr" && !cells.is_empty() {
+                Some(cells.remove(0))
+            } else {
+                None
+            };
+            labels.push(label);
+            rows.push(cells);
+        }
+
+        // A lone labeled row (a `\tag`ged equation) keeps its label separate for widgets
+        if rows.len() == 1 && rows[0].len() == 1 {
+            if let Some(label) = labels[0].take() {
+                let cell = rows.remove(0).remove(0);
+                return Ok(cell.with_label(label));
+            }
+        }
 
         if rows.is_empty() {
             return Ok(MathBox::empty(0, 1, 0));
@@ -602,19 +1793,74 @@ impl MathRenderer {
         // Calculate column widths and row heights
         let num_cols = rows.iter().map(|r| r.len()).max().unwrap_or(0);
         let mut col_widths = vec![0; num_cols];
-        let mut row_heights = vec![0; rows.len()];
+        let mut row_ascents = vec![0; rows.len()];
+        let mut row_descents = vec![0; rows.len()];
 
+        // Cells within a row share a baseline
         for (i, row) in rows.iter().enumerate() {
             for (j, cell) in row.iter().enumerate() {
                 col_widths[j] = col_widths[j].max(cell.width);
-                row_heights[i] = row_heights[i].max(cell.height);
+                row_ascents[i] = row_ascents[i].max(cell.baseline);
+                row_descents[i] =
+                    row_descents[i].max(cell.height.saturating_sub(cell.baseline + 1));
             }
         }
+        let row_heights: Vec<usize> = row_ascents
+            .iter()
+            .zip(&row_descents)
+            .map(|(a, d)| a + 1 + d)
+            .collect();
+
+        // Column alignment and spacing lists repeat their last entry
+        let aligns: Vec<&str> = node
+            .attribute("columnalign")
+            .map(|a| a.split_whitespace().collect())
+            .unwrap_or_default();
+        let spacings: Vec<usize> = node
+            .attribute("columnspacing")
+            .map(|a| {
+                a.split_whitespace()
+                    .map(|s| length_to_cells(s).unwrap_or(2))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let lines: Vec<&str> = node
+            .attribute("columnlines")
+            .map(|a| a.split_whitespace().collect())
+            .unwrap_or_default();
+        let row_lines: Vec<&str> = node
+            .attribute("rowlines")
+            .map(|a| a.split_whitespace().collect())
+            .unwrap_or_default();
+        let align_for = |j: usize| aligns.get(j).or(aligns.last()).copied().unwrap_or("center");
+        let line_for = |j: usize| match lines.get(j).or(lines.last()).copied() {
+            Some("solid") => Some('│'),
+            Some("dashed") => Some('┆'),
+            _ => None,
+        };
+        let row_line_for = |i: usize| match row_lines.get(i).or(row_lines.last()).copied() {
+            Some("solid") => Some('─'),
+            Some("dashed") => Some('┄'),
+            _ => None,
+        };
+        // Rules between rows take a row of their own
+        let row_gap_for = |i: usize| usize::from(row_line_for(i).is_some());
+        // Gaps holding a rule are widened so it has a blank cell on either side
+        let spacing_for = |j: usize| {
+            let spacing = spacings.get(j).or(spacings.last()).copied().unwrap_or(2);
+            if line_for(j).is_some() {
+                spacing.max(3)
+            } else {
+                spacing
+            }
+        };
 
-        // Add spacing
-        let spacing = 2;
-        let total_width: usize = col_widths.iter().sum::<usize>() + spacing * (num_cols.saturating_sub(1));
-        let total_height: usize = row_heights.iter().sum();
+        let total_width: usize = col_widths.iter().sum::<usize>()
+            + (0..num_cols.saturating_sub(1))
+                .map(spacing_for)
+                .sum::<usize>();
+        let total_height: usize =
+            row_heights.iter().sum::<usize>() + (0..rows.len() - 1).map(row_gap_for).sum::<usize>();
 
         let mut result = MathBox::empty(total_width, total_height, total_height / 2);
 
@@ -622,12 +1868,93 @@ impl MathRenderer {
         for (i, row) in rows.iter().enumerate() {
             let mut x_pos = 0;
             for (j, cell) in row.iter().enumerate() {
-                // Center cell in its column
-                let x_offset = (col_widths[j] - cell.width) / 2;
-                result.blit(cell, x_pos + x_offset, y_pos);
-                x_pos += col_widths[j] + spacing;
+                let x_offset = match align_for(j) {
+                    "left" => 0,
+                    "right" => col_widths[j] - cell.width,
+                    _ => (col_widths[j] - cell.width) / 2,
+                };
+                result.blit(
+                    cell,
+                    x_pos + x_offset,
+                    y_pos + row_ascents[i] - cell.baseline,
+                );
+                x_pos += col_widths[j] + spacing_for(j);
+            }
+            y_pos += row_heights[i] + row_gap_for(i);
+        }
+
+        let mut rule_cols = Vec::new();
+        let mut x_pos = 0;
+        for (j, width) in col_widths
+            .iter()
+            .enumerate()
+            .take(num_cols.saturating_sub(1))
+        {
+            x_pos += width;
+            if let Some(ch) = line_for(j) {
+                result.fill_col(x_pos + spacing_for(j) / 2, ch);
+                rule_cols.push(x_pos + spacing_for(j) / 2);
+            }
+            x_pos += spacing_for(j);
+        }
+
+        // A frame keeps a blank cell between the border and the cells
+        let frame = matches!(node.attribute("frame"), Some("solid" | "dashed"));
+        if frame {
+            let mut padded = MathBox::empty(total_width + 2, total_height, result.baseline);
+            padded.blit(&result, 1, 0);
+            result = padded;
+            rule_cols.iter_mut().for_each(|x| *x += 1);
+        }
+
+        let mut rule_rows = Vec::new();
+        let mut y_pos = 0;
+        for (i, height) in row_heights.iter().enumerate().take(rows.len() - 1) {
+            y_pos += height;
+            if let Some(ch) = row_line_for(i) {
+                result.fill_row(y_pos, ch);
+                for &x in &rule_cols {
+                    result.set(x, y_pos, '┼');
+                }
+                rule_rows.push(y_pos);
+            }
+            y_pos += row_gap_for(i);
+        }
+
+        if frame {
+            result = result.frame();
+            let (width, height) = (result.width, result.height);
+            for &x in &rule_cols {
+                result.set(x + 1, 0, '┬');
+                result.set(x + 1, height - 1, '┴');
+            }
+            for &y in &rule_rows {
+                result.set(0, y + 1, '├');
+                result.set(width - 1, y + 1, '┤');
+            }
+        }
+        let total_width = result.width;
+        let total_height = result.height;
+
+        // Labels of other labeled rows go in a column after the table
+        let label_width = labels.iter().flatten().map(|l| l.width).max().unwrap_or(0);
+        if label_width > 0 {
+            let mut labeled =
+                MathBox::empty(total_width + 2 + label_width, total_height, result.baseline);
+            labeled.blit(&result, 0, 0);
+            let mut y_pos = 0;
+            for (i, label) in labels.iter().enumerate() {
+                if let Some(label) = label {
+                    let x = total_width + 2 + label_width - label.width;
+                    labeled.blit(
+                        label,
+                        x,
+                        (y_pos + row_ascents[i]).saturating_sub(label.baseline),
+                    );
+                }
+                y_pos += row_heights[i];
             }
-            y_pos += row_heights[i];
+            return Ok(labeled);
         }
 
         Ok(result)
@@ -657,33 +1984,182 @@ impl MathRenderer {
         let open = node.attribute("open").unwrap_or("(");
         let close = node.attribute("close").unwrap_or(")");
 
-        let inner = self.process_row(node)?;
+        // Separators go between the children in turn, the last one repeating;
+        // whitespace in the attribute is ignored
+        let separators: Vec<char> = node
+            .attribute("separators")
+            .unwrap_or(",")
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let mut parts = Vec::new();
+        for (i, child) in node.children().filter(|n| n.is_element()).enumerate() {
+            if i > 0 {
+                if let Some(&sep) = separators.get(i - 1).or(separators.last()) {
+                    parts.push(MathBox::from_text(&sep.to_string()));
+                }
+            }
+            parts.push(self.process_element(&child)?);
+        }
+        let inner = MathBox::concat_horizontal(&parts);
+        Ok(self.fence(open, close, inner))
+    }
 
+    /// Surround content with delimiters scaled to its height.
+    /// Empty delimiters (`\left.`) take no space.
+    fn fence(&self, open: &str, close: &str, inner: MathBox) -> MathBox {
         if inner.height <= 1 {
             // Simple case
-            let text = format!("{}{}{}", open, inner.to_string(), close);
-            return Ok(MathBox::from_text(&text));
+            return MathBox::concat_horizontal(&[
+                MathBox::from_text(open),
+                inner,
+                MathBox::from_text(close),
+            ]);
         }
 
         // Scaled brackets
-        let left_chars = BRACKETS.get_left(open, inner.height);
-        let right_chars = BRACKETS.get_right(close, inner.height);
-
-        let width = 1 + inner.width + 1;
+        let left_width = usize::from(!open.is_empty());
+        let right_width = usize::from(!close.is_empty());
+        let width = left_width + inner.width + right_width;
         let height = inner.height;
         let mut result = MathBox::empty(width, height, inner.baseline);
 
         // Draw brackets
-        for (y, &ch) in left_chars.iter().enumerate() {
-            result.set(0, y, ch);
+        if !open.is_empty() {
+            for (y, &ch) in self.brackets().get_left(open, height).iter().enumerate() {
+                result.set(0, y, ch);
+                result.set_style(0, y, self.token_styles.delimiter);
+            }
         }
-        for (y, &ch) in right_chars.iter().enumerate() {
-            result.set(width - 1, y, ch);
+        if !close.is_empty() {
+            for (y, &ch) in self.brackets().get_right(close, height).iter().enumerate() {
+                result.set(width - 1, y, ch);
+                result.set_style(width - 1, y, self.token_styles.delimiter);
+            }
         }
 
         // Place content
-        result.blit(&inner, 1, 0);
+        result.blit(&inner, left_width, 0);
+
+        result
+    }
+
+    /// Get the mathvariant of a token, inherited from the nearest ancestor that sets one
+    fn mathvariant<'a>(&self, node: &Node<'a, '_>) -> Option<&'a str> {
+        node.ancestors().find_map(|n| n.attribute("mathvariant"))
+    }
+
+    /// Nearest explicit `displaystyle` setting (from `\dfrac`/`\tfrac` or an mstyle)
+    fn displaystyle(&self, node: &Node) -> Option<bool> {
+        node.ancestors()
+            .find_map(|n| n.attribute("displaystyle"))
+            .map(|d| d == "true")
+    }
+
+    /// Parenthesize a fraction part for the a/b form unless it is a single token
+    fn slash_operand(&self, node: &Node, mbox: MathBox) -> MathBox {
+        let mut part = *node;
+        while part.tag_name().name() == "mrow" {
+            let mut children = part.children().filter(|n| n.is_element());
+            match (children.next(), children.next()) {
+                (Some(only), None) => part = only,
+                _ => break,
+            }
+        }
+        if !matches!(part.tag_name().name(), "mrow" | "mfrac") {
+            mbox
+        } else {
+            MathBox::concat_horizontal(&[MathBox::from_text("("), mbox, MathBox::from_text(")")])
+        }
+    }
+
+    /// Render a stretchy arrow operator wide enough to cover labels of `label_width`
+    fn stretchy_arrow(&self, node: &Node, label_width: usize) -> Option<MathBox> {
+        if node.tag_name().name() != "mo" || node.attribute("stretchy") != Some("true") {
+            return None;
+        }
+        let arrow = stretch_arrow(&self.get_text_content(node), label_width + 2)?;
+        Some(MathBox::from_text(&arrow))
+    }
+
+    fn process_enclose(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let inner = self.process_row(node)?;
+        let notation = node.attribute("notation").unwrap_or("longdiv");
+
+        let mut result = inner;
+        for notation in notation.split_whitespace() {
+            result = match notation {
+                "box" | "roundedbox" => result.frame(),
+                "top" => result.overline(self.overline_char()),
+                "bottom" => result.underline('¯'),
+                "updiagonalstrike" => strike(result, false),
+                "downdiagonalstrike" => strike(result, true),
+                _ => result,
+            };
+        }
+        Ok(result)
+    }
+
+    /// Render the selected branch of an maction (1-based `selection`, default the
+    /// first). Toggle actions advance by the number of times they've been toggled.
+    fn process_action(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let branches: Vec<_> = node.children().filter(|n| n.is_element()).collect();
+        if branches.is_empty() {
+            return Ok(MathBox::empty(0, 1, 0));
+        }
+        let mut selection = node
+            .attribute("selection")
+            .and_then(|s| s.trim().parse::<usize>().ok())
+            .unwrap_or(1)
+            .saturating_sub(1);
+        if node.attribute("actiontype") == Some("toggle") {
+            selection += self
+                .action_toggles
+                .get(&action_id(node))
+                .copied()
+                .unwrap_or(0);
+        }
+        self.process_element(&branches[selection % branches.len()])
+    }
+
+    /// Apply mpadded `width` and `lspace` overrides: the content is shifted right by
+    /// `lspace` and the box takes the given width, clipping or padding the content
+    fn process_padded(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let inner = self.process_row(node)?;
+        let lspace = match node
+            .attribute("lspace")
+            .and_then(|l| padded_length(l, inner.width))
+        {
+            Some(('-', _)) | None => 0,
+            Some((_, cells)) => cells,
+        };
+        let width = match node
+            .attribute("width")
+            .and_then(|w| padded_length(w, inner.width))
+        {
+            Some(('+', cells)) => inner.width + cells,
+            Some(('-', cells)) => inner.width.saturating_sub(cells),
+            Some((_, cells)) => cells,
+            None => lspace + inner.width,
+        };
+        if lspace == 0 && width == inner.width {
+            return Ok(inner);
+        }
+        let mut result = MathBox::empty(width, inner.height, inner.baseline);
+        result.blit(&inner, lspace, 0);
+        Ok(result)
+    }
 
+    /// Render error markup flagged with ⟨! … !⟩ markers in red, so it stands out
+    /// even where styles are dropped
+    fn process_error(&self, node: &Node) -> Result<MathBox, RenderError> {
+        let inner = self.process_row(node)?;
+        let mut result = MathBox::concat_horizontal(&[
+            MathBox::from_text("⟨!"),
+            inner,
+            MathBox::from_text("!⟩"),
+        ]);
+        result.patch_style(Style::default().fg(Color::Red));
         Ok(result)
     }
 
@@ -698,38 +2174,1294 @@ impl MathRenderer {
     }
 }
 
-impl Default for MathRenderer {
-    fn default() -> Self {
-        Self::new()
+/// The text of the single token `\command` converts to, if it's a symbol
+fn command_glyph(command: &str) -> Option<String> {
+    let mathml = latex::to_mathml(&format!("\\{}", command), DisplayStyle::Inline).ok()?;
+    let doc = Document::parse(&mathml).ok()?;
+    let mut tokens = doc.root_element().children().filter(Node::is_element);
+    let (Some(token), None) = (tokens.next(), tokens.next()) else {
+        return None;
+    };
+    let text = token.text()?.trim();
+    (matches!(token.tag_name().name(), "mi" | "mo" | "mn") && !text.is_empty())
+        .then(|| text.to_string())
+}
+
+/// The id of a toggle maction: its `id` attribute, or else its position among the
+/// document's toggle actions ("0", "1", ...)
+fn action_id(node: &Node) -> String {
+    if let Some(id) = node.attribute("id") {
+        return id.to_string();
     }
+    let is_toggle =
+        |n: &Node| n.tag_name().name() == "maction" && n.attribute("actiontype") == Some("toggle");
+    node.document()
+        .descendants()
+        .filter(is_toggle)
+        .position(|n| n == *node)
+        .unwrap_or(0)
+        .to_string()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Stack limits above and below an operator, keeping the baseline on the operator
+/// so multi-row limits (e.g. `\substack`) don't shift it off the surrounding row.
+fn stack_limits(over: MathBox, base: MathBox, under: MathBox) -> MathBox {
+    let baseline = over.height + base.baseline;
+    let mut result = MathBox::stack_vertical(&[over, base, under]);
+    result.baseline = baseline;
+    result
+}
 
-    #[test]
-    fn test_simple_expression() {
-        let renderer = MathRenderer::new();
-        let result = renderer.render_latex("x + y").unwrap();
-        assert!(result.contains('x'));
-        assert!(result.contains('y'));
+/// Build an integral sign spanning `ascent` rows above and `descent` rows below the
+/// baseline from ⌠ ⎮ ⌡ pieces (side by side for ∬ and ∭, with ∮ marking the baseline
+/// of a contour integral), limits at its top and bottom right
+fn tall_integral(
+    sign: &str,
+    sub: Option<MathBox>,
+    sup: Option<MathBox>,
+    ascent: usize,
+    descent: usize,
+) -> MathBox {
+    let height = ascent + 1 + descent;
+    let columns = match sign {
+        "∬" => 2,
+        "∭" => 3,
+        _ => 1,
+    };
+    let limits_width = sub
+        .as_ref()
+        .map_or(0, |b| b.width)
+        .max(sup.as_ref().map_or(0, |b| b.width));
+    let mut result = MathBox::empty(columns + limits_width, height, ascent);
+
+    for x in 0..columns {
+        for y in 0..height {
+            let ch = match y {
+                0 => '⌠',
+                y if y == height - 1 => '⌡',
+                y if y == ascent && sign == "∮" => '∮',
+                _ => '⎮',
+            };
+            result.set(x, y, ch);
+        }
     }
-
-    #[test]
-    fn test_superscript() {
-        let renderer = MathRenderer::new();
-        let result = renderer.render_latex("x^2").unwrap();
-        // Should contain Unicode superscript
-        assert!(result.contains('²') || result.contains('2'));
+    if let Some(sup) = sup {
+        result.blit(&sup, columns, 0);
+    }
+    if let Some(sub) = sub {
+        result.blit(&sub, columns, height.saturating_sub(sub.height));
     }
+    result
+}
 
-    #[test]
-    fn test_fraction() {
-        let renderer = MathRenderer::new();
-        let result = renderer.render_latex(r"\frac{a}{b}").unwrap();
-        assert!(result.contains('a'));
-        assert!(result.contains('b'));
-        assert!(result.contains('─'));
+/// Draw ∑, ∏ or ∐ large enough for an operand reaching `ascent` rows above and
+/// `descent` rows below the baseline. The summation sign's diagonals meet at the
+/// baseline. Returns None for other operators.
+fn large_operator(sign: &str, ascent: usize, descent: usize) -> Option<MathBox> {
+    let mut lines = Vec::new();
+    let baseline = match sign {
+        "∑" => {
+            let half = ascent.max(descent) + 1;
+            lines.push("_".repeat(half + 1));
+            for y in 0..half {
+                lines.push(format!("{}╲", " ".repeat(y)));
+            }
+            for y in (0..half).rev() {
+                lines.push(format!("{}╱", " ".repeat(y)));
+            }
+            lines.push("‾".repeat(half + 1));
+            half
+        }
+        "∏" => {
+            lines.push("┬──┬".to_string());
+            lines.extend(std::iter::repeat_n(
+                "│  │".to_string(),
+                ascent + 1 + descent,
+            ));
+            ascent + 1
+        }
+        "∐" => {
+            lines.extend(std::iter::repeat_n(
+                "│  │".to_string(),
+                ascent + 1 + descent,
+            ));
+            lines.push("┴──┴".to_string());
+            ascent
+        }
+        _ => return None,
+    };
+    Some(MathBox::from_lines(lines, baseline))
+}
+
+/// Attach scripts beside a fenced base: the superscript level with the top of the
+/// closing delimiter and the subscript level with its foot
+fn fence_scripts(base: MathBox, sub: Option<MathBox>, sup: Option<MathBox>) -> MathBox {
+    let above = sup.as_ref().map_or(0, |sup| sup.height - 1);
+    let below = sub.as_ref().map_or(0, |sub| sub.height - 1);
+    let script_width = sub
+        .iter()
+        .chain(sup.iter())
+        .map(|s| s.width)
+        .max()
+        .unwrap_or(0);
+    let mut result = MathBox::empty(
+        base.width + script_width,
+        above + base.height + below,
+        above + base.baseline,
+    );
+
+    result.blit(&base, 0, above);
+    if let Some(sup) = sup {
+        result.blit(&sup, base.width, 0);
+    }
+    if let Some(sub) = sub {
+        result.blit(&sub, base.width, above + base.height - 1);
+    }
+    result
+}
+
+/// A subscript and superscript, either of which may be absent
+type ScriptPair = (Option<MathBox>, Option<MathBox>);
+
+/// An eqn-style ASCII radical: the radicand under a `_` overbar, with a `\/`
+/// tick on its bottom row and `|` above it
+///
+/// ```text
+///   ___
+/// \/x+1
+/// ```
+fn eqn_radical(inner: &MathBox) -> MathBox {
+    let mut result = MathBox::empty(inner.width + 2, inner.height + 1, inner.baseline + 1);
+    for x in 2..result.width {
+        result.set(x, 0, '_');
+    }
+    for y in 1..result.height - 1 {
+        result.set(1, y, '|');
+    }
+    result.set(0, result.height - 1, '\\');
+    result.set(1, result.height - 1, '/');
+    result.blit(inner, 2, 1);
+    result
+}
+
+/// Write Unicode `scripts` inline after a single-line `base`, keeping the
+/// base's styles and giving each script the style of the box it came from
+fn inline_scripts(base: &MathBox, scripts: &[(String, &MathBox)]) -> MathBox {
+    let text: String = scripts.iter().map(|(script, _)| script.as_str()).collect();
+    let mut result = MathBox::from_text(&format!("{}{}", base, text));
+    for x in 0..base.width {
+        result.set_style(x, 0, base.style(x, 0));
+    }
+    let mut x = base.width;
+    for (script, source) in scripts {
+        let style = (0..source.width)
+            .find(|&x| !matches!(source.get_grapheme(x, 0), " " | ""))
+            .map_or_else(Style::default, |x| source.style(x, 0));
+        for _ in 0..MathBox::from_text(script).width {
+            result.set_style(x, 0, style);
+            x += 1;
+        }
+    }
+    result
+}
+
+/// An ASCII script after its `mark` (`^2`, `_(i+1)`); primes need no mark
+fn ascii_script(mark: &str, script: &MathBox) -> String {
+    let text = script.to_string();
+    let text = text.trim();
+    if !text.is_empty() && text.chars().all(|c| c == '\'') {
+        text.to_string()
+    } else if text.chars().count() == 1 || text.chars().all(|c| c.is_ascii_digit()) {
+        format!("{}{}", mark, text)
+    } else {
+        format!("{}({})", mark, text)
+    }
+}
+
+/// Replace what's left of non-ASCII drawing (fraction bars, rules, stretched
+/// arrows, big operator pieces) with ASCII stand-ins
+fn asciify(mbox: &mut MathBox) {
+    for y in 0..mbox.height {
+        for x in 0..mbox.width {
+            let cell = mbox.get_grapheme(x, y);
+            if cell.is_ascii() && !cell.is_empty() {
+                continue;
+            }
+            let mut chars = cell.chars();
+            let glyph = match (chars.next().and_then(ascii_glyph), to_ascii(cell)) {
+                (Some(glyph), _) => glyph.to_string(),
+                (None, ascii) if ascii.len() == 1 => ascii,
+                (None, ascii) if ascii.is_empty() => " ".to_string(),
+                _ => "?".to_string(),
+            };
+            mbox.set_grapheme(x, y, &glyph);
+        }
+    }
+}
+
+/// Replace what `profile` can't show: combining marks are dropped, and
+/// characters outside the Basic Multilingual Plane become their plain letter
+/// or ASCII form, or `?`
+fn degrade(mbox: &mut MathBox, profile: CapabilityProfile) {
+    for (_, _, cell) in mbox.cells_mut() {
+        let mut chars = cell.chars();
+        let Some(first) = chars.next() else {
+            continue;
+        };
+        let first = match first {
+            c if profile.supports_astral() || u32::from(c) <= 0xFFFF => c,
+            c => from_mathvariant(c)
+                .or_else(|| ascii_glyph(c))
+                .unwrap_or('?'),
+        };
+        let glyph: String = std::iter::once(first)
+            .chain(chars.filter(|c| c.width() != Some(0)))
+            .collect();
+        if *cell != glyph.as_str() {
+            *cell = glyph.as_str().into();
+        }
+    }
+}
+
+/// The element at `path` (as from [`node_path`]) in `doc`
+pub(crate) fn node_at<'a, 'input>(
+    doc: &'a Document<'input>,
+    path: &[usize],
+) -> Option<Node<'a, 'input>> {
+    path.iter().try_fold(doc.root_element(), |node, &index| {
+        node.children().filter(Node::is_element).nth(index)
+    })
+}
+
+/// Whether a node is a token element
+fn is_token(node: &Node) -> bool {
+    matches!(node.tag_name().name(), "mi" | "mn" | "mo" | "mtext" | "ms")
+}
+
+/// Index path of an element below the root `<math>`, counting element children
+pub(crate) fn node_path(node: &Node) -> Vec<usize> {
+    let mut path = Vec::new();
+    let mut current = *node;
+    while let Some(parent) = current.parent_element() {
+        path.push(
+            parent
+                .children()
+                .filter(Node::is_element)
+                .position(|n| n == current)
+                .unwrap_or(0),
+        );
+        current = parent;
+    }
+    path.reverse();
+    path
+}
+
+/// Fences, brackets and bars
+fn is_delimiter(op: &str) -> bool {
+    matches!(
+        op,
+        "(" | ")"
+            | "["
+            | "]"
+            | "{"
+            | "}"
+            | "⟨"
+            | "⟩"
+            | "〈"
+            | "〉"
+            | "|"
+            | "‖"
+            | "∣"
+            | "∥"
+            | "⌊"
+            | "⌋"
+            | "⌈"
+            | "⌉"
+    )
+}
+
+/// Greek letters, plain or in a math alphabet (bold, italic, ...)
+fn is_greek(c: char) -> bool {
+    matches!(c, '\u{0391}'..='\u{03A9}' | '\u{03B1}'..='\u{03C9}' | 'ϑ' | 'ϕ' | 'ϖ' | 'ϵ' | 'ϱ' | 'ϰ')
+        || ('\u{1D6A8}'..='\u{1D7C9}').contains(&c)
+}
+
+/// Check whether a node is an msub, msup or msubsup
+fn is_scripted(node: &Node) -> bool {
+    matches!(node.tag_name().name(), "msub" | "msup" | "msubsup")
+}
+
+/// Check whether a node is a script on an empty base, as written with `{}^{a}`
+fn is_floating_script(node: &Node) -> bool {
+    is_scripted(node)
+        && node
+            .children()
+            .find(|n| n.is_element())
+            .is_some_and(|base| base.tag_name().name() == "mrow" && !base.has_children())
+}
+
+/// Lay out a base with columns of prescripts before it and postscripts after it,
+/// superscripts above the base and subscripts below. Prescripts are right-aligned
+/// against the base, postscripts left-aligned.
+fn multiscripts(base: MathBox, pre: &[ScriptPair], post: &[ScriptPair]) -> MathBox {
+    let pairs = || pre.iter().chain(post);
+    let sup_height = pairs()
+        .filter_map(|(_, sup)| sup.as_ref())
+        .map(|b| b.height)
+        .max()
+        .unwrap_or(0);
+    let sub_height = pairs()
+        .filter_map(|(sub, _)| sub.as_ref())
+        .map(|b| b.height)
+        .max()
+        .unwrap_or(0);
+    let column_width = |(sub, sup): &ScriptPair| {
+        sub.as_ref()
+            .map_or(0, |b| b.width)
+            .max(sup.as_ref().map_or(0, |b| b.width))
+    };
+    let width = pairs().map(column_width).sum::<usize>() + base.width;
+    let mut result = MathBox::empty(
+        width,
+        sup_height + base.height + sub_height,
+        sup_height + base.baseline,
+    );
+
+    let mut place = |x: usize, pair: &ScriptPair, right_align: bool| {
+        let column = column_width(pair);
+        if let Some(sup) = &pair.1 {
+            let offset = if right_align { column - sup.width } else { 0 };
+            result.blit(sup, x + offset, sup_height - sup.height);
+        }
+        if let Some(sub) = &pair.0 {
+            let offset = if right_align { column - sub.width } else { 0 };
+            result.blit(sub, x + offset, sup_height + base.height);
+        }
+        x + column
+    };
+
+    let mut x = 0;
+    for pair in pre {
+        x = place(x, pair, true);
+    }
+    let base_x = x;
+    x += base.width;
+    for pair in post {
+        x = place(x, pair, false);
+    }
+    result.blit(&base, base_x, sup_height);
+    result
+}
+
+/// Check whether an operator is a relation that equations can be aligned on
+fn is_relation(op: &str) -> bool {
+    matches!(
+        op,
+        "=" | "≠"
+            | "<"
+            | ">"
+            | "≤"
+            | "≥"
+            | "≈"
+            | "≡"
+            | "∼"
+            | "≅"
+            | "∝"
+            | "→"
+            | "⇒"
+            | "⟹"
+            | "⟺"
+            | "⇔"
+            | "↦"
+    )
+}
+
+/// Check whether a node puts limits under or over its base
+fn is_limits(node: &Node) -> bool {
+    matches!(node.tag_name().name(), "munder" | "mover" | "munderover")
+}
+
+/// Check whether a node is a stretchy fence operator of the given form (prefix/postfix)
+fn is_fence(node: &Node, form: &str) -> bool {
+    node.tag_name().name() == "mo"
+        && node.attribute("stretchy") == Some("true")
+        && node.attribute("form") == Some(form)
+}
+
+/// Draw a diagonal strike through a box: ╱ (or ╲ if `down`) in blank cells along
+/// the diagonal, and a combining solidus overlay on glyphs the stroke crosses.
+/// Single-row boxes overlay every glyph.
+fn strike(mut mbox: MathBox, down: bool) -> MathBox {
+    let (line, overlay) = if down {
+        ('╲', '\u{20E5}')
+    } else {
+        ('╱', '\u{0338}')
+    };
+    let cells: Vec<(usize, usize)> = if mbox.height <= 1 {
+        (0..mbox.width)
+            .filter(|&x| mbox.get_grapheme(x, 0) != " ")
+            .map(|x| (x, 0))
+            .collect()
+    } else {
+        let span = (mbox.width.saturating_sub(1)) as f64 / (mbox.height - 1) as f64;
+        (0..mbox.height)
+            .map(|y| {
+                let step = if down { y } else { mbox.height - 1 - y };
+                ((step as f64 * span).round() as usize, y)
+            })
+            .collect()
+    };
+
+    for (x, y) in cells {
+        let g = mbox.get_grapheme(x, y).to_string();
+        match g.as_str() {
+            "" => {}
+            " " => mbox.set(x, y, line),
+            _ => mbox.set_grapheme(x, y, &format!("{}{}", g, overlay)),
+        }
+    }
+    mbox
+}
+
+/// Named MathML spaces in eighteenths of an em
+const NAMED_SPACES: &[(&str, f32)] = &[
+    ("veryverythinmathspace", 1.0),
+    ("verythinmathspace", 2.0),
+    ("thinmathspace", 3.0),
+    ("mediummathspace", 4.0),
+    ("thickmathspace", 5.0),
+    ("verythickmathspace", 6.0),
+    ("veryverythickmathspace", 7.0),
+];
+
+/// Length units in ems, taking 1em = 10pt as in TeX's default font
+const LENGTH_UNITS: &[(&str, f32)] = &[
+    ("em", 1.0),
+    ("ex", 0.43),
+    ("mu", 1.0 / 18.0),
+    ("pt", 0.1),
+    ("pc", 1.2),
+    ("px", 0.075),
+    ("bp", 0.1004),
+    ("mm", 0.2845),
+    ("cm", 2.845),
+    ("in", 7.227),
+];
+
+/// Convert a MathML length (e.g. `2em`, `1ex`, `12pt`, `thickmathspace`, `3`) to a
+/// number of character cells. Unitless numbers are ems. A terminal cell is roughly
+/// half an em wide, so one em is two cells. Any positive width takes at least one
+/// cell so thin spaces stay visible; negative widths are zero.
+fn length_to_cells(length: &str) -> Option<usize> {
+    let length = length.trim();
+    let named = |name: &str| {
+        NAMED_SPACES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, e)| e / 18.0)
+    };
+    let em = if let Some(em) = named(length) {
+        em
+    } else if let Some(em) = length.strip_prefix("negative").and_then(named) {
+        -em
+    } else {
+        let (number, scale) = LENGTH_UNITS
+            .iter()
+            .find_map(|(unit, scale)| Some((length.strip_suffix(unit)?, *scale)))
+            .unwrap_or((length, 1.0));
+        number.trim().parse::<f32>().ok()? * scale
+    };
+    Some(if em > 0.0 {
+        ((em * 2.0).round() as usize).max(1)
+    } else {
+        0
+    })
+}
+
+/// Parse an mpadded dimension into its sign (`+` and `-` adjust the content's
+/// size, otherwise it is replaced) and a number of cells. Besides lengths it
+/// accepts multiples of the content width (`2width`) and percentages (`50%`).
+fn padded_length(value: &str, content_width: usize) -> Option<(char, usize)> {
+    let value = value.trim();
+    let (sign, magnitude) = match value.chars().next()? {
+        sign @ ('+' | '-') => (sign, value[1..].trim()),
+        _ => (' ', value),
+    };
+    let relative = |factor: &str| {
+        factor
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .map(|f| (f * content_width as f32).round() as usize)
+    };
+    let cells = if let Some(percent) = magnitude
+        .strip_suffix("%width")
+        .or(magnitude.strip_suffix('%'))
+    {
+        relative(percent).map(|c| c / 100)?
+    } else if let Some(factor) = magnitude.strip_suffix("width") {
+        relative(factor)?
+    } else {
+        length_to_cells(magnitude)?
+    };
+    Some((sign, cells))
+}
+
+impl Default for MathRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_expression() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex("x + y").unwrap();
+        assert!(result.contains('x'));
+        assert!(result.contains('y'));
+    }
+
+    #[test]
+    fn test_superscript() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex("x^2").unwrap();
+        // Should contain Unicode superscript
+        assert!(result.contains('²') || result.contains('2'));
+    }
+
+    #[test]
+    fn test_fraction() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex(r"\frac{a}{b}").unwrap();
+        assert!(result.contains('a'));
+        assert!(result.contains('b'));
+        assert!(result.contains('─'));
+    }
+
+    #[test]
+    fn test_text_spacing() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex(r"c = \text{speed of light}").unwrap();
+        assert_eq!(result, "c = speed of light");
+        let result = renderer.render_latex(r"x \text{ if } = y").unwrap();
+        assert_eq!(result, "x if = y");
+        let result = renderer.render_latex(r"\text{pi}").unwrap();
+        assert_eq!(result, "pi");
+    }
+
+    #[test]
+    fn test_fraktur() {
+        let renderer = MathRenderer::new();
+        assert_eq!(renderer.render_latex(r"\mathfrak{su}(2)").unwrap(), "𝔰𝔲(2)");
+        assert_eq!(
+            renderer.render_latex(r"\mathfrak{g} \mathfrak{R}").unwrap(),
+            "𝔤ℜ"
+        );
+    }
+
+    #[test]
+    fn test_bold() {
+        let renderer = MathRenderer::new();
+        assert_eq!(renderer.render_latex(r"\mathbf{v}_{12}").unwrap(), "𝐯₁₂");
+        assert_eq!(renderer.render_latex(r"\mathbf{A1}").unwrap(), "𝐀𝟏");
+        assert_eq!(
+            renderer.render_latex(r"\boldsymbol{\alpha x}").unwrap(),
+            "𝜶𝒙"
+        );
+
+        let renderer = MathRenderer::new().use_unicode_bold(false);
+        let mbox = renderer.render_to_box(r"\mathbf{v} + w").unwrap();
+        assert_eq!(mbox.to_string(), "v + w");
+        assert!(mbox.style(0, 0).add_modifier.contains(Modifier::BOLD));
+        assert!(!mbox.style(4, 0).add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_mathvariants() {
+        let renderer = MathRenderer::new();
+        assert_eq!(renderer.render_latex(r"\mathbb{R}^3").unwrap(), "ℝ³");
+        assert_eq!(renderer.render_latex(r"\mathsf{A}").unwrap(), "𝖠");
+        assert_eq!(renderer.render_latex(r"\mathtt{x1}").unwrap(), "𝚡𝟷");
+        assert_eq!(renderer.render_latex(r"\mathit{h}").unwrap(), "ℎ");
+        assert_eq!(renderer.render_latex(r"\mathcal{L}").unwrap(), "ℒ");
+        assert_eq!(renderer.render_latex(r"\mathrm{d}x").unwrap(), "dx");
+    }
+
+    #[test]
+    fn test_wide_overline_underline() {
+        let renderer = MathRenderer::new();
+        assert_eq!(
+            renderer.render_latex(r"\overline{x+y}").unwrap(),
+            "‾‾‾‾‾\nx + y"
+        );
+        assert_eq!(
+            MathRenderer::new()
+                .ascii_only(true)
+                .render_latex(r"\overline{x+y}")
+                .unwrap(),
+            "_____\nx + y"
+        );
+        assert_eq!(renderer.render_latex(r"\underline{ab}").unwrap(), "ab\n¯¯");
+        assert_eq!(renderer.render_latex(r"\overline{z}").unwrap(), "z\u{0304}");
+    }
+
+    #[test]
+    fn test_boxed() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex(r"\boxed{E=mc^2}").unwrap();
+        assert_eq!(result, "┌───────┐\n│E = mc²│\n└───────┘");
+    }
+
+    #[test]
+    fn test_cancel() {
+        let renderer = MathRenderer::new();
+        assert_eq!(renderer.render_latex(r"\cancel{x}").unwrap(), "x\u{0338}");
+        let result = renderer.render_latex(r"\cancel{\frac{ab}{cd}}").unwrap();
+        assert_eq!(result, "ab\u{0338}\n──\u{0338}\nc\u{0338}d");
+    }
+
+    #[test]
+    fn test_extensible_arrows() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex(r"A \xrightarrow{f} B").unwrap();
+        assert_eq!(result, "   f\nA ──→ B");
+        let result = renderer.render_latex(r"\xleftarrow[gh]{g}").unwrap();
+        assert_eq!(result, " g\n←───\n gh");
+    }
+
+    #[test]
+    fn test_dfrac_tfrac() {
+        let renderer = MathRenderer::new();
+        assert_eq!(
+            renderer.render_latex(r"\tfrac{a+b}{2}").unwrap(),
+            "(a + b)/2"
+        );
+        assert_eq!(renderer.render_latex(r"\dfrac{a}{b}").unwrap(), "a\n─\nb");
+    }
+
+    #[test]
+    fn test_array_column_spec() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\begin{array}{l|cr} a & b & c \\ dd & e & ff \end{array}")
+            .unwrap();
+        assert_eq!(result, "a  │ b  c\ndd │ e ff");
+    }
+
+    #[test]
+    fn test_table_rules() {
+        let renderer = MathRenderer::new();
+        let mathml = r#"<math><mtable rowlines="solid" columnlines="solid" frame="solid"><mtr><mtd><mi>p</mi></mtd><mtd><mi>q</mi></mtd></mtr><mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr></mtable></math>"#;
+        let result = renderer.render_mathml(mathml).unwrap();
+        assert_eq!(
+            result,
+            "┌───┬───┐\n│ p │ q │\n├───┼───┤\n│ 1 │ 0 │\n└───┴───┘"
+        );
+        let result = renderer
+            .render_latex(r"\begin{array}{c|c} a & b \\ \hline c & d \end{array}")
+            .unwrap();
+        assert_eq!(result, "a │ b\n──┼──\nc │ d");
+    }
+
+    #[test]
+    fn test_tag() {
+        let renderer = MathRenderer::new();
+        let mbox = renderer.render_to_box(r"x^2 = 4 \tag{3.1}").unwrap();
+        assert_eq!(mbox.to_string(), "x² = 4  (3.1)");
+        assert_eq!(
+            mbox.label().map(|l| l.to_string()),
+            Some("(3.1)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let renderer = MathRenderer::new();
+        assert_eq!(
+            renderer.render_latex(r"a = b + c \\ d = e").unwrap(),
+            "a = b + c\n  d = e"
+        );
+        assert_eq!(
+            renderer
+                .render_latex(r"\begin{gather} x = 1 \\ y \end{gather}")
+                .unwrap(),
+            "x = 1\n  y"
+        );
+    }
+
+    #[test]
+    fn test_render_aligned() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_aligned(&["(a + b)^2 = a^2 + 2ab + b^2", "x = 1", "y \\leq 2"])
+            .unwrap();
+        assert_eq!(
+            result,
+            "(a + b)² = a² + 2ab + b²\n       x = 1\n       y ≤ 2"
+        );
+    }
+
+    #[test]
+    fn test_spacing_commands() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex(r"a\quad b\qquad c\,d\!e~f").unwrap();
+        assert_eq!(result, "a  b    c de f");
+        let result = renderer
+            .render_latex(r"a\hspace{3em}b\hspace*{10pt}c")
+            .unwrap();
+        assert_eq!(result, "a      b  c");
+    }
+
+    #[test]
+    fn test_primes() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex("f'(x) + x'' + g'''^2").unwrap();
+        assert_eq!(result, "f′(x) + x″ + g‴²");
+        assert_eq!(renderer.render_latex("x_1'").unwrap(), "x₁′");
+    }
+
+    #[test]
+    fn test_subscript_superscript_pair() {
+        let renderer = MathRenderer::new();
+        assert_eq!(renderer.render_latex("x_i^2 + y^2_j").unwrap(), "xᵢ² + yⱼ²");
+    }
+
+    #[test]
+    fn test_operator_spacing() {
+        let renderer = MathRenderer::new();
+        assert_eq!(renderer.render_latex("a = -b").unwrap(), "a = -b");
+        assert_eq!(renderer.render_latex(r"(-x) \cdot y").unwrap(), "(-x) · y");
+        assert_eq!(
+            renderer.render_latex(r"x \in A \cup B").unwrap(),
+            "x ∈ A ∪ B"
+        );
+        let mathml = r#"<math><mi>a</mi><mo lspace="0" rspace="2em">=</mo><mi>b</mi><mo form="prefix">-</mo><mi>c</mi></math>"#;
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "a=    b-c");
+
+        let latex = r"f(a, b) = \sum x + 1";
+        let spaced = |spacing| {
+            let options = RendererOptions {
+                spacing,
+                ..RendererOptions::default()
+            };
+            MathRenderer::with_options(options)
+                .render_latex(latex)
+                .unwrap()
+        };
+        assert_eq!(spaced(Spacing::Tight), "f(a,b) = ∑x+1");
+        assert_eq!(spaced(Spacing::Normal), "f(a,b) = ∑x + 1");
+        assert_eq!(spaced(Spacing::Loose), "f(a, b)  =  ∑ x + 1");
+    }
+
+    #[test]
+    fn test_symbol_overrides() {
+        let renderer = MathRenderer::new()
+            .override_symbol(r"\epsilon", "ε")
+            .override_symbol("leq", "=<")
+            .override_symbol("newsym", "★");
+        assert_eq!(
+            renderer
+                .render_latex(r"\epsilon \leq \varepsilon \newsym")
+                .unwrap(),
+            "ε =< ε★"
+        );
+        assert_ne!(renderer.fingerprint(), MathRenderer::new().fingerprint());
+    }
+
+    #[test]
+    fn test_ascii_accents() {
+        let renderer = MathRenderer::new().accents(AccentStrategy::Ascii);
+        let result = renderer
+            .render_latex(r"\hat{x} + \vec{v} = \bar{xy} + \widehat{AB}")
+            .unwrap();
+        assert_eq!(
+            result,
+            "                  ‾‾\nhat(x) + vec(v) = xy + hat(AB)"
+        );
+        assert_eq!(
+            renderer.render_latex(r"\tilde{\frac{a}{b}}").unwrap(),
+            "     ⎛a⎞\ntilde⎜─⎟\n     ⎝b⎠"
+        );
+    }
+
+    #[test]
+    fn test_capability_profiles() {
+        let latex = r"\mathbf{x} \in \mathbb{R} + \hat{y}";
+        let render = |profile| {
+            MathRenderer::new()
+                .capability_profile(profile)
+                .render_to_box(latex)
+                .unwrap()
+        };
+        assert_eq!(
+            render(CapabilityProfile::FullUnicode).to_string(),
+            "𝐱 ∈ ℝ + y\u{302}"
+        );
+        assert_eq!(
+            render(CapabilityProfile::NoCombining).to_string(),
+            "        ^\n𝐱 ∈ ℝ + y"
+        );
+        let bmp = render(CapabilityProfile::BmpOnly);
+        assert_eq!(bmp.to_string(), "        ^\nx ∈ ℝ + y");
+        assert!(bmp.style(0, 1).add_modifier.contains(Modifier::BOLD));
+        assert_eq!(
+            render(CapabilityProfile::Ascii).to_string(),
+            "         ^\nx in R + y"
+        );
+        let renderer = MathRenderer::new().capability_profile(CapabilityProfile::BmpOnly);
+        let mathml = "<math><mi>𝐱</mi><mo>+</mo><mn>1</mn></math>";
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "x + 1");
+    }
+
+    #[test]
+    fn test_render_calc() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_calc("x^2/(y+1) + sqrt(2)").unwrap();
+        assert_eq!(result, " x²      _\n───── + √2\ny + 1");
+    }
+
+    #[test]
+    fn test_ascii_only() {
+        let renderer = MathRenderer::new().ascii_only(true);
+        let result = renderer
+            .render_latex(r"\frac{x^2+1}{y} + \sqrt{\alpha_i}")
+            .unwrap();
+        assert_eq!(result, "x^2 + 1\n------- + sqrt(alpha_i)\n   y");
+        let result = renderer
+            .render_latex(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix} \leq \infty")
+            .unwrap();
+        assert_eq!(result, "/a  b\\\n\\c  d/ <= inf");
+        let result = renderer.render_latex(r"\sum_{i=1}^n \frac{1}{i}").unwrap();
+        assert!(result.is_ascii(), "{}", result);
+    }
+
+    #[test]
+    fn test_renderer_options() {
+        let options = RendererOptions {
+            style: MathStyle::Text,
+            spacing: Spacing::Tight,
+            accents: AccentStrategy::Stacked,
+            ..RendererOptions::default()
+        };
+        let renderer = MathRenderer::with_options(options);
+        assert_eq!(
+            renderer.render_latex(r"\hat{x} = \frac{a}{b} + 1").unwrap(),
+            "^\nx = a/b+1"
+        );
+        assert_eq!(renderer.render_latex(r"\dfrac{a}{b}").unwrap(), "a\n─\nb");
+
+        let options = RendererOptions {
+            brackets: BracketSet::Ascii,
+            max_width: Some(12),
+            ..RendererOptions::default()
+        };
+        let result = MathRenderer::with_options(options)
+            .render_latex(r"\left( \frac{a}{b} \right) + c + d = e")
+            .unwrap();
+        assert_eq!(result, "/a\\\n|─| + c + d\n\\b/\n  = e");
+        let options = RendererOptions {
+            max_width: Some(6),
+            ..RendererOptions::default()
+        };
+        let renderer = MathRenderer::with_options(options);
+        let mathml = renderer.latex_to_mathml("a + b + c").unwrap();
+        assert_eq!(
+            renderer.render_mathml(&mathml).unwrap(),
+            renderer.render_latex("a + b + c").unwrap()
+        );
+        assert_eq!(renderer.render_mathml(&mathml).unwrap(), "a + b\n  + c");
+    }
+
+    #[test]
+    fn test_render_latex_styled() {
+        let styles = TokenStyles::colored();
+        let renderer = MathRenderer::new().token_styles(styles);
+        let text = renderer.render_latex_styled(r"x^2 + \alpha").unwrap();
+        let spans: Vec<_> = text.lines[0]
+            .spans
+            .iter()
+            .map(|s| (s.content.as_ref(), s.style))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("x", styles.identifier),
+                ("²", styles.number),
+                (" ", Style::default()),
+                ("+", styles.operator),
+                (" ", Style::default()),
+                ("α", styles.greek),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_newcommand() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\newcommand{\R}{\mathbb{R}} f: \R \to \R")
+            .unwrap();
+        assert_eq!(result, "f : ℝ → ℝ");
+        let renderer = MathRenderer::new().with_preamble(r"\newcommand{\sq}[1]{#1^2}");
+        assert_eq!(
+            renderer.render_latex(r"\sq{x} + \sq{y}").unwrap(),
+            "x² + y²"
+        );
+    }
+
+    #[test]
+    fn test_define_macro() {
+        let mut renderer = MathRenderer::new();
+        renderer.define_macro(r"\E", r"\mathbb{E}\left[ #1 \right]", 1);
+        renderer.define_macro("eps", r"\varepsilon", 0);
+        assert_eq!(renderer.render_latex(r"\E{X} + \eps").unwrap(), "𝔼[X] + ε");
+        assert!(renderer.render_latex(r"\newcommand{\x}{\x\x}\x").is_err());
+    }
+
+    #[test]
+    fn test_physics_macro_set() {
+        let renderer = MathRenderer::new().with_macro_set(MacroSet::Physics);
+        let result = renderer
+            .render_latex(r"\dv{f}{x} = \abs{\frac{a}{b}}")
+            .unwrap();
+        assert_eq!(result, "df   │a│\n── = │─│\ndx   │b│");
+    }
+
+    #[test]
+    fn test_not() {
+        let renderer = MathRenderer::new();
+        assert_eq!(renderer.render_latex(r"a \not= b").unwrap(), "a ≠ b");
+        assert_eq!(renderer.render_latex(r"x \not\in A").unwrap(), "x ∉ A");
+        assert_eq!(
+            renderer.render_latex(r"x \not\sqsubset y").unwrap(),
+            "x ⊏\u{0338} y"
+        );
+        assert_eq!(renderer.render_latex(r"\not\in").unwrap(), "∉");
+        assert_eq!(
+            renderer.render_latex(r"\not{ab} + c").unwrap(),
+            "a\u{0338}b\u{0338} + c"
+        );
+        assert_eq!(
+            renderer.render_latex(r"x \not{\frac{a}{b}} y").unwrap(),
+            "  a\u{0338}\nx ─\u{0338} y\n  b\u{0338}"
+        );
+    }
+
+    #[test]
+    fn test_bra_ket() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\bra{\psi} \ket{\phi} = \braket{\psi|\phi}")
+            .unwrap();
+        assert_eq!(result, "⟨ψ||ϕ⟩ = ⟨ψ|ϕ⟩");
+        let result = renderer.render_latex(r"\ket{\frac{a}{b}}").unwrap();
+        assert_eq!(result, "│a╲\n│─⟩\n│b╱");
+    }
+
+    #[test]
+    fn test_chemistry() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\ce{SO4^2- + Ba^2+ -> BaSO4(s)}")
+            .unwrap();
+        assert_eq!(result, "SO₄²⁻ + Ba²⁺ → BaSO₄(s)");
+    }
+
+    #[test]
+    fn test_si_units() {
+        let renderer = MathRenderer::new();
+        assert_eq!(
+            renderer.render_latex(r"\SI{3.0e8}{m/s}").unwrap(),
+            "3.0 × 10⁸ m/s"
+        );
+        assert_eq!(
+            renderer.render_latex(r"\si{kg.m.s^{-2}}").unwrap(),
+            "kg m s⁻²"
+        );
+    }
+
+    #[test]
+    fn test_multiscripts() {
+        let renderer = MathRenderer::new();
+        assert_eq!(
+            renderer.render_latex(r"{}^{a}T_{b}{}^{c}").unwrap(),
+            "a  c\n T\n  b"
+        );
+
+        let mathml = r#"<math><mmultiscripts><mi>C</mi><none/><mn>2</mn><mprescripts/><mn>6</mn><mn>14</mn></mmultiscripts></math>"#;
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "14 2\n  C\n 6");
+    }
+
+    #[test]
+    fn test_tall_integral() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\int_0^1 \frac{x^2}{1+x} dx")
+            .unwrap();
+        assert_eq!(result, "⌠1  x²\n⎮  ───── dx\n⌡0 1 + x");
+        // Single-line integrands keep the single glyph
+        assert_eq!(renderer.render_latex(r"\int f").unwrap(), "∫f");
+    }
+
+    #[test]
+    fn test_tall_sum() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\sum_{n=1}^{\infty} \frac{1}{n^2}")
+            .unwrap();
+        assert_eq!(
+            result,
+            "  ∞\n ___\n ╲    1\n  ╲   ──\n  ╱   n²\n ╱\n ‾‾‾\nn = 1"
+        );
+        let result = renderer.render_latex(r"\prod \frac{a}{b}").unwrap();
+        assert_eq!(result, "┬──┬\n│  │ a\n│  │ ─\n│  │ b");
+        // Single-line summands keep the single glyph
+        assert_eq!(renderer.render_latex(r"\sum_i x_i").unwrap(), "∑ xᵢ\ni");
+    }
+
+    #[test]
+    fn test_stretchy_operators() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex(r"\frac{a}{b} \middle| c").unwrap();
+        assert_eq!(result, "a │\n─ │ c\nb │");
+        let mathml = r#"<math><mo stretchy="true">↕</mo><mfrac><mi>a</mi><mi>b</mi></mfrac><mo>↕</mo></math>"#;
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "↑ a\n│ ─ ↕\n↓ b");
+    }
+
+    #[test]
+    fn test_evaluation_bar() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\left. \frac{df}{dx} \right|_{x=0}")
+            .unwrap();
+        assert_eq!(result, "df│\n──│\ndx│x=0");
+        let result = renderer
+            .render_latex(r"\left. \frac{x^2}{2} \right|_0^1")
+            .unwrap();
+        assert_eq!(result, "x²│1\n──│\n2 │0");
+    }
+
+    #[test]
+    fn test_fenced_separators() {
+        let renderer = MathRenderer::new();
+        let mathml = "<math><mfenced><mi>a</mi><mi>b</mi><mi>c</mi></mfenced></math>";
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "(a,b,c)");
+        let mathml = r#"<math><mfenced open="[" close="]" separators="; |"><mi>a</mi><mi>b</mi><mi>c</mi><mi>d</mi></mfenced></math>"#;
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "[a;b|c|d]");
+        let mathml = r#"<math><mfenced separators=""><mi>a</mi><mi>b</mi></mfenced></math>"#;
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "(ab)");
+    }
+
+    #[test]
+    fn test_string_literal() {
+        let renderer = MathRenderer::new();
+        let mathml = "<math><mi>f</mi><mo>(</mo><ms>a b</ms><mo>)</mo></math>";
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "f(\"a b\")");
+        let mathml = r#"<math><ms lquote="«" rquote="»">x</ms></math>"#;
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "«x»");
+    }
+
+    #[test]
+    fn test_maction() {
+        let mathml = r#"<math><maction actiontype="toggle" selection="2" id="step"><mi>a</mi><mi>b</mi><mi>c</mi></maction></math>"#;
+        assert_eq!(MathRenderer::new().render_mathml(mathml).unwrap(), "b");
+        let toggles = HashMap::from([("step".to_string(), 2)]);
+        let renderer = MathRenderer::new().action_toggles(toggles);
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_mpadded() {
+        let renderer = MathRenderer::new();
+        let mathml = r#"<math><mi>a</mi><mpadded width="0"><mi>x</mi></mpadded><mi>b</mi></math>"#;
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "ab");
+        let mathml = r#"<math><mi>a</mi><mpadded lspace="1em" width="+2em"><mi>x</mi></mpadded><mi>b</mi></math>"#;
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "a  x  b");
+        let mathml = r#"<math><mpadded width="200%"><mi>x</mi></mpadded><mi>b</mi></math>"#;
+        assert_eq!(renderer.render_mathml(mathml).unwrap(), "x b");
+    }
+
+    #[test]
+    fn test_length_to_cells() {
+        assert_eq!(length_to_cells("2em"), Some(4));
+        assert_eq!(length_to_cells("3"), Some(6));
+        assert_eq!(length_to_cells("1ex"), Some(1));
+        assert_eq!(length_to_cells("20pt"), Some(4));
+        assert_eq!(length_to_cells("18mu"), Some(2));
+        assert_eq!(length_to_cells("thickmathspace"), Some(1));
+        assert_eq!(length_to_cells("negativethinmathspace"), Some(0));
+        assert_eq!(length_to_cells("wide"), None);
+    }
+
+    #[test]
+    fn test_merror() {
+        let renderer = MathRenderer::new();
+        let mathml = "<math><mi>x</mi><mo>+</mo><merror><mtext>Undefined control sequence</mtext></merror></math>";
+        let doc = Document::parse(mathml).unwrap();
+        let mbox = renderer.process_element(&doc.root_element()).unwrap();
+        assert_eq!(mbox.to_string(), "x + ⟨!Undefined control sequence!⟩");
+        assert_eq!(mbox.style(4, 0).fg, Some(Color::Red));
+        assert_eq!(mbox.style(0, 0).fg, None);
+    }
+
+    #[test]
+    fn test_binom() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex(r"\binom{n}{k} p").unwrap();
+        assert_eq!(result, "⎛n⎞\n⎝k⎠ p");
+        let mbox = renderer.render_to_box(r"\binom{n}{k}").unwrap();
+        assert_eq!(mbox.baseline, 1);
+    }
+
+    #[test]
+    fn test_left_right_scaling() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\left[ \frac{a}{b} \right]")
+            .unwrap();
+        assert_eq!(result, "⎡a⎤\n⎢─⎥\n⎣b⎦");
+    }
+
+    #[test]
+    fn test_modular_arithmetic() {
+        let renderer = MathRenderer::new();
+        let result = renderer.render_latex(r"a \equiv b \pmod{n}").unwrap();
+        assert_eq!(result, "a ≡ b (mod n)");
+        let result = renderer.render_latex(r"a \bmod b").unwrap();
+        assert_eq!(result, "a mod b");
+    }
+
+    #[test]
+    fn test_substack() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\sum_{\substack{i=1 \\ i \ne j}}^{n}")
+            .unwrap();
+        assert_eq!(result, "  n\n  ∑\ni = 1\ni ≠ j");
+    }
+
+    #[test]
+    fn test_latex_to_mathml() {
+        let mut renderer = MathRenderer::new();
+        renderer.define_macro("sq", "#1^2", 1);
+        let mathml = renderer.latex_to_mathml(r"\sq{y}").unwrap();
+        assert!(mathml.starts_with("<math"));
+        assert!(mathml.contains("<msup><mi>y</mi><mn>2</mn></msup>"));
+    }
+
+    #[test]
+    fn test_render_to_buffer() {
+        let mut buf = Buffer::with_lines(["..........", ".........."]);
+        let area = Rect::new(2, 0, 4, 2);
+        let style = Style::default().fg(Color::Green);
+        MathRenderer::new()
+            .render_to_buffer("x + y", area, &mut buf, style)
+            .unwrap();
+
+        // Clipped to the area, with the blanks between tokens left untouched
+        let mut expected = Buffer::with_lines(["..x.+.....", ".........."]);
+        for x in [2, 4] {
+            expected[(x, 0)].set_style(style);
+        }
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn test_render_latex_spans() {
+        let renderer = MathRenderer::new().token_styles(TokenStyles::colored());
+        let spans = renderer
+            .render_latex_spans("x^2", Style::default().add_modifier(Modifier::BOLD))
+            .unwrap();
+        let text: String = spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, "x²");
+        assert_eq!(
+            spans[0].style,
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD)
+        );
+        assert!(renderer
+            .render_latex_spans(r"\frac{1}{2}", Style::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_semantics() {
+        let latex = r"\frac{a}{b} + 1";
+        let mbox = MathRenderer::new()
+            .semantics(true)
+            .render_to_box(latex)
+            .unwrap();
+        assert_eq!(mbox.to_string(), "a\n─ + 1\nb");
+        let at_a: Vec<_> = mbox
+            .semantics_at(0, 0)
+            .map(|s| (s.element.as_str(), s.latex.clone()))
+            .collect();
+        assert_eq!(at_a[..2], [("mi", Some(6..7)), ("mfrac", None)]);
+        let one = mbox.semantics_at(4, 1).find(|s| s.is_token()).unwrap();
+        assert_eq!(
+            (one.area, one.latex.clone()),
+            (Rect::new(4, 1, 1, 1), Some(14..15))
+        );
+        assert!(MathRenderer::new()
+            .render_to_box(latex)
+            .unwrap()
+            .semantics()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_render_with_baseline() {
+        let renderer = MathRenderer::new();
+        assert_eq!(
+            renderer.render_with_baseline("x^2").unwrap(),
+            ("x²".to_string(), 0)
+        );
+        let (text, baseline) = renderer.render_with_baseline(r"\sum_{i=1}^{n} i").unwrap();
+        assert_eq!(text.lines().nth(baseline), Some("  ∑   i"));
+    }
+
+    #[test]
+    fn test_eqn_style() {
+        let renderer = MathRenderer::new().eqn_style(true);
+        assert_eq!(
+            renderer.render_latex("x^2 + y_i").unwrap(),
+            " 2\nx  + y\n      i"
+        );
+        assert_eq!(
+            renderer.render_latex(r"\sqrt{x+1}").unwrap(),
+            "  _____\n\\/x + 1"
+        );
+        assert_eq!(
+            renderer.render_latex(r"\frac{\alpha}{2}").unwrap(),
+            "alpha\n-----\n  2"
+        );
+    }
+
+    #[test]
+    fn test_highlight() {
+        let mark = Style::default().bg(Color::Blue);
+        let renderer = MathRenderer::new().highlight(&[0, 0], mark);
+        let mbox = renderer.render_to_box(r"\frac{a+1}{b} + c").unwrap();
+        assert_eq!(mbox.to_string(), "a + 1\n───── + c\n  b");
+        // The numerator is highlighted, the spaces inside it included
+        assert!((0..5).all(|x| mbox.style(x, 0) == mark));
+        assert_eq!(mbox.style(0, 1), Style::default());
+        assert_eq!(mbox.style(2, 2), Style::default());
+    }
+
+    #[test]
+    fn test_aligned() {
+        let renderer = MathRenderer::new();
+        let result = renderer
+            .render_latex(r"\begin{aligned} f(x) &= (x+1)^2 \\ &= x^2 + 2x + 1 \end{aligned}")
+            .unwrap();
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].find('='), lines[1].find('='));
     }
 }
@@ -0,0 +1,271 @@
+//! Step-by-step reveal of an expression, for presentations and teaching
+
+use crate::renderer::node_path;
+use crate::unicode_maps::{operator_class, OperatorClass};
+use crate::widget::box_to_lines;
+use crate::{MathBox, MathRenderer, RenderError};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Paragraph, StatefulWidget, Widget},
+};
+use roxmltree::{Document, Node};
+
+/// What each step of a [`MathReveal`] uncovers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RevealBy {
+    /// The next term: everything up to the next top-level `+`, `−`, `=` or
+    /// other binary operator or relation
+    #[default]
+    Terms,
+    /// The next line of an aligned or multi-line block
+    Lines,
+}
+
+/// How far a [`MathReveal`] has got
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RevealState {
+    step: usize,
+    steps: Option<usize>,
+}
+
+impl RevealState {
+    /// Start with only the first term or line shown
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reveal the next term or line
+    pub fn step(&mut self) {
+        self.step = match self.steps {
+            Some(steps) => (self.step + 1).min(steps.saturating_sub(1)),
+            None => self.step + 1,
+        };
+    }
+
+    /// Hide the last revealed term or line again
+    pub fn step_back(&mut self) {
+        self.step = self.step.saturating_sub(1);
+    }
+
+    /// Go back to showing only the first term or line
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+
+    /// Index of the last term or line shown
+    pub fn current(&self) -> usize {
+        self.step
+    }
+
+    /// Whether everything is shown, as of the last render
+    pub fn is_complete(&self) -> bool {
+        self.steps.is_some_and(|steps| self.step + 1 >= steps)
+    }
+}
+
+/// A widget revealing an expression a term or a line at a time as its
+/// [`RevealState`] steps. The whole expression is laid out from the start,
+/// with parts not yet revealed left blank, so nothing moves as it fills in.
+#[derive(Clone)]
+pub struct MathReveal<'a> {
+    latex: &'a str,
+    by: RevealBy,
+    style: Style,
+    block: Option<Block<'a>>,
+    use_unicode_scripts: bool,
+}
+
+impl<'a> MathReveal<'a> {
+    /// Create a new MathReveal from a LaTeX expression
+    pub fn new(latex: &'a str) -> Self {
+        Self {
+            latex,
+            by: RevealBy::Terms,
+            style: Style::default(),
+            block: None,
+            use_unicode_scripts: true,
+        }
+    }
+
+    /// Set what each step uncovers (terms by default)
+    pub fn by(mut self, by: RevealBy) -> Self {
+        self.by = by;
+        self
+    }
+
+    /// Set the style for the rendered math
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Wrap the widget in a block
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Enable or disable Unicode superscript/subscript characters
+    pub fn use_unicode_scripts(mut self, use_unicode: bool) -> Self {
+        self.use_unicode_scripts = use_unicode;
+        self
+    }
+
+    /// Render with steps after `step` hidden, returning the box and the
+    /// number of steps
+    fn render_step(&self, step: usize) -> Result<(MathBox, usize), RenderError> {
+        let renderer = MathRenderer::new().use_unicode_scripts(self.use_unicode_scripts);
+        let mathml = renderer.latex_to_mathml(self.latex)?;
+        let doc = Document::parse(&mathml).map_err(|e| RenderError::MathMLParse(e.to_string()))?;
+        let units = match self.by {
+            RevealBy::Terms => terms(doc.root_element()),
+            RevealBy::Lines => lines(doc.root_element()),
+        };
+        let renderer = units
+            .iter()
+            .skip(step + 1)
+            .flatten()
+            .fold(renderer, |renderer, path| renderer.hide(path));
+        Ok((renderer.render_mathml_to_box(&mathml)?, units.len()))
+    }
+}
+
+/// Top-level parts of the expression, each a list of element paths, split
+/// before binary operators and relations
+fn terms(root: Node) -> Vec<Vec<Vec<usize>>> {
+    let mut parent = root;
+    while let [only] = parent
+        .children()
+        .filter(Node::is_element)
+        .collect::<Vec<_>>()[..]
+    {
+        if only.tag_name().name() != "mrow" {
+            break;
+        }
+        parent = only;
+    }
+    let mut units: Vec<Vec<Vec<usize>>> = Vec::new();
+    for child in parent.children().filter(Node::is_element) {
+        let splits = child.tag_name().name() == "mo"
+            && matches!(
+                operator_class(child.text().unwrap_or("").trim()),
+                OperatorClass::Binary | OperatorClass::Relation
+            );
+        match units.last_mut() {
+            Some(unit) if !splits => unit.push(node_path(&child)),
+            _ => units.push(vec![node_path(&child)]),
+        }
+    }
+    units
+}
+
+/// Rows of the first table in the expression, each a list of the element
+/// paths in its cells; the whole expression if it has no rows
+fn lines(root: Node) -> Vec<Vec<Vec<usize>>> {
+    let Some(table) = root.descendants().find(|n| n.tag_name().name() == "mtable") else {
+        return vec![vec![Vec::new()]];
+    };
+    table
+        .children()
+        .filter(|row| matches!(row.tag_name().name(), "mtr" | "mlabeledtr"))
+        .map(|row| {
+            row.children()
+                .filter(Node::is_element)
+                .flat_map(|cell| cell.children().filter(Node::is_element))
+                .map(|node| node_path(&node))
+                .collect()
+        })
+        .collect()
+}
+
+impl StatefulWidget for MathReveal<'_> {
+    type State = RevealState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut RevealState) {
+        let lines = match self.render_step(state.step) {
+            Ok((mbox, steps)) => {
+                state.steps = Some(steps);
+                state.step = state.step.min(steps.saturating_sub(1));
+                box_to_lines(&mbox, self.style)
+            }
+            Err(e) => vec![Line::from(Span::styled(
+                format!("Error: {}", e),
+                self.style,
+            ))],
+        };
+
+        let mut paragraph = Paragraph::new(lines);
+        if let Some(block) = self.block {
+            paragraph = paragraph.block(block);
+        }
+        paragraph.render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(widget: MathReveal, state: &mut RevealState) -> Vec<String> {
+        let area = Rect::new(0, 0, 14, 2);
+        let mut buf = Buffer::empty(area);
+        widget.render(area, &mut buf, state);
+        (0..area.height)
+            .map(|y| {
+                (0..area.width)
+                    .map(|x| buf[(x, y)].symbol())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reveal_terms() {
+        let mut state = RevealState::new();
+        assert_eq!(
+            rows(MathReveal::new("x^2 + 2x = 1"), &mut state),
+            ["x²", ""]
+        );
+        state.step();
+        assert_eq!(
+            rows(MathReveal::new("x^2 + 2x = 1"), &mut state),
+            ["x² + 2x", ""]
+        );
+        state.step();
+        state.step();
+        assert_eq!(
+            rows(MathReveal::new("x^2 + 2x = 1"), &mut state),
+            ["x² + 2x = 1", ""]
+        );
+        assert!(state.is_complete());
+    }
+
+    #[test]
+    fn test_reveal_with_macros() {
+        let latex = r"\newcommand{\sq}[1]{#1^2} \sq{x} + 1";
+        let mut state = RevealState::new();
+        assert_eq!(rows(MathReveal::new(latex), &mut state), ["x²", ""]);
+        state.step();
+        assert_eq!(rows(MathReveal::new(latex), &mut state), ["x² + 1", ""]);
+    }
+
+    #[test]
+    fn test_reveal_lines() {
+        let latex = r"\begin{aligned} a &= b \\ &= c \end{aligned}";
+        let mut state = RevealState::new();
+        assert_eq!(
+            rows(MathReveal::new(latex).by(RevealBy::Lines), &mut state),
+            ["a = b", ""]
+        );
+        state.step();
+        assert_eq!(
+            rows(MathReveal::new(latex).by(RevealBy::Lines), &mut state),
+            ["a = b", "  = c"]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Sixel graphics export of the rendered layout
+//!
+//! The MathBox is rasterized (see [`crate::raster`]) and emitted as a sixel
+//! image for terminals that display them.
+
+use crate::raster::{rasterize, rgb, CellSize, Raster};
+use crate::MathBox;
+use ratatui::style::Color;
+use std::fmt::Write;
+
+/// Rasterize a MathBox (and its label) and encode it as a sixel image. Text
+/// without a color of its own is drawn in `foreground`.
+pub(crate) fn box_to_sixel(mbox: &MathBox, foreground: Color) -> String {
+    encode(&rasterize(mbox, foreground, &CellSize::IMAGE))
+}
+
+/// Encode a raster as a sixel string; unset pixels stay transparent
+fn encode(raster: &Raster) -> String {
+    // P2 = 1 leaves pixels that aren't drawn transparent
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", raster.width, raster.height);
+    for (index, &color) in raster.palette.iter().enumerate() {
+        let (r, g, b) = rgb(color);
+        let percent = |c: &u8| u32::from(*c) * 100 / 255;
+        let _ = write!(
+            out,
+            "#{};2;{};{};{}",
+            index,
+            percent(&r),
+            percent(&g),
+            percent(&b)
+        );
+    }
+
+    for band in 0..raster.height.div_ceil(6) {
+        for color in 0..raster.palette.len() {
+            let sixels: Vec<u8> = (0..raster.width)
+                .map(|x| {
+                    (0..6)
+                        .filter(|dy| {
+                            let y = band * 6 + dy;
+                            y < raster.height && raster.pixels[y * raster.width + x] == Some(color)
+                        })
+                        .fold(0, |bits, dy| bits | (1 << dy))
+                })
+                .collect();
+            if sixels.iter().all(|&bits| bits == 0) {
+                continue;
+            }
+            let _ = write!(out, "#{}", color);
+            // Run-length encode repeated columns
+            let mut x = 0;
+            while x < sixels.len() {
+                let run = sixels[x..]
+                    .iter()
+                    .take_while(|&&bits| bits == sixels[x])
+                    .count();
+                let ch = char::from(63 + sixels[x]);
+                if run > 3 {
+                    let _ = write!(out, "!{}{}", run, ch);
+                } else {
+                    out.extend(std::iter::repeat_n(ch, run));
+                }
+                x += run;
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_to_sixel() {
+        let sixel = box_to_sixel(&MathBox::from_text("─"), Color::White);
+        assert!(sixel.starts_with("\x1bP0;1;0q\"1;1;12;24#0;2;100;100;100"));
+        assert!(sixel.ends_with("\x1b\\"));
+        // The bar is two pixels thick halfway down the cell, straddling the
+        // second and third six-pixel bands
+        assert!(sixel.ends_with("100-#0!12_$-#0!12@$--\x1b\\"));
+    }
+}
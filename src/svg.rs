@@ -0,0 +1,128 @@
+//! SVG export of the rendered layout
+//!
+//! Each grapheme of the MathBox is placed as text on a monospace grid, and
+//! drawing characters (fraction bars, rules, radicals, brackets, arrows) become
+//! line segments, so the picture matches the terminal rendering at any size.
+
+use crate::canvas_widget::glyph_strokes;
+use crate::MathBox;
+use ratatui::style::{Color, Modifier, Style};
+use std::fmt::Write;
+
+/// Size of a grid cell in SVG user units
+const CELL_WIDTH: f64 = 10.0;
+const CELL_HEIGHT: f64 = 20.0;
+const FONT_SIZE: f64 = 16.0;
+
+/// Draw a MathBox (and its label) as an SVG document
+pub(crate) fn box_to_svg(mbox: &MathBox) -> String {
+    let mbox = mbox.place_label(0);
+    let width = mbox.width as f64 * CELL_WIDTH;
+    let height = mbox.height as f64 * CELL_HEIGHT;
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="monospace" font-size="{FONT_SIZE}">"#,
+        w = width,
+        h = height,
+    );
+    for y in 0..mbox.height {
+        for x in 0..mbox.width {
+            let grapheme = mbox.get_grapheme(x, y);
+            if matches!(grapheme, " " | "") {
+                continue;
+            }
+            let style = mbox.style(x, y);
+            let mut chars = grapheme.chars();
+            let strokes = match (chars.next(), chars.next()) {
+                (Some(ch), None) => glyph_strokes(ch),
+                _ => None,
+            };
+            match strokes {
+                Some(strokes) => {
+                    let color = color(style).unwrap_or_else(|| "currentColor".to_string());
+                    for (x1, y1, x2, y2) in strokes {
+                        let _ = write!(
+                            svg,
+                            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}"/>"#,
+                            (x as f64 + x1) * CELL_WIDTH,
+                            (y as f64 + 1.0 - y1) * CELL_HEIGHT,
+                            (x as f64 + x2) * CELL_WIDTH,
+                            (y as f64 + 1.0 - y2) * CELL_HEIGHT,
+                            color,
+                        );
+                    }
+                }
+                None => {
+                    let _ = write!(
+                        svg,
+                        r#"<text x="{}" y="{}"{}>{}</text>"#,
+                        x as f64 * CELL_WIDTH,
+                        (y as f64 + 0.75) * CELL_HEIGHT,
+                        text_attributes(style),
+                        escape(grapheme),
+                    );
+                }
+            }
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Fill, weight and slant for styled text
+fn text_attributes(style: Style) -> String {
+    let mut attributes = String::new();
+    if let Some(color) = color(style) {
+        let _ = write!(attributes, r#" fill="{}""#, color);
+    }
+    if style.add_modifier.contains(Modifier::BOLD) {
+        attributes.push_str(r#" font-weight="bold""#);
+    }
+    if style.add_modifier.contains(Modifier::ITALIC) {
+        attributes.push_str(r#" font-style="italic""#);
+    }
+    attributes
+}
+
+/// The SVG color for a style's foreground, if it has one SVG can name
+fn color(style: Style) -> Option<String> {
+    let name = match style.fg? {
+        Color::Rgb(r, g, b) => return Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        Color::Black => "black",
+        Color::Red => "red",
+        Color::Green => "green",
+        Color::Yellow => "yellow",
+        Color::Blue => "blue",
+        Color::Magenta => "magenta",
+        Color::Cyan => "cyan",
+        Color::Gray => "gray",
+        Color::White => "white",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_to_svg() {
+        let mut mbox = MathBox::from_text("a<b");
+        mbox.set_style(0, 0, Style::default().fg(Color::Red));
+        let svg = box_to_svg(&mbox);
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="30" height="20""#)
+        );
+        assert!(svg.contains(r#"<text x="0" y="15" fill="red">a</text>"#));
+        assert!(svg.contains(r#"<text x="10" y="15">&lt;</text>"#));
+
+        let svg = box_to_svg(&MathBox::from_text("─"));
+        assert!(svg.contains(r#"<line x1="5" y1="10" x2="0" y2="10" stroke="currentColor"/>"#));
+    }
+}
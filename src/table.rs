@@ -0,0 +1,76 @@
+//! Math in ratatui [`Table`](ratatui::widgets::Table) cells
+
+use crate::widget::box_to_lines;
+use crate::{MathBox, MathRenderer};
+use ratatui::{
+    style::Style,
+    text::{Line, Text},
+    widgets::{Cell, Row},
+};
+
+/// Render LaTeX for a table cell, falling back to the source text if it
+/// doesn't render so one bad formula doesn't break the table
+fn cell_box(latex: &str) -> MathBox {
+    MathRenderer::new()
+        .render_to_box(latex)
+        .map(|mbox| mbox.place_label(0))
+        .unwrap_or_else(|_| MathBox::from_text(latex))
+}
+
+/// A table cell showing LaTeX math. Tall formulas need a [`Row`] at least
+/// as high; [`math_row`] sets that up.
+pub fn math_cell(latex: &str) -> Cell<'static> {
+    Cell::from(Text::from(box_to_lines(&cell_box(latex), Style::default())))
+}
+
+/// A table row of LaTeX math cells, as high as its tallest formula, with
+/// every cell padded so the formulas share a baseline
+pub fn math_row<'a, I>(cells: I) -> Row<'static>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let boxes: Vec<MathBox> = cells.into_iter().map(cell_box).collect();
+    let ascent = boxes.iter().map(|b| b.baseline).max().unwrap_or(0);
+    let descent = boxes
+        .iter()
+        .map(|b| b.height - b.baseline)
+        .max()
+        .unwrap_or(1);
+    let cells = boxes.iter().map(|mbox| {
+        let padding = (mbox.baseline..ascent).map(|_| Line::default());
+        Cell::from(Text::from_iter(
+            padding.chain(box_to_lines(mbox, Style::default())),
+        ))
+    });
+    Row::new(cells.collect::<Vec<_>>()).height(u16::try_from(ascent + descent).unwrap_or(u16::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{
+        buffer::Buffer,
+        layout::{Constraint, Rect},
+        widgets::{Table, Widget},
+    };
+
+    #[test]
+    fn test_math_row_aligns_baselines() {
+        let rows = [math_row(["x^2", r"\frac{1}{2}", r"\bad{"])];
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(6),
+            ],
+        );
+        let area = Rect::new(0, 0, 14, 3);
+        let mut buf = Buffer::empty(area);
+        table.render(area, &mut buf);
+        assert_eq!(
+            buf,
+            Buffer::with_lines(["    1         ", "x²  ─   \\bad{ ", "    2         "])
+        );
+    }
+}
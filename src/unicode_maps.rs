@@ -362,6 +362,657 @@ pub fn get_greek(name: &str) -> Option<char> {
     GREEK_LETTERS.get(name).copied()
 }
 
+/// Starting code points of a mathvariant in the Mathematical Alphanumeric Symbols block
+struct AlphanumericRange {
+    variant: &'static str,
+    upper: u32,
+    lower: u32,
+    digits: Option<u32>,
+    greek_upper: Option<u32>,
+    greek_lower: Option<u32>,
+}
+
+const ALPHANUMERIC_RANGES: [AlphanumericRange; 13] = [
+    AlphanumericRange {
+        variant: "bold",
+        upper: 0x1D400,
+        lower: 0x1D41A,
+        digits: Some(0x1D7CE),
+        greek_upper: Some(0x1D6A8),
+        greek_lower: Some(0x1D6C2),
+    },
+    AlphanumericRange {
+        variant: "italic",
+        upper: 0x1D434,
+        lower: 0x1D44E,
+        digits: None,
+        greek_upper: Some(0x1D6E2),
+        greek_lower: Some(0x1D6FC),
+    },
+    AlphanumericRange {
+        variant: "bold-italic",
+        upper: 0x1D468,
+        lower: 0x1D482,
+        digits: Some(0x1D7CE),
+        greek_upper: Some(0x1D71C),
+        greek_lower: Some(0x1D736),
+    },
+    AlphanumericRange {
+        variant: "script",
+        upper: 0x1D49C,
+        lower: 0x1D4B6,
+        digits: None,
+        greek_upper: None,
+        greek_lower: None,
+    },
+    AlphanumericRange {
+        variant: "bold-script",
+        upper: 0x1D4D0,
+        lower: 0x1D4EA,
+        digits: Some(0x1D7CE),
+        greek_upper: None,
+        greek_lower: None,
+    },
+    AlphanumericRange {
+        variant: "fraktur",
+        upper: 0x1D504,
+        lower: 0x1D51E,
+        digits: None,
+        greek_upper: None,
+        greek_lower: None,
+    },
+    AlphanumericRange {
+        variant: "double-struck",
+        upper: 0x1D538,
+        lower: 0x1D552,
+        digits: Some(0x1D7D8),
+        greek_upper: None,
+        greek_lower: None,
+    },
+    AlphanumericRange {
+        variant: "bold-fraktur",
+        upper: 0x1D56C,
+        lower: 0x1D586,
+        digits: Some(0x1D7CE),
+        greek_upper: None,
+        greek_lower: None,
+    },
+    AlphanumericRange {
+        variant: "sans-serif",
+        upper: 0x1D5A0,
+        lower: 0x1D5BA,
+        digits: Some(0x1D7E2),
+        greek_upper: None,
+        greek_lower: None,
+    },
+    AlphanumericRange {
+        variant: "bold-sans-serif",
+        upper: 0x1D5D4,
+        lower: 0x1D5EE,
+        digits: Some(0x1D7EC),
+        greek_upper: Some(0x1D756),
+        greek_lower: Some(0x1D770),
+    },
+    AlphanumericRange {
+        variant: "sans-serif-italic",
+        upper: 0x1D608,
+        lower: 0x1D622,
+        digits: None,
+        greek_upper: None,
+        greek_lower: None,
+    },
+    AlphanumericRange {
+        variant: "sans-serif-bold-italic",
+        upper: 0x1D63C,
+        lower: 0x1D656,
+        digits: Some(0x1D7EC),
+        greek_upper: Some(0x1D790),
+        greek_lower: Some(0x1D7AA),
+    },
+    AlphanumericRange {
+        variant: "monospace",
+        upper: 0x1D670,
+        lower: 0x1D68A,
+        digits: Some(0x1D7F6),
+        greek_upper: None,
+        greek_lower: None,
+    },
+];
+
+/// Letters that live in the Letterlike Symbols block instead of the
+/// Mathematical Alphanumeric Symbols block (the "holes" in the ranges above)
+pub static VARIANT_EXCEPTIONS: Lazy<HashMap<(&'static str, char), char>> = Lazy::new(|| {
+    [
+        (("italic", 'h'), 'ℎ'),
+        (("script", 'B'), 'ℬ'),
+        (("script", 'E'), 'ℰ'),
+        (("script", 'F'), 'ℱ'),
+        (("script", 'H'), 'ℋ'),
+        (("script", 'I'), 'ℐ'),
+        (("script", 'L'), 'ℒ'),
+        (("script", 'M'), 'ℳ'),
+        (("script", 'R'), 'ℛ'),
+        (("script", 'e'), 'ℯ'),
+        (("script", 'g'), 'ℊ'),
+        (("script", 'o'), 'ℴ'),
+        (("fraktur", 'C'), 'ℭ'),
+        (("fraktur", 'H'), 'ℌ'),
+        (("fraktur", 'I'), 'ℑ'),
+        (("fraktur", 'R'), 'ℜ'),
+        (("fraktur", 'Z'), 'ℨ'),
+        (("double-struck", 'C'), 'ℂ'),
+        (("double-struck", 'H'), 'ℍ'),
+        (("double-struck", 'N'), 'ℕ'),
+        (("double-struck", 'P'), 'ℙ'),
+        (("double-struck", 'Q'), 'ℚ'),
+        (("double-struck", 'R'), 'ℝ'),
+        (("double-struck", 'Z'), 'ℤ'),
+    ]
+    .iter()
+    .copied()
+    .collect()
+});
+
+/// Map a character to its form in the given MathML mathvariant
+/// (e.g. "bold" → 𝐀, "double-struck" → ℝ, "monospace" → 𝚡).
+/// Returns None for "normal", unknown variants, and characters without a variant form.
+pub fn to_mathvariant(ch: char, variant: &str) -> Option<char> {
+    if let Some(&mapped) = VARIANT_EXCEPTIONS.get(&(variant, ch)) {
+        return Some(mapped);
+    }
+    let range = ALPHANUMERIC_RANGES.iter().find(|r| r.variant == variant)?;
+    let code = match ch {
+        'A'..='Z' => range.upper + (ch as u32 - 'A' as u32),
+        'a'..='z' => range.lower + (ch as u32 - 'a' as u32),
+        '0'..='9' => range.digits? + (ch as u32 - '0' as u32),
+        'Α'..='Ω' => range.greek_upper? + (ch as u32 - 'Α' as u32),
+        'α'..='ω' => range.greek_lower? + (ch as u32 - 'α' as u32),
+        _ => return None,
+    };
+    char::from_u32(code)
+}
+
+/// Map a character from a mathvariant back to its plain form (𝐀 → A, ℝ → R)
+pub fn from_mathvariant(ch: char) -> Option<char> {
+    if let Some(((_, plain), _)) = VARIANT_EXCEPTIONS.iter().find(|(_, &mapped)| mapped == ch) {
+        return Some(*plain);
+    }
+    let code = ch as u32;
+    let offset = |start: Option<u32>, len: u32| {
+        start
+            .filter(|s| (*s..s + len).contains(&code))
+            .map(|s| code - s)
+    };
+    ALPHANUMERIC_RANGES.iter().find_map(|range| {
+        let (first, offset) = if let Some(offset) = offset(Some(range.upper), 26) {
+            ('A', offset)
+        } else if let Some(offset) = offset(Some(range.lower), 26) {
+            ('a', offset)
+        } else if let Some(offset) = offset(range.digits, 10) {
+            ('0', offset)
+        } else if let Some(offset) = offset(range.greek_upper, 25) {
+            ('Α', offset)
+        } else {
+            ('α', offset(range.greek_lower, 25)?)
+        };
+        char::from_u32(first as u32 + offset)
+    })
+}
+
+/// ASCII spellings of symbols, for ASCII-only output
+pub static ASCII_SYMBOLS: Lazy<HashMap<char, &'static str>> = Lazy::new(|| {
+    [
+        ('±', "+-"),
+        ('∓', "-+"),
+        ('×', "x"),
+        ('÷', "/"),
+        ('·', "*"),
+        ('∗', "*"),
+        ('⋅', "*"),
+        ('∘', "o"),
+        ('−', "-"),
+        ('≤', "<="),
+        ('≥', ">="),
+        ('≠', "!="),
+        ('≡', "=="),
+        ('≈', "~="),
+        ('≅', "~="),
+        ('∼', "~"),
+        ('≃', "~="),
+        ('∝', "~"),
+        ('≪', "<<"),
+        ('≫', ">>"),
+        ('∈', "in"),
+        ('∉', "notin"),
+        ('∋', "ni"),
+        ('⊂', "subset"),
+        ('⊃', "supset"),
+        ('⊆', "subseteq"),
+        ('⊇', "supseteq"),
+        ('∪', "cup"),
+        ('∩', "cap"),
+        ('∖', "\\"),
+        ('→', "->"),
+        ('←', "<-"),
+        ('↔', "<->"),
+        ('⇒', "=>"),
+        ('⇐', "<="),
+        ('⇔', "<=>"),
+        ('⟹', "==>"),
+        ('⟺', "<==>"),
+        ('↦', "|->"),
+        ('↑', "^"),
+        ('↓', "v"),
+        ('∑', "sum"),
+        ('∏', "prod"),
+        ('∐', "coprod"),
+        ('∫', "int"),
+        ('∬', "iint"),
+        ('∭', "iiint"),
+        ('∮', "oint"),
+        ('⋃', "Union"),
+        ('⋂', "Intersection"),
+        ('∞', "inf"),
+        ('∂', "d"),
+        ('∇', "nabla"),
+        ('∀', "forall"),
+        ('∃', "exists"),
+        ('∅', "{}"),
+        ('¬', "~"),
+        ('∧', "/\\"),
+        ('∨', "\\/"),
+        ('√', "sqrt"),
+        ('…', "..."),
+        ('⋯', "..."),
+        ('′', "'"),
+        ('″', "''"),
+        ('‴', "'''"),
+        ('⟨', "<"),
+        ('⟩', ">"),
+        ('⌈', "["),
+        ('⌉', "]"),
+        ('⌊', "["),
+        ('⌋', "]"),
+        ('‖', "||"),
+        ('ℏ', "hbar"),
+        ('ℓ', "l"),
+        ('ℵ', "aleph"),
+        ('°', "deg"),
+        ('µ', "u"),
+    ]
+    .iter()
+    .copied()
+    .collect()
+});
+
+/// A single ASCII character standing in for a drawing character: rules,
+/// bracket and big operator pieces, arrow heads
+pub fn ascii_glyph(ch: char) -> Option<char> {
+    let glyph = match ch {
+        '─' | '‾' | '¯' | '═' => '-',
+        '│' | '║' | '‖' | '∥' | '⎜' | '⎟' | '⎢' | '⎥' | '⎪' | '⎮' | '∣' => {
+            '|'
+        }
+        '┌' | '┐' | '└' | '┘' | '┼' | '├' | '┤' | '┬' | '┴' => '+',
+        '╲' | '⎝' | '⎞' | '⎩' | '⎫' => '\\',
+        '╱' | '⎛' | '⎠' | '⎧' | '⎭' | '⌠' | '⌡' => '/',
+        '⎡' | '⎣' => '[',
+        '⎤' | '⎦' => ']',
+        '⎨' => '{',
+        '⎬' => '}',
+        '→' | '⇒' | '⟩' => '>',
+        '←' | '⇐' | '⟨' => '<',
+        '↑' => '^',
+        '↓' => 'v',
+        _ => return None,
+    };
+    Some(glyph)
+}
+
+/// Spell out text in ASCII: Greek letters by name, math alphabets as plain
+/// letters, symbols as ASCII operators. Combining marks are dropped and
+/// anything else becomes `?`.
+pub fn to_ascii(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        let ch = from_mathvariant(ch).unwrap_or(ch);
+        let greek = GREEK_LETTERS
+            .iter()
+            .filter(|(_, &letter)| letter == ch)
+            .map(|(name, _)| *name)
+            .min_by_key(|name| (name.len(), *name));
+        let script = SUPERSCRIPTS
+            .iter()
+            .chain(SUBSCRIPTS.iter())
+            .find(|(_, &s)| s == ch);
+        if ch.is_ascii() {
+            out.push(ch);
+        } else if let Some(name) = greek {
+            out.push_str(name);
+        } else if let Some(ascii) = ASCII_SYMBOLS.get(&ch) {
+            out.push_str(ascii);
+        } else if let Some((plain, _)) = script {
+            out.push(*plain);
+        } else if let Some(glyph) = ascii_glyph(ch) {
+            out.push(glyph);
+        } else if !matches!(ch, '\u{0300}'..='\u{036F}' | '\u{20D0}'..='\u{20FF}') {
+            out.push('?');
+        }
+    }
+    out
+}
+
+/// The LaTeX name of an accent drawn over a base (`^` → hat, `→` → vec),
+/// for writing accents out as `hat(x)`
+pub fn accent_name(accent: &str) -> Option<&'static str> {
+    let name = match accent {
+        "^" | "ˆ" => "hat",
+        "~" | "˜" => "tilde",
+        "¯" | "-" | "_" | "‾" => "bar",
+        "." | "˙" => "dot",
+        ".." | "¨" => "ddot",
+        "→" | "⃗" => "vec",
+        "ˇ" => "check",
+        "˘" => "breve",
+        "´" => "acute",
+        "`" => "grave",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Build an arrow stretched to `width` cells (e.g. `───→`), for extensible arrows.
+/// Returns None if `arrow` is not a stretchable arrow.
+pub fn stretch_arrow(arrow: &str, width: usize) -> Option<String> {
+    let (left, fill, right) = match arrow {
+        "→" => ("", '─', "→"),
+        "←" => ("←", '─', ""),
+        "↔" => ("←", '─', "→"),
+        "⇒" => ("", '═', "⇒"),
+        "⇐" => ("⇐", '═', ""),
+        "⇔" => ("⇐", '═', "⇒"),
+        "↦" => ("├", '─', "→"),
+        _ => return None,
+    };
+    let ends = left.chars().count() + right.chars().count();
+    let fill: String = std::iter::repeat_n(fill, width.max(ends + 1) - ends).collect();
+    Some(format!("{}{}{}", left, fill, right))
+}
+
+/// Build a vertical bar or arrow stretched to `height` rows (e.g. `↑ │ │ ↓`), for
+/// stretchy operators next to tall content.
+/// Returns None if `op` is not a vertically stretchable operator.
+pub fn stretch_vertical(op: &str, height: usize) -> Option<Vec<char>> {
+    let (top, fill, bottom) = match op {
+        "|" | "∣" => ('│', '│', '│'),
+        "‖" | "∥" => ('║', '║', '║'),
+        "↑" => ('↑', '│', '│'),
+        "↓" => ('│', '│', '↓'),
+        "↕" => ('↑', '│', '↓'),
+        "⇑" => ('⇑', '║', '║'),
+        "⇓" => ('║', '║', '⇓'),
+        "⇕" => ('⇑', '║', '⇓'),
+        _ => return None,
+    };
+    if height <= 1 {
+        return Some(vec![op.chars().next()?]);
+    }
+    let mut column = vec![top];
+    column.extend(std::iter::repeat_n(fill, height - 2));
+    column.push(bottom);
+    Some(column)
+}
+
+/// Split a leading run of primes off `text`, collapsing it into the fewest prime
+/// characters (`′′` → `″`, `′′′` → `‴`, `′′′′` → `⁗`).
+/// Returns the collapsed primes and the remaining text, or None if `text` doesn't
+/// start with a prime.
+pub fn collapse_primes(text: &str) -> Option<(String, &str)> {
+    let mut count = 0;
+    let mut end = 0;
+    for (i, c) in text.char_indices() {
+        count += match c {
+            '\'' | '′' => 1,
+            '″' => 2,
+            '‴' => 3,
+            '⁗' => 4,
+            _ => break,
+        };
+        end = i + c.len_utf8();
+    }
+    if count == 0 {
+        return None;
+    }
+
+    let mut primes: String = std::iter::repeat_n('⁗', count / 4).collect();
+    primes.push_str(["", "′", "″", "‴"][count % 4]);
+    Some((primes, &text[end..]))
+}
+
+/// Precomposed negated relations (`\not=` → ≠, `\not\in` → ∉)
+pub static NEGATED_RELATIONS: Lazy<HashMap<char, char>> = Lazy::new(|| {
+    [
+        ('=', '≠'),
+        ('<', '≮'),
+        ('>', '≯'),
+        ('≤', '≰'),
+        ('≥', '≱'),
+        ('≡', '≢'),
+        ('∼', '≁'),
+        ('≃', '≄'),
+        ('≈', '≉'),
+        ('≅', '≇'),
+        ('≺', '⊀'),
+        ('≻', '⊁'),
+        ('∈', '∉'),
+        ('∋', '∌'),
+        ('⊂', '⊄'),
+        ('⊃', '⊅'),
+        ('⊆', '⊈'),
+        ('⊇', '⊉'),
+        ('∣', '∤'),
+        ('∥', '∦'),
+        ('⊢', '⊬'),
+        ('⊨', '⊭'),
+        ('∃', '∄'),
+        ('←', '↚'),
+        ('→', '↛'),
+        ('↔', '↮'),
+        ('⇐', '⇍'),
+        ('⇒', '⇏'),
+        ('⇔', '⇎'),
+    ]
+    .into_iter()
+    .collect()
+});
+
+/// Negate a relation: the precomposed form if there is one, otherwise the
+/// relation followed by U+0338 COMBINING LONG SOLIDUS OVERLAY
+pub fn negate_relation(op: &str) -> String {
+    let mut chars = op.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+        if let Some(&negated) = NEGATED_RELATIONS.get(&ch) {
+            return negated.to_string();
+        }
+    }
+    format!("{}\u{0338}", op)
+}
+
+/// Plain-language meanings of common symbols, for tooltips
+pub static SYMBOL_DESCRIPTIONS: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
+    [
+        ("∂", "partial derivative"),
+        ("∇", "nabla (gradient)"),
+        ("∑", "summation"),
+        ("∏", "product"),
+        ("∐", "coproduct"),
+        ("∫", "integral"),
+        ("∬", "double integral"),
+        ("∭", "triple integral"),
+        ("∮", "contour integral"),
+        ("√", "square root"),
+        ("∞", "infinity"),
+        ("±", "plus or minus"),
+        ("∓", "minus or plus"),
+        ("×", "times"),
+        ("÷", "divided by"),
+        ("·", "dot product"),
+        ("∘", "composition"),
+        ("⊗", "tensor product"),
+        ("⊕", "direct sum"),
+        ("=", "equals"),
+        ("≠", "not equal to"),
+        ("<", "less than"),
+        (">", "greater than"),
+        ("≤", "less than or equal to"),
+        ("≥", "greater than or equal to"),
+        ("≈", "approximately equal to"),
+        ("≡", "identical to"),
+        ("≅", "congruent to"),
+        ("∼", "similar to"),
+        ("∝", "proportional to"),
+        ("∈", "element of"),
+        ("∉", "not an element of"),
+        ("⊂", "proper subset of"),
+        ("⊆", "subset of"),
+        ("⊃", "proper superset of"),
+        ("⊇", "superset of"),
+        ("∪", "union"),
+        ("∩", "intersection"),
+        ("∖", "set difference"),
+        ("∅", "empty set"),
+        ("∀", "for all"),
+        ("∃", "there exists"),
+        ("∄", "there is no"),
+        ("¬", "not"),
+        ("∧", "and"),
+        ("∨", "or"),
+        ("→", "tends to / maps to"),
+        ("↦", "maps to"),
+        ("⇒", "implies"),
+        ("⇔", "if and only if"),
+        ("∴", "therefore"),
+        ("∵", "because"),
+        ("ℝ", "real numbers"),
+        ("ℕ", "natural numbers"),
+        ("ℤ", "integers"),
+        ("ℚ", "rational numbers"),
+        ("ℂ", "complex numbers"),
+        ("ℏ", "reduced Planck constant"),
+        ("ℓ", "script l"),
+        ("†", "adjoint"),
+        ("⟨", "left angle bracket"),
+        ("⟩", "right angle bracket"),
+        ("‖", "norm"),
+    ]
+    .into_iter()
+    .collect()
+});
+
+/// What a rendered symbol means: a plain-language description if there is
+/// one, otherwise the name of the Greek letter or the LaTeX command it
+/// comes from (`\otimes`). `None` for letters, digits and unknown symbols.
+pub fn describe_symbol(symbol: &str) -> Option<String> {
+    if let Some(description) = SYMBOL_DESCRIPTIONS.get(symbol) {
+        return Some(description.to_string());
+    }
+    let shortest =
+        |names: Vec<&'static str>| names.into_iter().min_by_key(|name| (name.len(), *name));
+    let mut chars = symbol.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+        let greek = GREEK_LETTERS
+            .iter()
+            .filter(|(_, &c)| c == ch)
+            .map(|(&name, _)| name)
+            .collect();
+        if let Some(name) = shortest(greek) {
+            return Some(format!("Greek letter {}", name));
+        }
+    }
+    let commands = MATH_SYMBOLS
+        .iter()
+        .filter(|(_, &s)| s == symbol)
+        .map(|(&name, _)| name)
+        .collect();
+    shortest(commands).map(|name| format!("\\{}", name))
+}
+
+/// Spacing class of an operator, following TeX's atom types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperatorClass {
+    Ordinary,
+    /// Big operators (∑, ∫, ...)
+    Large,
+    Binary,
+    Relation,
+    Open,
+    Close,
+    Punctuation,
+}
+
+/// A space between atoms in TeX's spacing table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtomSpace {
+    None,
+    Thin,
+    Medium,
+    Thick,
+}
+
+/// The space TeX puts between an atom of class `left` and one of class
+/// `right` (The TeXbook, chapter 18). Pairs TeX never produces, such as a
+/// binary operator after a relation, get none: the binary operator is
+/// unary there and counts as ordinary.
+pub fn atom_space(left: OperatorClass, right: OperatorClass) -> AtomSpace {
+    use OperatorClass::*;
+    match (left, right) {
+        (Ordinary | Large | Close, Large) | (Large, Ordinary) => AtomSpace::Thin,
+        (Ordinary | Close, Binary) | (Binary, Ordinary | Large | Open) => AtomSpace::Medium,
+        (Ordinary | Large | Close, Relation) | (Relation, Ordinary | Large | Open) => {
+            AtomSpace::Thick
+        }
+        (Punctuation, Ordinary | Large | Relation | Open | Close | Punctuation) => AtomSpace::Thin,
+        _ => AtomSpace::None,
+    }
+}
+
+/// Look up an operator's spacing class in the operator dictionary
+pub fn operator_class(op: &str) -> OperatorClass {
+    let mut chars = op.chars();
+    let (Some(ch), None) = (chars.next(), chars.next()) else {
+        // Relations negated with a combining overlay (`\not\prec` → ⊀)
+        return if op.ends_with('\u{0338}') {
+            OperatorClass::Relation
+        } else {
+            OperatorClass::Ordinary
+        };
+    };
+    let negated = NEGATED_RELATIONS
+        .iter()
+        .any(|(&rel, &neg)| ch == rel || ch == neg);
+    match ch {
+        '∑' | '∏' | '∐' | '∫' | '∬' | '∭' | '∮' | '⋃' | '⋂' | '⋁' | '⋀' | '⨁' | '⨂' => {
+            OperatorClass::Large
+        }
+        '+' | '-' | '−' | '±' | '∓' | '×' | '÷' | '·' | '⋅' | '∗' | '*' | '∘' | '∙' | '∪' | '∩'
+        | '∧' | '∨' | '⊕' | '⊖' | '⊗' | '⊘' | '⊙' | '∖' | '⊓' | '⊔' | '⋄' | '⋆' | '⊎' | '≀' => {
+            OperatorClass::Binary
+        }
+        '∃' | '∄' => OperatorClass::Ordinary,
+        '<' | '>' | '≦' | '≧' | '≪' | '≫' | '∝' | '⪯' | '⪰' | '⊊' | '⊋' | '⊥' | '≔' | '≐' | '≜'
+        | '⟶' | '⟵' | '⟹' | '⟸' | '⟺' | '↦' | '⟼' | '⇌' | '⇀' | '↪' | '↩' | ':' => {
+            OperatorClass::Relation
+        }
+        _ if negated => OperatorClass::Relation,
+        '(' | '[' | '{' | '⟨' | '〈' | '⌈' | '⌊' => OperatorClass::Open,
+        ')' | ']' | '}' | '⟩' | '〉' | '⌉' | '⌋' | '!' | '′' | '″' | '‴' => {
+            OperatorClass::Close
+        }
+        ',' | ';' => OperatorClass::Punctuation,
+        _ => OperatorClass::Ordinary,
+    }
+}
+
 /// Bracket scaling characters
 pub static BRACKETS: Lazy<BracketChars> = Lazy::new(|| BracketChars {
     left_paren: ['⎛', '⎜', '⎝', '('],
@@ -372,6 +1023,25 @@ pub static BRACKETS: Lazy<BracketChars> = Lazy::new(|| BracketChars {
     right_brace: ['⎫', '⎬', '⎭', '}'],
     left_vert: ['│', '│', '│', '|'],
     right_vert: ['│', '│', '│', '|'],
+    double_vert: ['║', '║', '║', '‖'],
+    left_angle: ['╱', '⟨', '╲'],
+    right_angle: ['╲', '⟩', '╱'],
+});
+
+/// Bracket scaling characters in plain ASCII, for
+/// [`MathRenderer::ascii_only`](crate::MathRenderer::ascii_only)
+pub static ASCII_BRACKETS: Lazy<BracketChars> = Lazy::new(|| BracketChars {
+    left_paren: ['/', '|', '\\', '('],
+    right_paren: ['\\', '|', '/', ')'],
+    left_bracket: ['[', '[', '[', '['],
+    right_bracket: [']', ']', ']', ']'],
+    left_brace: ['/', '{', '\\', '{'],
+    right_brace: ['\\', '}', '/', '}'],
+    left_vert: ['|', '|', '|', '|'],
+    right_vert: ['|', '|', '|', '|'],
+    double_vert: ['|', '|', '|', '|'],
+    left_angle: ['/', '<', '\\'],
+    right_angle: ['\\', '>', '/'],
 });
 
 pub struct BracketChars {
@@ -383,6 +1053,9 @@ pub struct BracketChars {
     pub right_brace: [char; 4],
     pub left_vert: [char; 4],
     pub right_vert: [char; 4],
+    pub double_vert: [char; 4],
+    pub left_angle: [char; 3], // top, middle, bottom
+    pub right_angle: [char; 3],
 }
 
 impl BracketChars {
@@ -392,6 +1065,8 @@ impl BracketChars {
             "[" | "\\left[" => &self.left_bracket,
             "{" | "\\left{" | "\\left\\{" | "\\lbrace" => &self.left_brace,
             "|" | "\\left|" | "\\lvert" => &self.left_vert,
+            "‖" | "∥" | "\\lVert" => &self.double_vert,
+            "⟨" | "〈" => return self.scale_angle(height, self.left_angle),
             _ => &self.left_paren,
         };
         self.scale_bracket(chars, height)
@@ -403,11 +1078,25 @@ impl BracketChars {
             "]" | "\\right]" => &self.right_bracket,
             "}" | "\\right}" | "\\right\\}" | "\\rbrace" => &self.right_brace,
             "|" | "\\right|" | "\\rvert" => &self.right_vert,
+            "‖" | "∥" | "\\rVert" => &self.double_vert,
+            "⟩" | "〉" => return self.scale_angle(height, self.right_angle),
             _ => &self.right_paren,
         };
         self.scale_bracket(chars, height)
     }
 
+    /// Angle brackets are drawn as two diagonals meeting at the middle row
+    /// (`chars` is top, middle, bottom)
+    fn scale_angle(&self, height: usize, chars: [char; 3]) -> Vec<char> {
+        (0..height.max(1))
+            .map(|y| match (2 * y + 1).cmp(&height) {
+                std::cmp::Ordering::Less => chars[0],
+                std::cmp::Ordering::Equal => chars[1],
+                std::cmp::Ordering::Greater => chars[2],
+            })
+            .collect()
+    }
+
     fn scale_bracket(&self, chars: &[char; 4], height: usize) -> Vec<char> {
         if height <= 1 {
             vec![chars[3]]
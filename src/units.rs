@@ -0,0 +1,231 @@
+//! Physical quantities: a subset of siunitx's `\SI`, `\si` and `\num`
+//!
+//! Numbers are written with `×10ⁿ` exponents and `±` uncertainties, units are
+//! set upright with a thin space between them. Units can be given literally
+//! (`kg.m.s^{-2}`, `m/s`) or with unit macros (`\kilo\gram\per\second`).
+
+/// Prefix macros and their symbols
+const PREFIXES: &[(&str, &str)] = &[
+    ("yocto", "y"),
+    ("zepto", "z"),
+    ("atto", "a"),
+    ("femto", "f"),
+    ("pico", "p"),
+    ("nano", "n"),
+    ("micro", "µ"),
+    ("milli", "m"),
+    ("centi", "c"),
+    ("deci", "d"),
+    ("deca", "da"),
+    ("hecto", "h"),
+    ("kilo", "k"),
+    ("mega", "M"),
+    ("giga", "G"),
+    ("tera", "T"),
+    ("peta", "P"),
+    ("exa", "E"),
+    ("zetta", "Z"),
+    ("yotta", "Y"),
+];
+
+/// Unit macros and their symbols
+const UNITS: &[(&str, &str)] = &[
+    ("meter", "m"),
+    ("metre", "m"),
+    ("second", "s"),
+    ("gram", "g"),
+    ("kilogram", "kg"),
+    ("ampere", "A"),
+    ("kelvin", "K"),
+    ("mole", "mol"),
+    ("candela", "cd"),
+    ("hertz", "Hz"),
+    ("newton", "N"),
+    ("pascal", "Pa"),
+    ("joule", "J"),
+    ("watt", "W"),
+    ("coulomb", "C"),
+    ("volt", "V"),
+    ("farad", "F"),
+    ("ohm", "Ω"),
+    ("siemens", "S"),
+    ("weber", "Wb"),
+    ("tesla", "T"),
+    ("henry", "H"),
+    ("lumen", "lm"),
+    ("lux", "lx"),
+    ("becquerel", "Bq"),
+    ("gray", "Gy"),
+    ("sievert", "Sv"),
+    ("liter", "L"),
+    ("litre", "L"),
+    ("minute", "min"),
+    ("hour", "h"),
+    ("day", "d"),
+    ("electronvolt", "eV"),
+    ("bar", "bar"),
+    ("percent", "%"),
+    ("degree", "°"),
+    ("celsius", "°C"),
+    ("degreeCelsius", "°C"),
+    ("radian", "rad"),
+    ("steradian", "sr"),
+    ("byte", "B"),
+    ("bit", "bit"),
+];
+
+/// Format a number: `3.0e8` → `3.0 \times 10^{8}`, `1.2+-0.1` → `1.2 \pm 0.1`
+pub(crate) fn number_to_latex(number: &str) -> String {
+    let number: String = number.chars().filter(|c| !c.is_whitespace()).collect();
+    let (value, uncertainty) = match number.split_once("+-") {
+        Some((value, uncertainty)) => (value, Some(uncertainty)),
+        None => (number.as_str(), None),
+    };
+
+    let mantissa = |part: &str| -> String {
+        // An `e` with no digits after it (`1e`) isn't an exponent
+        let is_exponent = |exponent: &str| {
+            exponent
+                .trim_start_matches(['+', '-'])
+                .starts_with(|c: char| c.is_ascii_digit())
+        };
+        match part
+            .split_once(['e', 'E'])
+            .filter(|(_, exponent)| is_exponent(exponent))
+        {
+            Some(("", exponent)) => format!("10^{{{}}}", exponent.trim_start_matches('+')),
+            Some((mantissa, exponent)) => {
+                format!(
+                    r"{} \times 10^{{{}}}",
+                    mantissa,
+                    exponent.trim_start_matches('+')
+                )
+            }
+            None => part.to_string(),
+        }
+    };
+
+    match uncertainty {
+        Some(uncertainty) => format!(r"{} \pm {}", mantissa(value), mantissa(uncertainty)),
+        None => mantissa(value),
+    }
+}
+
+/// Format a unit, either literal (`kg.m/s^2`) or built from unit macros
+/// (`\kilo\gram\per\second\squared`)
+pub(crate) fn unit_to_latex(unit: &str) -> String {
+    if unit.contains('\\') {
+        return macro_unit_to_latex(unit);
+    }
+
+    let mut out = String::new();
+    let mut chars = unit.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' | '~' => out.push_str(r"\,"),
+            '/' => out.push('/'),
+            '^' => {
+                // Exponents may be braced or a bare signed integer (s^-2)
+                let exponent: String = if chars.peek() == Some(&'{') {
+                    chars.next();
+                    chars.by_ref().take_while(|&c| c != '}').collect()
+                } else {
+                    let mut exponent = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_ascii_digit() || (exponent.is_empty() && c == '-')) {
+                            break;
+                        }
+                        exponent.push(c);
+                        chars.next();
+                    }
+                    exponent
+                };
+                out.push_str(&format!("^{{{}}}", exponent));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut symbol = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if matches!(c, '.' | '~' | '/' | '^') || c.is_whitespace() {
+                        break;
+                    }
+                    symbol.push(c);
+                    chars.next();
+                }
+                out.push_str(&format!(r"\mathrm{{{}}}", symbol));
+            }
+        }
+    }
+    out
+}
+
+/// Format a unit written with siunitx macros; `\per` gives the next unit a
+/// negative power and `\squared`/`\cubed` raise the previous one
+fn macro_unit_to_latex(unit: &str) -> String {
+    let mut units: Vec<(String, i32)> = Vec::new();
+    let mut prefix = "";
+    let mut per = false;
+
+    for name in unit
+        .split('\\')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if let Some((_, symbol)) = PREFIXES.iter().find(|(macro_name, _)| *macro_name == name) {
+            prefix = symbol;
+            continue;
+        }
+        match name {
+            "per" => per = true,
+            "squared" | "cubed" => {
+                if let Some((_, power)) = units.last_mut() {
+                    *power *= if name == "squared" { 2 } else { 3 };
+                }
+            }
+            _ => {
+                let symbol = UNITS
+                    .iter()
+                    .find(|(macro_name, _)| *macro_name == name)
+                    .map_or(name, |(_, symbol)| symbol);
+                units.push((format!("{}{}", prefix, symbol), if per { -1 } else { 1 }));
+                prefix = "";
+                per = false;
+            }
+        }
+    }
+
+    units
+        .iter()
+        .map(|(symbol, power)| match power {
+            1 => format!(r"\mathrm{{{}}}", symbol),
+            _ => format!(r"\mathrm{{{}}}^{{{}}}", symbol, power),
+        })
+        .collect::<Vec<_>>()
+        .join(r"\,")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_to_latex() {
+        assert_eq!(number_to_latex("3.0e8"), r"3.0 \times 10^{8}");
+        assert_eq!(number_to_latex("1.2 +- 0.1"), r"1.2 \pm 0.1");
+        assert_eq!(number_to_latex("1e-3"), r"1 \times 10^{-3}");
+        assert_eq!(number_to_latex("1e"), "1e");
+        assert_eq!(number_to_latex("2E+"), "2E+");
+    }
+
+    #[test]
+    fn test_unit_to_latex() {
+        assert_eq!(
+            unit_to_latex("kg.m/s^2"),
+            r"\mathrm{kg}\,\mathrm{m}/\mathrm{s}^{2}"
+        );
+        assert_eq!(
+            unit_to_latex(r"\kilo\gram\per\second\squared"),
+            r"\mathrm{kg}\,\mathrm{s}^{-2}"
+        );
+    }
+}
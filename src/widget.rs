@@ -1,33 +1,180 @@
 //! Ratatui widget for rendering math expressions
 
-use crate::{MathRenderer, RenderError};
+use crate::latex;
+use crate::raster::scale_box;
+use crate::renderer::node_at;
+use crate::unicode_maps::describe_symbol;
+use crate::{AccentStrategy, MathBox, MathRenderer, MathTheme, RenderError, RendererOptions};
 use ratatui::{
     buffer::Buffer,
-    layout::Rect,
+    layout::{Margin, Rect},
     style::{Color, Style},
     text::{Line, Span},
-    widgets::{Block, Paragraph, Widget, Wrap},
+    widgets::{
+        Block, Clear, Padding, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState,
+        StatefulWidget, Widget, Wrap,
+    },
 };
+use roxmltree::Document;
+use std::cell::{OnceCell, Ref, RefCell};
+use std::collections::HashMap;
+use std::ops::Range;
+use unicode_width::UnicodeWidthStr;
+
+/// What a widget does with an equation wider than its area
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Cut the equation off at the edge of the area
+    #[default]
+    Clip,
+    /// Cut at the last token boundary that fits and append `…`
+    Ellipsis,
+}
+
+/// What a widget does with the cells of its area that the equation leaves
+/// blank, e.g. when drawing math over existing content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Background {
+    /// Write the equation's rows, spaces included, in the widget's style and
+    /// leave the rest of the area as it was
+    #[default]
+    Rows,
+    /// Clear the whole area and fill it with the widget's style, so its
+    /// background color covers everything underneath
+    Fill,
+    /// Write only the equation's visible characters (and the block), leaving
+    /// every blank cell as it was
+    Transparent,
+}
+
+impl Background {
+    /// Run `draw` over `area` of `buf` with this background, filling with
+    /// `style` for [`Background::Fill`]
+    pub(crate) fn apply(
+        self,
+        area: Rect,
+        buf: &mut Buffer,
+        style: Style,
+        draw: impl FnOnce(&mut Buffer),
+    ) {
+        match self {
+            Background::Rows => draw(buf),
+            Background::Fill => {
+                Clear.render(area, buf);
+                buf.set_style(area, style);
+                draw(buf);
+            }
+            Background::Transparent => {
+                let area = area.intersection(buf.area);
+                let mut scratch = Buffer::empty(area);
+                draw(&mut scratch);
+                for position in area.positions() {
+                    let cell = &scratch[position];
+                    if !matches!(cell.symbol(), " " | "") {
+                        buf[position] = cell.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How a widget shows LaTeX that fails to render
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorDisplay {
+    /// `Error: ...` on a single line
+    #[default]
+    Message,
+    /// The LaTeX source with a `^~~~` caret under the offending token (when
+    /// it can be located), then the message
+    Caret,
+}
+
+/// What a widget draws: LaTeX or MathML to render, or an already rendered box
+#[derive(Clone)]
+pub(crate) enum Source<'a> {
+    Latex(&'a str),
+    MathMl(&'a str),
+    Box(MathBox),
+}
+
+impl Source<'_> {
+    /// Render the source with `renderer`; a pre-rendered box is used as is
+    pub(crate) fn to_box(&self, renderer: &MathRenderer) -> Result<MathBox, RenderError> {
+        match self {
+            Source::Latex(latex) => renderer.render_to_box(latex),
+            Source::MathMl(mathml) => renderer.render_mathml_to_box(mathml),
+            Source::Box(mbox) => Ok(mbox.clone()),
+        }
+    }
+
+    /// The LaTeX source, or "" for MathML or a pre-rendered box
+    pub(crate) fn latex(&self) -> &str {
+        match self {
+            Source::Latex(latex) => latex,
+            Source::MathMl(_) | Source::Box(_) => "",
+        }
+    }
+}
 
 /// A ratatui widget for rendering LaTeX math expressions
 #[derive(Clone)]
 pub struct MathWidget<'a> {
-    latex: &'a str,
+    source: Source<'a>,
     style: Style,
     block: Option<Block<'a>>,
-    use_unicode_scripts: bool,
+    padding: Padding,
+    background: Background,
+    options: RendererOptions,
+    use_unicode_bold: bool,
     wrap: bool,
+    operator_wrap: Option<u16>,
+    overflow: Overflow,
+    scale: u16,
+    theme: MathTheme,
+    highlights: Vec<(Vec<usize>, Style)>,
+    error_display: ErrorDisplay,
+    error_style: Style,
 }
 
 impl<'a> MathWidget<'a> {
     /// Create a new MathWidget from a LaTeX expression
     pub fn new(latex: &'a str) -> Self {
+        Self::with_source(Source::Latex(latex))
+    }
+
+    /// Create a MathWidget from a MathML document (`<math>...</math>`), for
+    /// notebook and EPUB viewers that already have MathML, without going
+    /// through LaTeX
+    pub fn from_mathml(mathml: &'a str) -> Self {
+        Self::with_source(Source::MathMl(mathml))
+    }
+
+    /// Create a MathWidget showing a box already rendered (e.g. with
+    /// [`MathRenderer::render_to_box`] on a worker thread), so rendering the
+    /// widget doesn't parse the LaTeX again. Options that affect rendering
+    /// (scripts, bold, theme, highlights) were fixed when the box was made.
+    pub fn from_box(mathbox: MathBox) -> Self {
+        Self::with_source(Source::Box(mathbox))
+    }
+
+    fn with_source(source: Source<'a>) -> Self {
         Self {
-            latex,
+            source,
             style: Style::default(),
             block: None,
-            use_unicode_scripts: true,
+            padding: Padding::ZERO,
+            background: Background::Rows,
+            options: RendererOptions::default(),
+            use_unicode_bold: true,
             wrap: false,
+            operator_wrap: None,
+            overflow: Overflow::Clip,
+            scale: 1,
+            theme: MathTheme::default(),
+            highlights: Vec::new(),
+            error_display: ErrorDisplay::Message,
+            error_style: Style::default(),
         }
     }
 
@@ -55,9 +202,44 @@ impl<'a> MathWidget<'a> {
         self
     }
 
+    /// Leave space between the equation and the edges of the area (inside
+    /// the block's borders, if any)
+    pub fn padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Set what happens to the cells the equation leaves blank (see
+    /// [`Background`]), e.g. `Background::Transparent` for overlays
+    pub fn background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
     /// Enable or disable Unicode superscript/subscript characters
     pub fn use_unicode_scripts(mut self, use_unicode: bool) -> Self {
-        self.use_unicode_scripts = use_unicode;
+        self.options.unicode_scripts = use_unicode;
+        self
+    }
+
+    /// Set the layout options (display or text style, spacing, brackets,
+    /// accents, ...), replacing any set with
+    /// [`use_unicode_scripts`](Self::use_unicode_scripts)
+    pub fn options(mut self, options: RendererOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set how accents are drawn (see [`AccentStrategy`]), e.g.
+    /// `AccentStrategy::Ascii` for terminals that garble combining marks
+    pub fn accents(mut self, strategy: AccentStrategy) -> Self {
+        self.options.accents = strategy;
+        self
+    }
+
+    /// Use Unicode bold characters for bold math, or a bold style modifier if disabled
+    pub fn use_unicode_bold(mut self, use_unicode: bool) -> Self {
+        self.use_unicode_bold = use_unicode;
         self
     }
 
@@ -67,68 +249,617 @@ impl<'a> MathWidget<'a> {
         self
     }
 
+    /// Break equations wider than the area at `+`, `−`, `=` and relation
+    /// operators instead of clipping them, indenting continuation lines by
+    /// `indent` columns. Unlike [`wrap`](Self::wrap) this never splits a token.
+    pub fn wrap_at_operators(mut self, indent: u16) -> Self {
+        self.operator_wrap = Some(indent);
+        self
+    }
+
+    /// Color numbers, identifiers, operators, Greek letters, delimiters and
+    /// function names, e.g. to match the host application's palette
+    pub fn theme(mut self, theme: MathTheme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Highlight the subexpression at `path` in the MathML tree (see
+    /// [`MathRenderer::highlight`]), e.g. the step a tutorial is on
+    pub fn highlight(mut self, path: &[usize], style: Style) -> Self {
+        self.highlights.push((path.to_vec(), style));
+        self
+    }
+
+    /// Set how rendering errors are shown
+    pub fn error_display(mut self, display: ErrorDisplay) -> Self {
+        self.error_display = display;
+        self
+    }
+
+    /// Set the style for the error message and caret, patched over the
+    /// widget's style
+    pub fn error_style(mut self, style: Style) -> Self {
+        self.error_style = style;
+        self
+    }
+
+    /// Set how an equation wider than the area is cut (clipped by default)
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Draw the equation `scale` times larger in each direction with
+    /// quadrant-block characters, for headline equations. At 2× text is only
+    /// roughly legible; 3× and up read well. 1 (the default) renders normally.
+    pub fn scale(mut self, scale: u16) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
     /// Render the LaTeX to a string (useful for debugging)
     pub fn render_to_string(&self) -> Result<String, RenderError> {
-        let renderer = MathRenderer::new().use_unicode_scripts(self.use_unicode_scripts);
-        renderer.render_latex(self.latex)
+        Ok(self.source.to_box(&self.renderer())?.to_string())
+    }
+
+    /// Width and height (columns, rows) the widget needs to show the whole
+    /// equation, including any `\tag` label, the scale factor, the padding
+    /// and the block's borders and padding. Use it to build layout constraints.
+    pub fn required_size(&self) -> Result<(u16, u16), RenderError> {
+        let mbox = self.source.to_box(&self.renderer())?.place_label(0);
+        let scale = usize::from(self.scale);
+        let outer = Rect::new(0, 0, 100, 100);
+        let inner = pad(
+            self.block
+                .as_ref()
+                .map_or(outer, |block| block.inner(outer)),
+            self.padding,
+        );
+        let (extra_width, extra_height) = (
+            usize::from(outer.width - inner.width),
+            usize::from(outer.height - inner.height),
+        );
+        let size =
+            |cells: usize, extra: usize| u16::try_from(cells * scale + extra).unwrap_or(u16::MAX);
+        Ok((
+            size(mbox.width, extra_width),
+            size(mbox.height, extra_height),
+        ))
     }
+
+    /// Row of the equation's baseline counted from the top of the widget's
+    /// area (block border and padding included), so a label rendered beside
+    /// the widget can sit on the same line. Like [`required_size`](Self::required_size)
+    /// it assumes the equation isn't wrapped.
+    pub fn baseline(&self) -> Result<u16, RenderError> {
+        let mbox = self.source.to_box(&self.renderer())?.place_label(0);
+        let outer = Rect::new(0, 0, 100, 100);
+        let inner = pad(
+            self.block
+                .as_ref()
+                .map_or(outer, |block| block.inner(outer)),
+            self.padding,
+        );
+        let scale = usize::from(self.scale);
+        let row = mbox.baseline * scale + scale / 2 + usize::from(inner.y);
+        Ok(u16::try_from(row).unwrap_or(u16::MAX))
+    }
+
+    fn renderer(&self) -> MathRenderer {
+        let renderer = MathRenderer::with_options(self.options.clone())
+            .use_unicode_bold(self.use_unicode_bold)
+            .token_styles(self.theme.token_styles());
+        self.highlights
+            .iter()
+            .fold(renderer, |renderer, (path, style)| {
+                renderer.highlight(path, *style)
+            })
+    }
+}
+
+/// Convert a MathBox into lines of spans, patching each cell's style onto `base`.
+/// Trailing blank cells are dropped, matching `MathBox::to_string`.
+pub(crate) fn box_to_lines(mbox: &MathBox, base: Style) -> Vec<Line<'static>> {
+    (0..mbox.height)
+        .map(|y| {
+            let end = (0..mbox.width)
+                .rev()
+                .find(|&x| !matches!(mbox.get_grapheme(x, y), " " | ""))
+                .map_or(0, |x| x + 1);
+
+            let mut spans: Vec<Span<'static>> = Vec::new();
+            let mut current = String::new();
+            let mut current_style = base;
+            for x in 0..end {
+                let style = base.patch(mbox.style(x, y));
+                if style != current_style && !current.is_empty() {
+                    spans.push(Span::styled(std::mem::take(&mut current), current_style));
+                }
+                current_style = style;
+                current.push_str(mbox.get_grapheme(x, y));
+            }
+            if !current.is_empty() {
+                spans.push(Span::styled(current, current_style));
+            }
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Paint a MathBox into `area` of `buf`, clipped to the area, patching each
+/// cell's style onto `base`. Blank cells are left untouched so the math can be
+/// drawn over existing content.
+pub(crate) fn paint_box(mbox: &MathBox, area: Rect, buf: &mut Buffer, base: Style) {
+    let area = area.intersection(buf.area);
+    for y in 0..mbox.height.min(area.height as usize) {
+        for x in 0..mbox.width.min(area.width as usize) {
+            let grapheme = mbox.get_grapheme(x, y);
+            if matches!(grapheme, " " | "") {
+                continue;
+            }
+            let position = (area.x + x as u16, area.y + y as u16);
+            buf[position]
+                .set_symbol(grapheme)
+                .set_style(base.patch(mbox.style(x, y)));
+        }
+    }
+}
+
+/// Lines showing a rendering error: the source in `style` (for
+/// [`ErrorDisplay::Caret`]) and the caret and message in `error_style`
+fn error_lines(
+    latex_source: &str,
+    error: &RenderError,
+    display: ErrorDisplay,
+    style: Style,
+    error_style: Style,
+) -> Vec<Line<'static>> {
+    let message = Line::from(Span::styled(format!("Error: {}", error), error_style));
+    if display == ErrorDisplay::Message {
+        return vec![message];
+    }
+
+    let span = latex::error_span(latex_source, &error.to_string());
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for source_line in latex_source.split('\n') {
+        lines.push(Line::from(Span::styled(source_line.to_string(), style)));
+        let end = offset + source_line.len();
+        if let Some((start, len)) = span.filter(|(start, _)| (offset..=end).contains(start)) {
+            let column = source_line[..start - offset].width();
+            let width = latex_source[start..start + len].width().max(1);
+            let caret = format!("{}^{}", " ".repeat(column), "~".repeat(width - 1));
+            lines.push(Line::from(Span::styled(caret, error_style)));
+        }
+        offset = end + 1;
+    }
+    lines.push(message);
+    lines
+}
+
+/// `area` less `padding` on each side
+pub(crate) fn pad(area: Rect, padding: Padding) -> Rect {
+    let horizontal = padding.left.saturating_add(padding.right).min(area.width);
+    let vertical = padding.top.saturating_add(padding.bottom).min(area.height);
+    Rect::new(
+        area.x + padding.left.min(area.width),
+        area.y + padding.top.min(area.height),
+        area.width - horizontal,
+        area.height - vertical,
+    )
+}
+
+/// Width available for right-aligning an equation label (`\tag`) inside `area`
+fn label_width(area: Rect, block: Option<&Block>) -> usize {
+    block.map_or(area, |b| b.inner(area)).width as usize
 }
 
 impl Widget for MathWidget<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let renderer = MathRenderer::new().use_unicode_scripts(self.use_unicode_scripts);
+        self.draw(area, buf);
+    }
+}
 
-        let rendered = match renderer.render_latex(self.latex) {
-            Ok(s) => s,
-            Err(e) => format!("Error: {}", e),
-        };
+/// Rendering by reference lets a widget kept in app state be drawn every frame
+/// without cloning or rebuilding it. With the `widget-ref` feature this comes
+/// from ratatui's blanket impl over `WidgetRef` instead.
+#[cfg(not(feature = "widget-ref"))]
+impl Widget for &MathWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        self.draw(area, buf);
+    }
+}
 
-        let lines: Vec<Line> = rendered
-            .lines()
-            .map(|line| Line::from(Span::styled(line.to_string(), self.style)))
-            .collect();
+#[cfg(feature = "widget-ref")]
+impl ratatui::widgets::WidgetRef for MathWidget<'_> {
+    fn render_ref(&self, area: Rect, buf: &mut Buffer) {
+        self.draw(area, buf);
+    }
+}
 
-        let mut paragraph = Paragraph::new(lines);
+impl MathWidget<'_> {
+    fn draw(&self, area: Rect, buf: &mut Buffer) {
+        let inner = pad(
+            self.block.as_ref().map_or(area, |b| b.inner(area)),
+            self.padding,
+        );
+        let width = usize::from(inner.width);
+        let scale = usize::from(self.scale);
+        let rendered = self.source.to_box(&self.renderer()).map(|mbox| {
+            let mbox = match self.operator_wrap {
+                Some(indent) => mbox.wrap_at_operators(width / scale, usize::from(indent)),
+                None => mbox,
+            };
+            match self.overflow {
+                Overflow::Clip => mbox,
+                Overflow::Ellipsis => mbox.ellipsize(width / scale),
+            }
+        });
+        let lines = match rendered {
+            Ok(mbox) if self.scale > 1 => box_to_lines(
+                &scale_box(&mbox.place_label(width / scale), scale),
+                self.style,
+            ),
+            Ok(mbox) => box_to_lines(&mbox.place_label(width), self.style),
+            Err(e) => error_lines(
+                self.source.latex(),
+                &e,
+                self.error_display,
+                self.style,
+                self.style.patch(self.error_style),
+            ),
+        };
 
-        if let Some(block) = self.block {
-            paragraph = paragraph.block(block);
-        }
+        let mut paragraph = Paragraph::new(lines);
 
         if self.wrap {
             paragraph = paragraph.wrap(Wrap { trim: false });
         }
 
-        paragraph.render(area, buf);
+        self.background.apply(area, buf, self.style, |buf| {
+            if let Some(block) = &self.block {
+                block.clone().render(area, buf);
+            }
+            paragraph.render(inner, buf);
+        });
     }
 }
 
+/// Where a symbol in the rendering came from, as byte ranges into the source
+/// (see [`MathWidgetState::hit_test`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceSpan {
+    /// The token's element in the MathML
+    pub mathml: Range<usize>,
+    /// The token in the LaTeX, if the state was loaded from LaTeX and the
+    /// token could be traced back to it
+    pub latex: Option<Range<usize>>,
+}
+
 /// A stateful version of MathWidget that caches the rendered output
 pub struct MathWidgetState {
     rendered: Option<String>,
+    latex: Option<String>,
+    mathbox: Option<MathBox>,
     error: Option<String>,
+    mathml: Option<String>,
+    options: RendererOptions,
+    action_toggles: HashMap<String, usize>,
+    offset: (u16, u16),
+    selection: Option<Vec<usize>>,
+    tooltips: HashMap<String, String>,
+    /// Continuation indent when breaking at operators, if enabled
+    operator_wrap: Option<u16>,
+    /// Width set by `update_for_area`, and the layouts made for each width
+    width: Option<u16>,
+    layouts: HashMap<u16, MathBox>,
+    laid_out: Option<MathBox>,
+    /// The layout with the selection highlighted, and the style it's in
+    selected: RefCell<Option<(Style, MathBox)>>,
+    /// LaTeX spans of the tokens by MathML path, found on the first hit test
+    token_spans: OnceCell<HashMap<Vec<usize>, Range<usize>>>,
 }
 
 impl MathWidgetState {
     pub fn new() -> Self {
         Self {
             rendered: None,
+            latex: None,
+            mathbox: None,
             error: None,
+            mathml: None,
+            options: RendererOptions::default(),
+            action_toggles: HashMap::new(),
+            offset: (0, 0),
+            selection: None,
+            tooltips: HashMap::new(),
+            operator_wrap: None,
+            width: None,
+            layouts: HashMap::new(),
+            laid_out: None,
+            selected: RefCell::new(None),
+            token_spans: OnceCell::new(),
         }
     }
 
     /// Pre-render the math expression (call this when latex changes)
     pub fn update(&mut self, latex: &str, use_unicode_scripts: bool) {
-        let renderer = MathRenderer::new().use_unicode_scripts(use_unicode_scripts);
-        match renderer.render_latex(latex) {
-            Ok(s) => {
-                self.rendered = Some(s);
+        match self.renderer().latex_to_mathml(latex) {
+            Ok(mathml) => {
+                self.update_mathml(&mathml, use_unicode_scripts);
+                self.latex = Some(latex.to_string());
+            }
+            Err(e) => {
+                self.rendered = None;
+                self.latex = None;
+                self.mathbox = None;
+                self.error = Some(e.to_string());
+                self.mathml = None;
+                self.width = None;
+                self.layouts.clear();
+                self.laid_out = None;
+            }
+        }
+    }
+
+    /// Pre-render for a content area of `area`'s size (inside any block),
+    /// placing a `\tag` label flush right and, if enabled with
+    /// [`set_operator_wrap`](Self::set_operator_wrap), breaking the equation
+    /// at operators if it is wider than the area. Call it every frame: the LaTeX is
+    /// only parsed again when it changes, and the layout only when the width
+    /// does, with the layout for each width kept until the LaTeX changes.
+    pub fn update_for_area(&mut self, latex: &str, area: Rect) {
+        if self.latex.as_deref() != Some(latex) {
+            self.update(latex, self.options.unicode_scripts);
+        }
+        if self.width != Some(area.width) {
+            self.width = Some(area.width);
+            self.lay_out();
+        }
+    }
+
+    /// Break equations wider than the area given to `update_for_area` at
+    /// `+`, `−`, `=` and relation operators, indenting continuation lines by
+    /// `indent` columns, or don't break them (`None`, the default)
+    pub fn set_operator_wrap(&mut self, indent: Option<u16>) {
+        self.operator_wrap = indent;
+        self.layouts.clear();
+        self.lay_out();
+    }
+
+    /// Lay the equation out for the width set by `update_for_area`, if any
+    fn lay_out(&mut self) {
+        self.selected.take();
+        self.laid_out = match (self.width, &self.mathbox) {
+            (Some(width), Some(mbox)) => {
+                let columns = usize::from(width);
+                let layout = self.layouts.entry(width).or_insert_with(|| {
+                    match self.operator_wrap {
+                        Some(indent) => mbox.wrap_at_operators(columns, usize::from(indent)),
+                        None => mbox.clone(),
+                    }
+                    .place_label(columns)
+                });
+                Some(layout.clone())
+            }
+            _ => None,
+        };
+    }
+
+    /// Pre-render a MathML expression (call this when the MathML changes).
+    /// Toggle actions (`<maction actiontype="toggle">`) start on their selected branch.
+    pub fn update_mathml(&mut self, mathml: &str, use_unicode_scripts: bool) {
+        self.latex = None;
+        self.mathml = Some(mathml.to_string());
+        self.token_spans = OnceCell::new();
+        self.width = None;
+        self.options.unicode_scripts = use_unicode_scripts;
+        self.action_toggles.clear();
+        self.select(None);
+        self.rerender();
+    }
+
+    /// Advance a toggle action to its next branch and re-render. Actions are
+    /// identified by their `id` attribute, or else by their position among the
+    /// expression's toggle actions ("0", "1", ...).
+    pub fn toggle(&mut self, action_id: &str) {
+        *self
+            .action_toggles
+            .entry(action_id.to_string())
+            .or_insert(0) += 1;
+        self.rerender();
+    }
+
+    fn rerender(&mut self) {
+        let Some(mathml) = &self.mathml else {
+            return;
+        };
+        // Semantics locate the selection in the layout
+        match self.renderer().semantics(true).render_mathml_to_box(mathml) {
+            Ok(mbox) => {
+                self.rendered = Some(mbox.to_string());
+                self.mathbox = Some(mbox);
                 self.error = None;
             }
             Err(e) => {
                 self.rendered = None;
+                self.mathbox = None;
                 self.error = Some(e.to_string());
             }
         }
+        self.layouts.clear();
+        self.lay_out();
+    }
+
+    /// Set the layout options the expression is rendered with (see
+    /// [`RendererOptions`]) and render it again. `update` and `update_mathml`
+    /// only change the Unicode scripts setting.
+    pub fn set_options(&mut self, options: RendererOptions) {
+        self.options = options;
+        self.rerender();
+    }
+
+    fn renderer(&self) -> MathRenderer {
+        MathRenderer::with_options(self.options.clone()).action_toggles(self.action_toggles.clone())
+    }
+
+    /// Select the subexpression at `path` in the MathML tree (as for
+    /// [`MathRenderer::highlight`]), or nothing. [`StatefulMathWidget`] draws
+    /// the selection with its selection style.
+    pub fn select(&mut self, path: Option<Vec<usize>>) {
+        self.selection = path;
+        self.selected.take();
+    }
+
+    /// The selected subexpression's path, if any
+    pub fn selection(&self) -> Option<&[usize]> {
+        self.selection.as_deref()
+    }
+
+    /// Select the subexpression enclosing the selection; the whole
+    /// expression (`[]`) is the outermost
+    pub fn select_parent(&mut self) {
+        if let Some(path) = &mut self.selection {
+            path.pop();
+            self.selected.take();
+        }
+    }
+
+    /// Select the first part of the selected subexpression (the first term
+    /// if nothing is selected), if it has parts
+    pub fn select_child(&mut self) {
+        let mut path = self.selection.clone().unwrap_or_default();
+        path.push(0);
+        self.select_existing(path);
+    }
+
+    /// Select the subexpression after the selection at the same level
+    pub fn select_next_sibling(&mut self) {
+        self.step_sibling(|index| index.checked_add(1));
+    }
+
+    /// Select the subexpression before the selection at the same level
+    pub fn select_previous_sibling(&mut self) {
+        self.step_sibling(|index| index.checked_sub(1));
+    }
+
+    fn step_sibling(&mut self, step: impl Fn(usize) -> Option<usize>) {
+        let Some(mut path) = self.selection.clone() else {
+            return self.select_child();
+        };
+        let Some(index) = path.last_mut() else {
+            return;
+        };
+        if let Some(next) = step(*index) {
+            *index = next;
+            self.select_existing(path);
+        }
+    }
+
+    /// Select `path` if the current expression has an element there
+    fn select_existing(&mut self, path: Vec<usize>) {
+        let Some(doc) = self
+            .mathml
+            .as_deref()
+            .and_then(|mathml| Document::parse(mathml).ok())
+        else {
+            return;
+        };
+        if node_at(&doc, &path).is_some() {
+            self.select(Some(path));
+        }
+    }
+
+    /// The laid-out rendering with the selection highlighted in `style`,
+    /// patched over the selected element's cells on the first frame after
+    /// the selection, the layout or the style changes
+    fn selected_box(&self, style: Style) -> Option<Ref<'_, MathBox>> {
+        let path = self.selection.as_ref()?;
+        let stale = self
+            .selected
+            .borrow()
+            .as_ref()
+            .is_none_or(|(built, _)| *built != style);
+        if stale {
+            let mut mbox = self.laid_out.as_ref().or(self.mathbox.as_ref())?.clone();
+            let cells: Vec<_> = mbox
+                .semantics()
+                .iter()
+                .filter(|semantic| semantic.path == *path)
+                .flat_map(|semantic| semantic.area.positions())
+                .collect();
+            for cell in cells {
+                let (x, y) = (usize::from(cell.x), usize::from(cell.y));
+                mbox.set_style(x, y, mbox.style(x, y).patch(style));
+            }
+            *self.selected.borrow_mut() = Some((style, mbox));
+        }
+        Ref::filter_map(self.selected.borrow(), |selected| {
+            selected.as_ref().map(|(_, mbox)| mbox)
+        })
+        .ok()
+    }
+
+    /// Find the source of the symbol at (`column`, `row`) of the widget's
+    /// content area (inside any block), taking the scroll offset into
+    /// account, e.g. for click-to-edit or hover tooltips. Returns `None`
+    /// over blank space and for parts drawn for structure, such as fraction
+    /// bars and radicals.
+    pub fn hit_test(&self, column: u16, row: u16) -> Option<SourceSpan> {
+        let mathml = self.mathml.as_deref()?;
+        let x = usize::from(column) + usize::from(self.offset.0);
+        let y = usize::from(row) + usize::from(self.offset.1);
+        let path = self.renderer().token_at(mathml, x, y)?;
+        let doc = Document::parse(mathml).ok()?;
+        let node = node_at(&doc, &path)?;
+        let latex = self.latex.as_deref().and_then(|latex| {
+            let spans = self
+                .token_spans
+                .get_or_init(|| self.renderer().token_spans(latex, mathml));
+            spans.get(&path).cloned()
+        });
+        Some(SourceSpan {
+            mathml: node.range(),
+            latex,
+        })
+    }
+
+    /// Attach a tooltip to a symbol as it appears in the rendering, e.g. to
+    /// explain `"E"` as `"energy"`. Overrides the built-in description.
+    pub fn set_tooltip(&mut self, symbol: &str, text: &str) {
+        self.tooltips.insert(symbol.to_string(), text.to_string());
+    }
+
+    /// The tooltip for the symbol at (`column`, `row`) of the content area
+    /// (as for [`hit_test`](Self::hit_test)), such as `"∂: partial
+    /// derivative"`: one set with [`set_tooltip`](Self::set_tooltip), or
+    /// else a built-in description of operators, relations, Greek letters
+    /// and other symbols
+    pub fn tooltip(&self, column: u16, row: u16) -> Option<String> {
+        let span = self.hit_test(column, row)?;
+        let mathml = self.mathml.as_deref()?;
+        let doc = Document::parse(mathml).ok()?;
+        let node = doc
+            .descendants()
+            .find(|n| n.is_element() && n.range() == span.mathml)?;
+        let symbol = node.text()?.trim();
+        let text = match self.tooltips.get(symbol) {
+            Some(text) => text.clone(),
+            None => describe_symbol(symbol)?,
+        };
+        Some(format!("{}: {}", symbol, text))
+    }
+
+    /// Scroll the view of an equation larger than the widget to start `x`
+    /// columns and `y` rows into it
+    pub fn scroll_to(&mut self, x: u16, y: u16) {
+        self.offset = (x, y);
+    }
+
+    /// The scroll offset as (columns, rows)
+    pub fn offset(&self) -> (u16, u16) {
+        self.offset
     }
 
     /// Get the rendered string
@@ -136,6 +867,11 @@ impl MathWidgetState {
         self.rendered.as_deref()
     }
 
+    /// Get the rendered MathBox
+    pub fn mathbox(&self) -> Option<&MathBox> {
+        self.mathbox.as_ref()
+    }
+
     /// Get the error if any
     pub fn error(&self) -> Option<&str> {
         self.error.as_deref()
@@ -153,6 +889,9 @@ pub struct StatefulMathWidget<'a> {
     style: Style,
     block: Option<Block<'a>>,
     wrap: bool,
+    scrollbars: bool,
+    selection_style: Style,
+    error_style: Style,
 }
 
 impl<'a> StatefulMathWidget<'a> {
@@ -161,6 +900,9 @@ impl<'a> StatefulMathWidget<'a> {
             style: Style::default(),
             block: None,
             wrap: false,
+            scrollbars: false,
+            selection_style: Style::default().bg(Color::DarkGray),
+            error_style: Style::default(),
         }
     }
 
@@ -179,18 +921,54 @@ impl<'a> StatefulMathWidget<'a> {
         self
     }
 
+    /// Show scrollbars on the right and bottom edges (over the block's
+    /// border, if any) when the equation doesn't fit, reflecting the state's
+    /// scroll offset
+    pub fn scrollbars(mut self, scrollbars: bool) -> Self {
+        self.scrollbars = scrollbars;
+        self
+    }
+
+    /// Set the style patched over the state's selected subexpression
+    /// (a dark gray background by default)
+    pub fn selection_style(mut self, style: Style) -> Self {
+        self.selection_style = style;
+        self
+    }
+
+    /// Set the style for the error shown when the state's expression failed
+    /// to render, patched over the widget's style (e.g. red and italic)
+    pub fn error_style(mut self, style: Style) -> Self {
+        self.error_style = style;
+        self
+    }
+
     pub fn render(self, area: Rect, buf: &mut Buffer, state: &MathWidgetState) {
-        let text = state
-            .rendered
+        let width = label_width(area, self.block.as_ref());
+        let inner = self.block.as_ref().map_or(area, |b| b.inner(area));
+        let selected = state.selected_box(self.selection_style);
+        let lines: Vec<Line> = match selected
             .as_deref()
-            .or(state.error.as_deref())
-            .unwrap_or("");
-
-        let lines: Vec<Line> = text
-            .lines()
-            .map(|line| Line::from(Span::styled(line.to_string(), self.style)))
-            .collect();
+            .or(state.laid_out.as_ref())
+            .or(state.mathbox.as_ref())
+        {
+            Some(mbox) => box_to_lines(&mbox.place_label(width), self.style),
+            None => state
+                .error
+                .as_deref()
+                .unwrap_or("")
+                .lines()
+                .map(|line| {
+                    Line::from(Span::styled(
+                        line.to_string(),
+                        self.style.patch(self.error_style),
+                    ))
+                })
+                .collect(),
+        };
 
+        let content = (lines_width(&lines), lines.len());
+        let has_block = self.block.is_some();
         let mut paragraph = Paragraph::new(lines);
 
         if let Some(block) = self.block {
@@ -201,7 +979,58 @@ impl<'a> StatefulMathWidget<'a> {
             paragraph = paragraph.wrap(Wrap { trim: false });
         }
 
-        paragraph.render(area, buf);
+        let (x, y) = state.offset;
+        paragraph.scroll((y, x)).render(area, buf);
+
+        if self.scrollbars {
+            render_scrollbars(area, inner, has_block, content, state.offset, buf);
+        }
+    }
+}
+
+/// Widest line, in columns
+fn lines_width(lines: &[Line]) -> usize {
+    lines.iter().map(Line::width).max().unwrap_or(0)
+}
+
+/// Draw a vertical and/or horizontal scrollbar for `content` (columns, rows)
+/// that overflows `inner`, on the block's border if there is one. The thumb
+/// moves over the offsets that can be scrolled to, 0 through the overflow.
+fn render_scrollbars(
+    area: Rect,
+    inner: Rect,
+    has_block: bool,
+    content: (usize, usize),
+    offset: (u16, u16),
+    buf: &mut Buffer,
+) {
+    let (columns, rows) = content;
+    let bar_area = |margin: Margin| if has_block { area.inner(margin) } else { area };
+    if rows > inner.height as usize {
+        let mut scroll_state = ScrollbarState::new(rows - inner.height as usize + 1)
+            .viewport_content_length(inner.height as usize)
+            .position(offset.1 as usize);
+        Scrollbar::new(ScrollbarOrientation::VerticalRight).render(
+            bar_area(Margin {
+                horizontal: 0,
+                vertical: 1,
+            }),
+            buf,
+            &mut scroll_state,
+        );
+    }
+    if columns > inner.width as usize {
+        let mut scroll_state = ScrollbarState::new(columns - inner.width as usize + 1)
+            .viewport_content_length(inner.width as usize)
+            .position(offset.0 as usize);
+        Scrollbar::new(ScrollbarOrientation::HorizontalBottom).render(
+            bar_area(Margin {
+                horizontal: 1,
+                vertical: 0,
+            }),
+            buf,
+            &mut scroll_state,
+        );
     }
 }
 
@@ -210,3 +1039,420 @@ impl Default for StatefulMathWidget<'_> {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MathStyle;
+    use ratatui::style::Modifier;
+
+    #[test]
+    fn test_bold_modifier_fallback() {
+        let area = Rect::new(0, 0, 10, 1);
+        let mut buf = Buffer::empty(area);
+        MathWidget::new(r"\mathbf{v} + w")
+            .use_unicode_bold(false)
+            .render(area, &mut buf);
+
+        assert_eq!(buf[(0, 0)].symbol(), "v");
+        assert!(buf[(0, 0)].modifier.contains(Modifier::BOLD));
+        assert!(!buf[(4, 0)].modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_toggle_action() {
+        let mut state = MathWidgetState::new();
+        let mathml = r#"<math><mi>y</mi><mo>=</mo><maction actiontype="toggle"><mi>a</mi><mi>b</mi></maction></math>"#;
+        state.update_mathml(mathml, true);
+        assert_eq!(state.rendered(), Some("y = a"));
+        state.toggle("0");
+        assert_eq!(state.rendered(), Some("y = b"));
+        state.toggle("0");
+        assert_eq!(state.rendered(), Some("y = a"));
+    }
+
+    #[test]
+    fn test_tag_flush_right() {
+        let area = Rect::new(0, 0, 20, 1);
+        let mut buf = Buffer::empty(area);
+        MathWidget::new(r"x^2 = 4 \tag{3.1}").render(area, &mut buf);
+
+        assert_eq!(buf[(0, 0)].symbol(), "x");
+        assert_eq!(buf[(15, 0)].symbol(), "(");
+        assert_eq!(buf[(19, 0)].symbol(), ")");
+    }
+
+    #[test]
+    fn test_scale() {
+        let area = Rect::new(0, 0, 12, 6);
+        let mut buf = Buffer::empty(area);
+        MathWidget::new("x - 1").scale(3).render(area, &mut buf);
+
+        // The minus sign lands on the middle row of its 3×3 block
+        assert_eq!(buf[(6, 1)].symbol(), "▀");
+        assert!((0..12).all(|x| buf[(x, 3)].symbol() == " "));
+    }
+
+    #[test]
+    fn test_scrollbars() {
+        let mut state = MathWidgetState::new();
+        state.update(r"\frac{\frac{a}{b}}{\frac{c}{d}}", true);
+        state.scroll_to(0, 3);
+        let area = Rect::new(0, 0, 6, 6);
+        let mut buf = Buffer::empty(area);
+        StatefulMathWidget::new()
+            .block(Block::bordered())
+            .scrollbars(true)
+            .render(area, &mut buf, &state);
+
+        // The last four rows of the 7-row fraction show, with the thumb at the bottom
+        assert_eq!(buf[(1, 2)].symbol(), "c");
+        let bar: Vec<&str> = (0..6).map(|y| buf[(5, y)].symbol()).collect();
+        assert_eq!(bar, ["┐", "▲", "║", "█", "▼", "┘"]);
+    }
+
+    #[test]
+    fn test_selection_navigation() {
+        let mut state = MathWidgetState::new();
+        state.update(r"\frac{a+1}{b} + c", true);
+        state.select_child();
+        state.select_child();
+        state.select_next_sibling();
+        assert_eq!(state.selection(), Some(&[0, 1][..]));
+        state.select_next_sibling();
+        assert_eq!(state.selection(), Some(&[0, 1][..]));
+        state.select_parent();
+        state.select_next_sibling();
+        state.select_next_sibling();
+        assert_eq!(state.selection(), Some(&[2][..]));
+
+        let area = Rect::new(0, 0, 9, 3);
+        let mut buf = Buffer::empty(area);
+        StatefulMathWidget::new().render(area, &mut buf, &state);
+        let highlighted: Vec<(u16, u16)> = area
+            .positions()
+            .filter(|&p| buf[p].bg == Color::DarkGray)
+            .map(|p| (p.x, p.y))
+            .collect();
+        assert_eq!(highlighted, [(8, 1)]);
+        assert_eq!(buf[(8, 1)].symbol(), "c");
+    }
+
+    #[test]
+    fn test_selection_in_wrapped_layout() {
+        let mut state = MathWidgetState::new();
+        state.set_operator_wrap(Some(2));
+        let area = Rect::new(0, 0, 9, 2);
+        state.update_for_area("a + b + c = d", area);
+        state.select(Some(vec![4]));
+
+        let mut buf = Buffer::empty(area);
+        StatefulMathWidget::new().render(area, &mut buf, &state);
+        let highlighted: Vec<(u16, u16)> = area
+            .positions()
+            .filter(|&p| buf[p].bg == Color::DarkGray)
+            .map(|p| (p.x, p.y))
+            .collect();
+        assert_eq!(highlighted, [(4, 1)]);
+        assert_eq!(buf[(4, 1)].symbol(), "c");
+        assert!(state.selected.borrow().is_some());
+    }
+
+    #[test]
+    fn test_hit_test() {
+        let mut state = MathWidgetState::new();
+        let latex = r"\alpha + \frac{12}{y}";
+        state.update(latex, true);
+        // Rendered as "     12" / "α +  ──" / "     y"
+        let span = state.hit_test(5, 0).unwrap();
+        assert_eq!(span.latex.map(|r| &latex[r]), Some("12"));
+        assert_eq!(
+            &state.mathml.as_deref().unwrap()[span.mathml],
+            "<mn>12</mn>"
+        );
+        assert_eq!(state.hit_test(0, 1).unwrap().latex, Some(0..6));
+        assert_eq!(state.hit_test(2, 1).unwrap().latex, Some(7..8));
+        assert_eq!(state.hit_test(5, 1), None);
+    }
+
+    #[test]
+    fn test_hit_test_styled_text() {
+        let mut state = MathWidgetState::new();
+        let latex = r"\text{ab} + \mathbf{v} + x";
+        state.update(latex, true);
+        // Rendered as "ab + 𝐯 + x"
+        let source = |column| {
+            state
+                .hit_test(column, 0)
+                .and_then(|span| span.latex)
+                .map(|r| &latex[r])
+        };
+        assert_eq!(source(1), Some(r"\text{ab}"));
+        assert_eq!(source(3), Some("+"));
+        assert_eq!(source(5), Some("v"));
+        assert_eq!(source(9), Some("x"));
+    }
+
+    #[test]
+    fn test_tooltip() {
+        let mut state = MathWidgetState::new();
+        state.update(r"\partial E \leq \alpha", true);
+        state.set_tooltip("E", "energy");
+        assert_eq!(
+            state.tooltip(0, 0).as_deref(),
+            Some("∂: partial derivative")
+        );
+        assert_eq!(state.tooltip(1, 0).as_deref(), Some("E: energy"));
+        assert_eq!(
+            state.tooltip(3, 0).as_deref(),
+            Some("≤: less than or equal to")
+        );
+        assert_eq!(
+            state.tooltip(5, 0).as_deref(),
+            Some("α: Greek letter alpha")
+        );
+    }
+
+    #[test]
+    fn test_padding() {
+        let widget = MathWidget::new("x^2")
+            .block(Block::bordered())
+            .padding(Padding::new(2, 1, 1, 0));
+        assert_eq!(widget.required_size().unwrap(), (7, 4));
+        let area = Rect::new(0, 0, 7, 4);
+        let mut buf = Buffer::empty(area);
+        widget.render(area, &mut buf);
+        assert_eq!(
+            buf,
+            Buffer::with_lines(["┌─────┐", "│     │", "│  x² │", "└─────┘"])
+        );
+    }
+
+    #[test]
+    fn test_background() {
+        let render = |background: Background| {
+            let mut buf = Buffer::with_lines(["......", "......", "......"]);
+            MathWidget::new(r"\frac{1}{2} + x")
+                .bg(Color::Blue)
+                .background(background)
+                .render(buf.area, &mut buf);
+            let rows: Vec<String> = (0..3)
+                .map(|y| (0..6).map(|x| buf[(x, y)].symbol()).collect())
+                .collect();
+            (rows, buf[(5, 2)].bg)
+        };
+        assert_eq!(
+            render(Background::Rows),
+            (
+                vec!["1.....".into(), "─ + x.".into(), "2.....".into()],
+                Color::Reset
+            )
+        );
+        assert_eq!(
+            render(Background::Fill),
+            (
+                vec!["1     ".into(), "─ + x ".into(), "2     ".into()],
+                Color::Blue
+            )
+        );
+        assert_eq!(
+            render(Background::Transparent).0,
+            ["1.....", "─.+.x.", "2....."]
+        );
+    }
+
+    #[test]
+    fn test_baseline() {
+        assert_eq!(MathWidget::new(r"\frac{a}{b}").baseline().unwrap(), 1);
+        let boxed = MathWidget::new(r"\frac{a}{b}")
+            .block(Block::bordered())
+            .padding(Padding::vertical(1));
+        assert_eq!(boxed.baseline().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_update_for_area() {
+        let mut state = MathWidgetState::new();
+        let latex = "a + b + c = d";
+        state.set_operator_wrap(Some(2));
+        let render = |state: &MathWidgetState, width: u16| {
+            let area = Rect::new(0, 0, width, 2);
+            let mut buf = Buffer::empty(area);
+            StatefulMathWidget::new().render(area, &mut buf, state);
+            (0..2)
+                .map(|y| (0..width).map(|x| buf[(x, y)].symbol()).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+        state.update_for_area(latex, Rect::new(0, 0, 9, 2));
+        assert_eq!(render(&state, 9), ["a + b    ", "  + c = d"]);
+        state.update_for_area(latex, Rect::new(0, 0, 20, 2));
+        state.update_for_area(latex, Rect::new(0, 0, 9, 5));
+        assert_eq!(render(&state, 9), ["a + b    ", "  + c = d"]);
+        assert_eq!(state.layouts.len(), 2);
+        state.update_for_area("x", Rect::new(0, 0, 9, 2));
+        assert_eq!((state.rendered(), state.layouts.len()), (Some("x"), 1));
+
+        // Without operator wrapping the equation is clipped
+        let mut state = MathWidgetState::new();
+        state.update_for_area(latex, Rect::new(0, 0, 9, 2));
+        assert_eq!(render(&state, 9), ["a + b + c", "         "]);
+
+        // Loading new LaTeX with `update` drops the width until the next
+        // `update_for_area`
+        state.update("y", true);
+        assert!(state.laid_out.is_none());
+    }
+
+    #[test]
+    fn test_options() {
+        let text = RendererOptions {
+            style: MathStyle::Text,
+            ..RendererOptions::default()
+        };
+        let widget = MathWidget::new(r"x^2 + \frac{1}{2}").options(text.clone());
+        assert_eq!(widget.render_to_string().unwrap(), "x² + 1/2");
+        let mut state = MathWidgetState::new();
+        state.update(r"\frac{1}{2}", true);
+        state.set_options(text);
+        assert_eq!(state.rendered(), Some("1/2"));
+        state.update(r"\newcommand{\R}{\mathbb{R}} x \in \R", true);
+        assert_eq!(state.rendered(), Some("x ∈ ℝ"));
+        let widget = MathWidget::new(r"\vec{v}").accents(AccentStrategy::Ascii);
+        assert_eq!(widget.render_to_string().unwrap(), "vec(v)");
+    }
+
+    #[test]
+    fn test_stateful_error_style() {
+        let mut state = MathWidgetState::new();
+        state.update(r"\frac{a}{", true);
+        let area = Rect::new(0, 0, 10, 1);
+        let mut buf = Buffer::empty(area);
+        StatefulMathWidget::new()
+            .style(Style::default().bg(Color::Black))
+            .error_style(
+                Style::default()
+                    .fg(Color::Red)
+                    .add_modifier(Modifier::ITALIC),
+            )
+            .render(area, &mut buf, &state);
+        let cell = &buf[(0, 0)];
+        assert_ne!(cell.symbol(), " ");
+        assert_eq!(
+            (cell.fg, cell.bg, cell.modifier),
+            (Color::Red, Color::Black, Modifier::ITALIC)
+        );
+    }
+
+    #[test]
+    fn test_from_mathml() {
+        let mathml = "<math><mfrac><mi>a</mi><mn>2</mn></mfrac></math>";
+        let widget = MathWidget::from_mathml(mathml);
+        assert_eq!(widget.render_to_string().unwrap(), "a\n─\n2");
+        assert!(MathWidget::from_mathml("<math>")
+            .render_to_string()
+            .is_err());
+    }
+
+    #[test]
+    fn test_render_by_reference() {
+        let widget = MathWidget::new("x^2").block(Block::bordered());
+        let area = Rect::new(0, 0, 6, 3);
+        let mut by_ref = Buffer::empty(area);
+        let mut by_value = Buffer::empty(area);
+        (&widget).render(area, &mut by_ref);
+        (&widget).render(area, &mut by_ref);
+        widget.render(area, &mut by_value);
+        assert_eq!(by_ref, by_value);
+    }
+
+    #[test]
+    fn test_required_size() {
+        assert_eq!(
+            MathWidget::new(r"\frac{a+1}{b}").required_size().unwrap(),
+            (5, 3)
+        );
+        assert_eq!(
+            MathWidget::new(r"x = 1 \tag{2}").required_size().unwrap(),
+            (10, 1)
+        );
+        let widget = MathWidget::new("x").block(Block::bordered()).scale(3);
+        assert_eq!(widget.required_size().unwrap(), (5, 5));
+        assert!(MathWidget::new(r"\frac{").required_size().is_err());
+    }
+
+    #[test]
+    fn test_wrap_at_operators() {
+        let area = Rect::new(0, 0, 12, 3);
+        let mut buf = Buffer::empty(area);
+        MathWidget::new("a + b + c + d + e")
+            .wrap_at_operators(2)
+            .render(area, &mut buf);
+        assert_eq!(
+            buf,
+            Buffer::with_lines(["a + b + c   ", "  + d + e   ", "            "])
+        );
+    }
+
+    #[test]
+    fn test_overflow_ellipsis() {
+        let area = Rect::new(0, 0, 8, 1);
+        let mut buf = Buffer::empty(area);
+        MathWidget::new(r"\alpha + \beta + \gamma")
+            .overflow(Overflow::Ellipsis)
+            .render(area, &mut buf);
+        assert_eq!(buf, Buffer::with_lines(["α + β…  "]));
+    }
+
+    #[test]
+    fn test_theme() {
+        let theme = MathTheme {
+            number: Some(Color::Yellow),
+            delimiter: Some(Color::Red),
+            function: Some(Color::Blue),
+            ..MathTheme::default()
+        };
+        let area = Rect::new(0, 0, 10, 1);
+        let mut buf = Buffer::empty(area);
+        MathWidget::new(r"\sin(2x)")
+            .theme(theme)
+            .fg(Color::White)
+            .render(area, &mut buf);
+
+        let colors: Vec<Color> = (0..7).map(|x| buf[(x, 0)].fg).collect();
+        let (w, y, r, b) = (Color::White, Color::Yellow, Color::Red, Color::Blue);
+        assert_eq!(buf[(0, 0)].symbol(), "s");
+        assert_eq!(colors, [b, b, b, r, y, w, r]);
+    }
+
+    #[test]
+    fn test_error_caret() {
+        let area = Rect::new(0, 0, 80, 3);
+        let mut buf = Buffer::empty(area);
+        MathWidget::new(r"x + \begin{foo} y \end{foo}")
+            .error_display(ErrorDisplay::Caret)
+            .error_style(Style::default().fg(Color::Red))
+            .render(area, &mut buf);
+
+        let row = |y: u16| {
+            (0..80)
+                .map(|x| buf[(x, y)].symbol())
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        };
+        assert_eq!(row(0), r"x + \begin{foo} y \end{foo}");
+        assert_eq!(row(1), "    ^~~~~~~~~~~");
+        assert!(row(2).starts_with("Error: "));
+        assert_eq!((buf[(0, 0)].fg, buf[(4, 1)].fg), (Color::Reset, Color::Red));
+    }
+
+    #[test]
+    fn test_from_box() {
+        let mbox = MathRenderer::new().render_to_box(r"\frac{1}{2}").unwrap();
+        let area = Rect::new(0, 0, 3, 3);
+        let (mut from_box, mut from_latex) = (Buffer::empty(area), Buffer::empty(area));
+        MathWidget::from_box(mbox).render(area, &mut from_box);
+        MathWidget::new(r"\frac{1}{2}").render(area, &mut from_latex);
+        assert_eq!(from_box, from_latex);
+    }
+}